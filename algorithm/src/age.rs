@@ -0,0 +1,958 @@
+//! Age-structured Susceptible-Exposed-Infectious-Removed model.
+//!
+//! The population is split into age bands that mix according to a contact
+//! matrix, where `contacts[a][b]` is the average number of people in band `b`
+//! somebody in band `a` meets each day. Every band has its own
+//! susceptibility, fraction of infections that become symptomatic and
+//! fatality among symptomatic cases. Infectious people are counted
+//! separately by whether they show symptoms, and removed people by whether
+//! they recovered or died, for each band. A vaccination campaign can
+//! move susceptible people into a vaccinated compartment, see
+//! `VaccinationConfig`; the time between doses is then exponentially
+//! distributed with mean `second_dose_delay`.
+//!
+//! Contact matrices can be read from CSV, with a header naming the bands and
+//! a row per band starting with its name:
+//!
+//! ```text
+//! band,0-19,20-64,65+
+//! 0-19,7.9,5.2,0.6
+//! 20-64,2.9,9.1,1.0
+//! 65+,0.9,3.6,2.2
+//! ```
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{self, ParamError};
+use crate::export::{self, Format, Metadata};
+use crate::solver::{self, OdeModel, SolveError, Solver};
+use crate::trajectory::{Sample, Trajectory};
+use crate::vaccination::{Priority, VaccinationConfig};
+
+// Compartments of each band in the ODE state. Vaccinated people who have
+// not been infected are split by their number of doses, and exposed people
+// by whether their infection will become symptomatic.
+const S: usize = 0;
+const V1: usize = 1;
+const V2: usize = 2;
+const E_SYMPTOMATIC: usize = 3;
+const E_ASYMPTOMATIC: usize = 4;
+const I_SYMPTOMATIC: usize = 5;
+const I_ASYMPTOMATIC: usize = 6;
+const R: usize = 7;
+const D: usize = 8;
+const COMPARTMENTS: usize = 9;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgeBand {
+    pub name: String,
+    ///youngest age in the band, needed to vaccinate by age
+    #[serde(default)]
+    pub min_age: Option<f64>,
+    pub population: f64,
+    #[serde(default)]
+    pub initial_infected: f64,
+    ///chance of being infected by a contact relative to other bands
+    #[serde(default = "one")]
+    pub susceptibility: f64,
+    ///fraction of infections that become symptomatic
+    pub symptomatic_fraction: f64,
+    ///fraction of symptomatic cases that die
+    pub fatality: f64,
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+impl AgeBand {
+    pub fn validate(&self) -> Result<(), ParamError> {
+        error::positive("bands.population", self.population)?;
+        error::within_population(
+            "bands.initial_infected",
+            self.initial_infected,
+            self.population,
+        )?;
+        if let Some(min_age) = self.min_age {
+            error::non_negative("bands.min_age", min_age)?;
+        }
+        error::non_negative("bands.susceptibility", self.susceptibility)?;
+        error::probability("bands.symptomatic_fraction", self.symptomatic_fraction)?;
+        error::probability("bands.fatality", self.fatality)?;
+        Ok(())
+    }
+}
+
+/// Average daily contacts between age bands, as read from CSV.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContactMatrix {
+    pub bands: Vec<String>,
+    ///`contacts[a][b]` people in band `b` met by each person in band `a`
+    pub contacts: Vec<Vec<f64>>,
+}
+
+#[derive(Debug)]
+pub enum ContactMatrixError {
+    Io(io::Error),
+    /// A line of the contact matrix could not be read.
+    Csv {
+        line: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ContactMatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContactMatrixError::Io(error) => write!(f, "could not read contacts: {}", error),
+            ContactMatrixError::Csv { line, reason } => write!(f, "line {}: {}", line, reason),
+        }
+    }
+}
+
+impl std::error::Error for ContactMatrixError {}
+
+impl From<io::Error> for ContactMatrixError {
+    fn from(error: io::Error) -> ContactMatrixError {
+        ContactMatrixError::Io(error)
+    }
+}
+
+impl ContactMatrix {
+    pub fn from_csv<R: BufRead>(reader: R) -> Result<ContactMatrix, ContactMatrixError> {
+        let mut bands: Option<Vec<String>> = None;
+        let mut contacts = Vec::new();
+        let mut last = 0;
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            last = n + 1;
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let csv = |reason: String| ContactMatrixError::Csv {
+                line: n + 1,
+                reason,
+            };
+
+            let bands = match &bands {
+                Some(bands) => bands,
+                None => {
+                    bands = Some(fields[1..].iter().map(|f| f.to_string()).collect());
+                    continue;
+                }
+            };
+            let row = contacts.len();
+            match bands.get(row) {
+                Some(band) if band == fields[0] => {}
+                Some(band) => return Err(csv(format!("expected the row for `{}`", band))),
+                None => return Err(csv("there are more rows than bands".into())),
+            }
+            if fields.len() != bands.len() + 1 {
+                return Err(csv(format!("expected {} contact rates", bands.len())));
+            }
+            let rates = fields[1..]
+                .iter()
+                .map(|field| match field.parse::<f64>() {
+                    Ok(rate) if rate.is_finite() && rate >= 0.0 => Ok(rate),
+                    _ => Err(csv(format!("`{}` is not a contact rate", field))),
+                })
+                .collect::<Result<Vec<f64>, _>>()?;
+            contacts.push(rates);
+        }
+
+        let bands = bands.unwrap_or_default();
+        if bands.is_empty() || contacts.len() != bands.len() {
+            return Err(ContactMatrixError::Csv {
+                line: last,
+                reason: format!("expected a row for each of {} bands", bands.len()),
+            });
+        }
+        Ok(ContactMatrix { bands, contacts })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ContactMatrix, ContactMatrixError> {
+        ContactMatrix::from_csv(BufReader::new(File::open(path)?))
+    }
+}
+
+/// The state of one age band.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BandSample {
+    pub t: f64,
+    pub s: f64,
+    ///vaccinated people who have not been infected
+    pub v: f64,
+    pub e: f64,
+    pub i_symptomatic: f64,
+    pub i_asymptomatic: f64,
+    ///recovered
+    pub r: f64,
+    ///dead
+    pub d: f64,
+}
+
+impl Sample for BandSample {
+    const COMPARTMENTS: &'static [&'static str] =
+        &["s", "v", "e", "i_symptomatic", "i_asymptomatic", "r", "d"];
+
+    fn time(&self) -> f64 {
+        self.t
+    }
+
+    fn values(&self) -> Vec<f64> {
+        vec![
+            self.s,
+            self.v,
+            self.e,
+            self.i_symptomatic,
+            self.i_asymptomatic,
+            self.r,
+            self.d,
+        ]
+    }
+
+    fn infectious(&self) -> f64 {
+        self.i_symptomatic + self.i_asymptomatic
+    }
+
+    fn removed(&self) -> f64 {
+        self.r + self.d
+    }
+
+    fn at_time(&self, t: f64) -> BandSample {
+        BandSample { t, ..*self }
+    }
+}
+
+/// The state of every age band at time `t`, in days. As a `Sample` it holds
+/// the totals over all bands.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AgeSeirSample {
+    pub t: f64,
+    pub bands: Vec<BandSample>,
+}
+
+impl AgeSeirSample {
+    /// The sum over all bands.
+    pub fn total(&self) -> BandSample {
+        let mut total = BandSample {
+            t: self.t,
+            s: 0.0,
+            v: 0.0,
+            e: 0.0,
+            i_symptomatic: 0.0,
+            i_asymptomatic: 0.0,
+            r: 0.0,
+            d: 0.0,
+        };
+        for band in &self.bands {
+            total.s += band.s;
+            total.v += band.v;
+            total.e += band.e;
+            total.i_symptomatic += band.i_symptomatic;
+            total.i_asymptomatic += band.i_asymptomatic;
+            total.r += band.r;
+            total.d += band.d;
+        }
+        total
+    }
+}
+
+impl Sample for AgeSeirSample {
+    const COMPARTMENTS: &'static [&'static str] = BandSample::COMPARTMENTS;
+
+    fn time(&self) -> f64 {
+        self.t
+    }
+
+    fn values(&self) -> Vec<f64> {
+        self.total().values()
+    }
+
+    fn infectious(&self) -> f64 {
+        self.total().infectious()
+    }
+
+    fn removed(&self) -> f64 {
+        self.total().removed()
+    }
+
+    fn at_time(&self, t: f64) -> AgeSeirSample {
+        let bands = self.bands.iter().map(|band| band.at_time(t)).collect();
+        AgeSeirSample { t, bands }
+    }
+}
+
+impl Trajectory<AgeSeirSample> {
+    /// The trajectory of the band at index `band` alone.
+    pub fn band(&self, band: usize) -> Trajectory<BandSample> {
+        let samples: Vec<BandSample> = self.iter().map(|sample| sample.bands[band]).collect();
+        samples.into()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AgeSeirModel {
+    bands: Vec<AgeBand>,
+    contacts: Vec<Vec<f64>>,
+    ///chance that a contact between an infectious and a susceptible person
+    ///transmits the disease
+    beta: f64,
+    ///the fraction of exposed people that become infectious each day
+    sigma: f64,
+    ///the fraction of infectious people that are removed each day
+    gamma: f64,
+    ///how infectious asymptomatic people are relative to symptomatic ones
+    asymptomatic_infectiousness: f64,
+    vaccination: Option<VaccinationConfig>,
+    t: f64,
+    ///`COMPARTMENTS` values for each band in turn
+    y: Vec<f64>,
+}
+
+impl AgeSeirModel {
+    /// Creates a model with each band's `initial_infected` people infectious
+    /// at `t = 0`, split by its symptomatic fraction, and everybody else
+    /// susceptible. `contacts` needs a row and a column for every band.
+    pub fn new(
+        bands: Vec<AgeBand>,
+        contacts: Vec<Vec<f64>>,
+        beta: f64,
+        sigma: f64,
+        gamma: f64,
+    ) -> Result<AgeSeirModel, ParamError> {
+        if bands.is_empty() {
+            return Err(ParamError::new("bands", "needs at least one age band"));
+        }
+        for band in &bands {
+            band.validate()?;
+        }
+        if contacts.len() != bands.len() || contacts.iter().any(|row| row.len() != bands.len()) {
+            return Err(ParamError::new(
+                "contacts",
+                "needs a row and a column for every band",
+            ));
+        }
+        for &rate in contacts.iter().flatten() {
+            error::non_negative("contacts", rate)?;
+        }
+        let beta = error::non_negative("beta", beta)?;
+        let sigma = error::non_negative("sigma", sigma)?;
+        let gamma = error::non_negative("gamma", gamma)?;
+
+        let mut y = vec![0.0; bands.len() * COMPARTMENTS];
+        for (band, y) in bands.iter().zip(y.chunks_mut(COMPARTMENTS)) {
+            y[S] = band.population - band.initial_infected;
+            y[I_SYMPTOMATIC] = band.initial_infected * band.symptomatic_fraction;
+            y[I_ASYMPTOMATIC] = band.initial_infected * (1.0 - band.symptomatic_fraction);
+        }
+        Ok(AgeSeirModel {
+            bands,
+            contacts,
+            beta,
+            sigma,
+            gamma,
+            asymptomatic_infectiousness: 1.0,
+            vaccination: None,
+            t: 0.0,
+            y,
+        })
+    }
+
+    /// Makes asymptomatic people `infectiousness` times as infectious as
+    /// symptomatic ones, rather than equally so.
+    pub fn with_asymptomatic_infectiousness(
+        mut self,
+        infectiousness: f64,
+    ) -> Result<AgeSeirModel, ParamError> {
+        self.asymptomatic_infectiousness =
+            error::probability("asymptomatic_infectiousness", infectiousness)?;
+        Ok(self)
+    }
+
+    /// Vaccinates susceptible people with the campaign `vaccination`.
+    pub fn with_vaccination(
+        mut self,
+        vaccination: VaccinationConfig,
+    ) -> Result<AgeSeirModel, ParamError> {
+        vaccination.validate()?;
+        if vaccination.priority == Priority::Age && self.bands.iter().any(|b| b.min_age.is_none()) {
+            return Err(ParamError::new(
+                "vaccination.priority",
+                "needs every band's `min_age` to go by age",
+            ));
+        }
+        self.vaccination = Some(vaccination);
+        Ok(self)
+    }
+
+    pub fn bands(&self) -> &[AgeBand] {
+        &self.bands
+    }
+
+    pub fn contacts(&self) -> &[Vec<f64>] {
+        &self.contacts
+    }
+
+    pub fn beta(&self) -> f64 {
+        self.beta
+    }
+
+    /// Changes the transmission chance per contact from now on, for example
+    /// when an intervention starts or ends.
+    pub fn set_beta(&mut self, beta: f64) -> Result<(), ParamError> {
+        self.beta = error::non_negative("beta", beta)?;
+        Ok(())
+    }
+
+    pub fn sigma(&self) -> f64 {
+        self.sigma
+    }
+
+    pub fn gamma(&self) -> f64 {
+        self.gamma
+    }
+
+    pub fn asymptomatic_infectiousness(&self) -> f64 {
+        self.asymptomatic_infectiousness
+    }
+
+    pub fn vaccination(&self) -> Option<&VaccinationConfig> {
+        self.vaccination.as_ref()
+    }
+
+    /// The dominant eigenvalue of the next generation matrix: the expected
+    /// number of secondary infections from a typical case in a fully
+    /// susceptible population.
+    pub fn basic_reproduction_number(&self) -> f64 {
+        let n = self.bands.len();
+        let next_generation: Vec<Vec<f64>> = (0..n)
+            .map(|a| {
+                (0..n)
+                    .map(|b| {
+                        let (to, from) = (&self.bands[a], &self.bands[b]);
+                        let infectiousness = from.symptomatic_fraction
+                            + self.asymptomatic_infectiousness * (1.0 - from.symptomatic_fraction);
+                        self.beta * to.susceptibility * self.contacts[a][b] * to.population
+                            / from.population
+                            * infectiousness
+                            / self.gamma
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // Power iteration, which converges for the non-negative matrices of
+        // any mixing pattern where every band can reach every other one.
+        let mut v = vec![1.0; n];
+        let mut eigenvalue = 0.0;
+        for _ in 0..1000 {
+            let next: Vec<f64> = next_generation
+                .iter()
+                .map(|row| row.iter().zip(&v).map(|(k, x)| k * x).sum())
+                .collect();
+            let norm = next.iter().cloned().fold(0.0, f64::max);
+            if norm == 0.0 {
+                return 0.0;
+            }
+            v = next.iter().map(|x| x / norm).collect();
+            if (norm - eigenvalue).abs() < 1e-12 * norm {
+                return norm;
+            }
+            eigenvalue = norm;
+        }
+        eigenvalue
+    }
+
+    pub fn state(&self) -> AgeSeirSample {
+        self.sample(self.t, &self.y)
+    }
+
+    /// The rate at which each unvaccinated susceptible person in each band is
+    /// infected.
+    fn forces(&self, y: &[f64]) -> Vec<f64> {
+        let pressure: Vec<f64> = y
+            .chunks(COMPARTMENTS)
+            .zip(&self.bands)
+            .map(|(y, band)| {
+                (y[I_SYMPTOMATIC] + self.asymptomatic_infectiousness * y[I_ASYMPTOMATIC])
+                    / band.population
+            })
+            .collect();
+        self.bands
+            .iter()
+            .zip(&self.contacts)
+            .map(|(band, row)| {
+                let contacts: f64 = row.iter().zip(&pressure).map(|(c, p)| c * p).sum();
+                self.beta * band.susceptibility * contacts
+            })
+            .collect()
+    }
+
+    /// The rates at which first and second doses are given in each band at
+    /// time `t`, sharing out the daily capacity among at most `available`
+    /// susceptible people a day in each band.
+    fn doses(&self, t: f64, y: &[f64], available: &[f64]) -> (Vec<f64>, Vec<f64>) {
+        let n = self.bands.len();
+        let mut first = vec![0.0; n];
+        let mut second = vec![0.0; n];
+        let config = match &self.vaccination {
+            Some(config) if t >= config.start => config,
+            _ => return (first, second),
+        };
+        let band = |b: usize| &y[b * COMPARTMENTS..(b + 1) * COMPARTMENTS];
+
+        let mut capacity = config.daily_doses;
+        if let Some(delay) = config.second_dose_delay {
+            for (b, second) in second.iter_mut().enumerate() {
+                *second = band(b)[V1] / delay;
+            }
+            let wanted: f64 = second.iter().sum();
+            if wanted > capacity {
+                second
+                    .iter_mut()
+                    .for_each(|doses| *doses *= capacity / wanted);
+            }
+            capacity -= wanted.min(capacity);
+        }
+
+        let mut order: Vec<usize> = (0..n).collect();
+        match config.priority {
+            Priority::Random => {
+                let total: f64 = available.iter().sum();
+                if total > 0.0 {
+                    let share = (capacity / total).min(1.0);
+                    for (first, available) in first.iter_mut().zip(available) {
+                        *first = available * share;
+                    }
+                }
+                return (first, second);
+            }
+            Priority::Age => {
+                let min_age = |b: usize| self.bands[b].min_age.unwrap_or_default();
+                order.sort_by(|&a, &b| min_age(b).partial_cmp(&min_age(a)).unwrap());
+            }
+            Priority::Risk => {
+                let fatality = |b: usize| {
+                    let band = &self.bands[b];
+                    band.symptomatic_fraction * band.fatality
+                };
+                order.sort_by(|&a, &b| fatality(b).partial_cmp(&fatality(a)).unwrap());
+            }
+        }
+        for b in order {
+            first[b] = available[b].min(capacity);
+            capacity -= first[b];
+        }
+        (first, second)
+    }
+
+    /// Advances the model by one day and returns the new state.
+    pub fn step(&mut self) -> AgeSeirSample {
+        let mut dy = vec![0.0; self.y.len()];
+        self.rates(self.t, &self.y, &mut dy, true);
+        for (y, dy) in self.y.iter_mut().zip(dy) {
+            *y += dy;
+        }
+        self.t += 1.0;
+        self.state()
+    }
+
+    /// Advances the model by `days`, returning the current state followed by
+    /// the state at the end of each day.
+    pub fn run(&mut self, days: usize) -> Trajectory<AgeSeirSample> {
+        let mut trajectory = Trajectory::with_capacity(days + 1);
+        trajectory.push(self.state());
+        for _ in 0..days {
+            trajectory.push(self.step());
+        }
+        trajectory
+    }
+
+    /// Integrates the model from its current state with `solver`, sampling
+    /// it at each of the increasing `times`.
+    pub fn solve(
+        &self,
+        solver: Solver,
+        times: &[f64],
+    ) -> Result<Trajectory<AgeSeirSample>, SolveError> {
+        solver::solve(self, solver, times)
+    }
+
+    /// Writes the rate of change of `y` at time `t` into `dy`. A `daily`
+    /// step offers no dose to anybody infected that day, which keeps the
+    /// susceptible compartments from going negative. Otherwise doses slow
+    /// down smoothly as a band runs out, reaching at most its susceptible
+    /// people each day.
+    fn rates(&self, t: f64, y: &[f64], dy: &mut [f64], daily: bool) {
+        let forces = self.forces(y);
+        let available: Vec<f64> = y
+            .chunks(COMPARTMENTS)
+            .zip(&forces)
+            .map(|(y, force)| match daily {
+                true => (y[S] * (1.0 - force)).max(0.0),
+                false => y[S],
+            })
+            .collect();
+        let (first_doses, second_doses) = self.doses(t, y, &available);
+        let (course, partial, full) = match &self.vaccination {
+            Some(config) => (config.course(), config.protection(1), config.protection(2)),
+            None => (1, (0.0, 0.0), (0.0, 0.0)),
+        };
+
+        let bands = y.chunks(COMPARTMENTS).zip(dy.chunks_mut(COMPARTMENTS));
+        for (b, (y, dy)) in bands.enumerate() {
+            let band = &self.bands[b];
+            let force = forces[b];
+            let unvaccinated = force * y[S];
+            let partially_vaccinated = force * (1.0 - partial.0) * y[V1];
+            let fully_vaccinated = force * (1.0 - full.0) * y[V2];
+            let symptomatic = band.symptomatic_fraction
+                * (unvaccinated
+                    + (1.0 - partial.1) * partially_vaccinated
+                    + (1.0 - full.1) * fully_vaccinated);
+            let exposures = unvaccinated + partially_vaccinated + fully_vaccinated;
+            let symptomatic_removals = self.gamma * y[I_SYMPTOMATIC];
+            let asymptomatic_removals = self.gamma * y[I_ASYMPTOMATIC];
+            let (to_v1, to_v2) = match course {
+                1 => (0.0, first_doses[b]),
+                _ => (first_doses[b], 0.0),
+            };
+
+            dy[S] = -unvaccinated - first_doses[b];
+            dy[V1] = to_v1 - second_doses[b] - partially_vaccinated;
+            dy[V2] = to_v2 + second_doses[b] - fully_vaccinated;
+            dy[E_SYMPTOMATIC] = symptomatic - self.sigma * y[E_SYMPTOMATIC];
+            dy[E_ASYMPTOMATIC] = exposures - symptomatic - self.sigma * y[E_ASYMPTOMATIC];
+            dy[I_SYMPTOMATIC] = self.sigma * y[E_SYMPTOMATIC] - symptomatic_removals;
+            dy[I_ASYMPTOMATIC] = self.sigma * y[E_ASYMPTOMATIC] - asymptomatic_removals;
+            dy[R] = (1.0 - band.fatality) * symptomatic_removals + asymptomatic_removals;
+            dy[D] = band.fatality * symptomatic_removals;
+        }
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        let mut metadata = Metadata::new("age_seir")
+            .parameter("beta", self.beta())
+            .parameter("sigma", self.sigma())
+            .parameter("gamma", self.gamma())
+            .parameter(
+                "asymptomatic_infectiousness",
+                self.asymptomatic_infectiousness(),
+            );
+        for band in self.bands() {
+            let name = |parameter: &str| format!("{}.{}", band.name, parameter);
+            if let Some(min_age) = band.min_age {
+                metadata = metadata.parameter(&name("min_age"), min_age);
+            }
+            metadata = metadata
+                .parameter(&name("population"), band.population)
+                .parameter(&name("susceptibility"), band.susceptibility)
+                .parameter(&name("symptomatic_fraction"), band.symptomatic_fraction)
+                .parameter(&name("fatality"), band.fatality);
+        }
+        metadata.vaccination(self.vaccination())
+    }
+}
+
+impl OdeModel for AgeSeirModel {
+    type Sample = AgeSeirSample;
+
+    fn initial_time(&self) -> f64 {
+        self.t
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        self.y.clone()
+    }
+
+    fn derivative(&self, t: f64, y: &[f64], dy: &mut [f64]) {
+        self.rates(t, y, dy, false);
+    }
+
+    fn sample(&self, t: f64, y: &[f64]) -> AgeSeirSample {
+        let bands = y
+            .chunks(COMPARTMENTS)
+            .map(|y| BandSample {
+                t,
+                s: y[S],
+                v: y[V1] + y[V2],
+                e: y[E_SYMPTOMATIC] + y[E_ASYMPTOMATIC],
+                i_symptomatic: y[I_SYMPTOMATIC],
+                i_asymptomatic: y[I_ASYMPTOMATIC],
+                r: y[R],
+                d: y[D],
+            })
+            .collect();
+        AgeSeirSample { t, bands }
+    }
+}
+
+/// Writes the totals followed by every band's compartments, prefixed with
+/// the band's name like `65+.d`. JSON holds each sample's bands in order.
+pub fn write<W: Write>(
+    mut writer: W,
+    format: Format,
+    metadata: &Metadata,
+    bands: &[String],
+    trajectory: &Trajectory<AgeSeirSample>,
+) -> io::Result<()> {
+    let mut columns: Vec<String> = BandSample::COMPARTMENTS
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    for band in bands {
+        for compartment in BandSample::COMPARTMENTS {
+            columns.push(format!("{}.{}", band, compartment));
+        }
+    }
+    let values = |sample: &AgeSeirSample| {
+        let mut values = sample.values();
+        for band in &sample.bands {
+            values.extend(band.values());
+        }
+        values
+    };
+
+    match format {
+        Format::Json => return export::write_json(writer, metadata, trajectory),
+        Format::WideCsv => {
+            metadata.write_header(&mut writer)?;
+            writeln!(writer, "t,{}", columns.join(","))?;
+            for sample in trajectory {
+                write!(writer, "{}", sample.t)?;
+                for value in values(sample) {
+                    write!(writer, ",{}", value)?;
+                }
+                writeln!(writer)?;
+            }
+        }
+        Format::LongCsv => {
+            metadata.write_header(&mut writer)?;
+            writeln!(writer, "t,compartment,value")?;
+            let values: Vec<Vec<f64>> = trajectory.iter().map(values).collect();
+            for (c, column) in columns.iter().enumerate() {
+                for (sample, values) in trajectory.iter().zip(&values) {
+                    writeln!(writer, "{},{},{}", sample.t, column, values[c])?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seir::SeirModel;
+    use crate::solver::daily_times;
+
+    fn band(name: &str, population: f64, symptomatic_fraction: f64, fatality: f64) -> AgeBand {
+        AgeBand {
+            name: name.to_string(),
+            min_age: None,
+            population,
+            initial_infected: 0.0,
+            susceptibility: 1.0,
+            symptomatic_fraction,
+            fatality,
+        }
+    }
+
+    fn town() -> AgeSeirModel {
+        let mut bands = vec![
+            band("young", 3000.0, 0.3, 0.0001),
+            band("adult", 5000.0, 0.6, 0.002),
+            band("old", 2000.0, 0.8, 0.05),
+        ];
+        bands[1].initial_infected = 10.0;
+        bands[0].susceptibility = 0.5;
+        for (band, min_age) in bands.iter_mut().zip(&[0.0, 20.0, 65.0]) {
+            band.min_age = Some(*min_age);
+        }
+        let contacts = vec![
+            vec![8.0, 4.0, 1.0],
+            vec![2.4, 6.0, 1.0],
+            vec![1.5, 2.5, 2.0],
+        ];
+        AgeSeirModel::new(bands, contacts, 0.05, 0.2, 0.1).unwrap()
+    }
+
+    #[test]
+    fn one_band_matches_the_seir_model() {
+        let mut only = band("all", 1000.0, 1.0, 0.0);
+        only.initial_infected = 1.0;
+        let mut age = AgeSeirModel::new(vec![only], vec![vec![10.0]], 0.05, 0.3, 0.2).unwrap();
+        let mut seir = SeirModel::new(1000.0, 0.5, 0.3, 0.2, 1.0).unwrap();
+        assert!((age.basic_reproduction_number() - seir.basic_reproduction_number()).abs() < 1e-9);
+
+        for (age, seir) in age.run(100).iter().zip(seir.run(100).iter()) {
+            let total = age.total();
+            assert!((total.s - seir.s).abs() < 1e-9);
+            assert!((total.e - seir.e).abs() < 1e-9);
+            assert!((total.infectious() - seir.i).abs() < 1e-9);
+            assert!((total.removed() - seir.r).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn outcomes_differ_by_age() {
+        let model = town();
+        assert!(model.basic_reproduction_number() > 1.0);
+        let trajectory = model
+            .solve(Solver::Rk4 { step: 0.1 }, &daily_times(0.0, 300))
+            .unwrap();
+        let last = trajectory.last().unwrap();
+        for (band, state) in model.bands().iter().zip(&last.bands) {
+            let total = state.s + state.v + state.e + state.infectious() + state.removed();
+            assert!((total - band.population).abs() < 1e-6);
+        }
+
+        let attack = |b: usize| 1.0 - last.bands[b].s / model.bands()[b].population;
+        let deaths = |b: usize| last.bands[b].d / model.bands()[b].population;
+        assert!(attack(0) < attack(1), "less susceptible children");
+        assert!(deaths(2) > 10.0 * deaths(1));
+        // Everybody infected has been removed, and deaths follow from the
+        // symptomatic fraction and fatality.
+        let removed = last.bands[2].removed();
+        assert!((last.bands[2].d - removed * 0.8 * 0.05).abs() < 1e-3 * removed);
+
+        let old = trajectory.band(2);
+        assert_eq!(old.len(), trajectory.len());
+        assert_eq!(old.last(), Some(&last.bands[2]));
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        let error = AgeSeirModel::new(vec![], vec![], 0.1, 0.2, 0.1).unwrap_err();
+        assert_eq!(error.field, "bands");
+        let bands = vec![band("a", 10.0, 0.5, 0.0), band("b", 10.0, 0.5, 0.0)];
+        let error = AgeSeirModel::new(bands.clone(), vec![vec![1.0; 2]], 0.1, 0.2, 0.1);
+        assert_eq!(error.unwrap_err().field, "contacts");
+        let mut invalid = bands;
+        invalid[1].fatality = 2.0;
+        let error = AgeSeirModel::new(invalid, vec![vec![1.0; 2]; 2], 0.1, 0.2, 0.1);
+        assert_eq!(error.unwrap_err().field, "bands.fatality");
+    }
+
+    #[test]
+    fn reads_contact_matrices() {
+        let text = "# daily contacts\nband,0-19,20-64,65+\n0-19,7.9,5.2,0.6\n\
+                    20-64,2.9,9.1,1.0\n65+,0.9,3.6,2.2\n";
+        let matrix = ContactMatrix::from_csv(text.as_bytes()).unwrap();
+        assert_eq!(matrix.bands, ["0-19", "20-64", "65+"]);
+        assert_eq!(matrix.contacts[2], [0.9, 3.6, 2.2]);
+
+        let line = |text: &str| match ContactMatrix::from_csv(text.as_bytes()) {
+            Err(ContactMatrixError::Csv { line, .. }) => line,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(line("band,a,b\nb,1,2\n"), 2);
+        assert_eq!(line("band,a,b\na,1,2\nb,1\n"), 3);
+        assert_eq!(line("band,a,b\na,1,2\nb,1,-2\n"), 3);
+        assert_eq!(line("band,a,b\na,1,2\n"), 2);
+    }
+
+    #[test]
+    fn vaccinates_by_priority() {
+        let campaign = |priority| VaccinationConfig {
+            start: 10.0,
+            daily_doses: 100.0,
+            priority,
+            efficacy_against_infection: 0.7,
+            efficacy_against_symptoms: 0.9,
+            second_dose_delay: Some(21.0),
+            first_dose_efficacy: 0.5,
+        };
+        let solve = |model: AgeSeirModel| {
+            let trajectory = model.solve(Solver::Rk4 { step: 0.1 }, &daily_times(0.0, 300));
+            trajectory.unwrap().last().unwrap().clone()
+        };
+        let unvaccinated = solve(town());
+        let by_age = solve(town().with_vaccination(campaign(Priority::Age)).unwrap());
+        let at_random = solve(town().with_vaccination(campaign(Priority::Random)).unwrap());
+
+        for (band, state) in town().bands().iter().zip(&by_age.bands) {
+            let total = state.s + state.v + state.e + state.infectious() + state.removed();
+            assert!((total - band.population).abs() < 1e-6);
+        }
+        assert!(by_age.bands[2].v > 500.0, "the oldest are vaccinated first");
+        assert!(by_age.bands[2].v > at_random.bands[2].v);
+        let deaths = |sample: &AgeSeirSample| sample.total().d;
+        assert!(deaths(&by_age) < deaths(&at_random));
+        assert!(deaths(&at_random) < deaths(&unvaccinated));
+
+        let mut model = town().with_vaccination(campaign(Priority::Risk)).unwrap();
+        let before = model.run(10).last().unwrap().total();
+        assert_eq!(before.v, 0.0, "nobody is vaccinated before the start");
+        let after = model.run(5).last().unwrap().clone();
+        // Five days of doses, less the vaccinated people infected anyway.
+        let vaccinated = after.total().v;
+        assert!(vaccinated > 450.0 && vaccinated <= 500.0, "{}", vaccinated);
+        assert_eq!(after.bands[2].v, vaccinated, "highest fatality first");
+
+        // Going by age follows `min_age` rather than the order of the bands.
+        let mut model = town().with_vaccination(campaign(Priority::Age)).unwrap();
+        model.bands[0].min_age = Some(90.0);
+        let after = model.run(15).last().unwrap().clone();
+        assert!(after.bands[0].v > 450.0 && after.bands[2].v == 0.0);
+        let mut unordered = town();
+        unordered.bands[1].min_age = None;
+        let error = unordered
+            .with_vaccination(campaign(Priority::Age))
+            .unwrap_err();
+        assert_eq!(error.field, "vaccination.priority");
+    }
+
+    #[test]
+    fn ode_doses_do_not_stop_under_heavy_infection() {
+        let mut model = town();
+        model.beta = 1000.0;
+        let model = model
+            .with_vaccination(VaccinationConfig {
+                start: 0.0,
+                daily_doses: 100.0,
+                priority: Priority::Random,
+                efficacy_against_infection: 0.7,
+                efficacy_against_symptoms: 0.9,
+                second_dose_delay: None,
+                first_dose_efficacy: 0.5,
+            })
+            .unwrap();
+        let y = model.initial_state();
+        assert!(model.forces(&y).iter().all(|&force| force > 1.0));
+        let mut dy = vec![0.0; y.len()];
+        model.derivative(0.0, &y, &mut dy);
+        let doses: f64 = dy.chunks(COMPARTMENTS).map(|dy| dy[V2]).sum();
+        assert!((doses - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn writes_every_band() {
+        let mut model = town();
+        let trajectory = model.run(2);
+        let names: Vec<String> = model.bands().iter().map(|b| b.name.clone()).collect();
+        let mut csv = Vec::new();
+        write(
+            &mut csv,
+            Format::WideCsv,
+            &Metadata::new("age_seir"),
+            &names,
+            &trajectory,
+        )
+        .unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let header = csv.lines().nth(1).unwrap();
+        assert!(header.starts_with("t,s,v,e,i_symptomatic,i_asymptomatic,r,d,young.s,"));
+        assert!(header.ends_with(",old.r,old.d"));
+        assert_eq!(csv.lines().count(), 5);
+    }
+}
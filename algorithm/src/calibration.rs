@@ -0,0 +1,641 @@
+//! Fitting compartment models to observed case counts.
+//!
+//! Incidence is read from a CSV file with a `day` (or `t`) and a `cases`
+//! column, where each row holds the new cases since the previous row, or on
+//! that day for the first row:
+//!
+//! ```text
+//! day,cases
+//! 1,3
+//! 2,5
+//! ```
+//!
+//! `Calibration` then searches for the `beta`, `gamma` and initial number of
+//! infected people whose new infections best match the data, by least
+//! squares or by maximising a Poisson or negative binomial likelihood. The
+//! search uses the Nelder–Mead simplex method over the logarithms of the
+//! parameters, which keeps them positive.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::error::{self, ParamError};
+use crate::scenario::Output;
+use crate::seir::SeirModel;
+use crate::sir::SirModel;
+use crate::solver::{daily_times, SolveError, Solver};
+use crate::trajectory::{Sample, Trajectory};
+
+/// Observed new cases over time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Incidence {
+    ///increasing times of the observations, in days
+    pub days: Vec<f64>,
+    ///new cases since the previous observation
+    pub cases: Vec<f64>,
+}
+
+impl Incidence {
+    pub fn new(days: Vec<f64>, cases: Vec<f64>) -> Result<Incidence, ParamError> {
+        if days.is_empty() || days.len() != cases.len() {
+            return Err(ParamError::new(
+                "cases",
+                "needs one count for each of at least one day",
+            ));
+        }
+        if days.iter().any(|day| !day.is_finite()) || days.windows(2).any(|w| w[1] <= w[0]) {
+            return Err(ParamError::new("days", "must be finite and increasing"));
+        }
+        for &count in &cases {
+            error::non_negative("cases", count)?;
+        }
+        Ok(Incidence { days, cases })
+    }
+
+    /// Reads a CSV file with a header naming a `day` or `t` column and a
+    /// `cases` column. Other columns, blank lines and lines starting with `#`
+    /// are ignored.
+    pub fn from_csv<R: BufRead>(reader: R) -> Result<Incidence, CalibrationError> {
+        let mut columns = None;
+        let mut days = Vec::new();
+        let mut cases = Vec::new();
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let csv = |reason: String| CalibrationError::Csv {
+                line: n + 1,
+                reason,
+            };
+
+            let (day, count) = match columns {
+                Some(columns) => columns,
+                None => {
+                    let find = |names: &[&str]| fields.iter().position(|f| names.contains(f));
+                    let day = find(&["day", "t"])
+                        .ok_or_else(|| csv("the header has no `day` or `t` column".into()))?;
+                    let count = find(&["cases"])
+                        .ok_or_else(|| csv("the header has no `cases` column".into()))?;
+                    columns = Some((day, count));
+                    continue;
+                }
+            };
+            let number = |column: usize| {
+                let field = fields.get(column).copied().unwrap_or_default();
+                field
+                    .parse::<f64>()
+                    .map_err(|_| csv(format!("`{}` is not a number", field)))
+            };
+            days.push(number(day)?);
+            cases.push(number(count)?);
+        }
+        Ok(Incidence::new(days, cases)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Incidence, CalibrationError> {
+        Incidence::from_csv(BufReader::new(File::open(path)?))
+    }
+
+    pub fn len(&self) -> usize {
+        self.days.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.days.is_empty()
+    }
+
+    /// The day before the first observation, when fitted models start.
+    fn start(&self) -> f64 {
+        self.days[0] - 1.0
+    }
+}
+
+#[derive(Debug)]
+pub enum CalibrationError {
+    Io(io::Error),
+    /// A line of the incidence file could not be read.
+    Csv {
+        line: usize,
+        reason: String,
+    },
+    /// The data or a setting is out of range.
+    Invalid(ParamError),
+    /// The ODE solver failed at the estimate.
+    Solve(SolveError),
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::Io(error) => write!(f, "could not read incidence: {}", error),
+            CalibrationError::Csv { line, reason } => write!(f, "line {}: {}", line, reason),
+            CalibrationError::Invalid(error) => error.fmt(f),
+            CalibrationError::Solve(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for CalibrationError {}
+
+impl From<io::Error> for CalibrationError {
+    fn from(error: io::Error) -> CalibrationError {
+        CalibrationError::Io(error)
+    }
+}
+
+impl From<ParamError> for CalibrationError {
+    fn from(error: ParamError) -> CalibrationError {
+        CalibrationError::Invalid(error)
+    }
+}
+
+impl From<SolveError> for CalibrationError {
+    fn from(error: SolveError) -> CalibrationError {
+        CalibrationError::Solve(error)
+    }
+}
+
+/// The model fitted to the data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compartments {
+    Sir,
+    /// SEIR with a known rate `sigma` of leaving the latent period.
+    Seir {
+        sigma: f64,
+    },
+}
+
+/// How the distance between the data and the model is measured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Objective {
+    /// The sum of squared differences.
+    LeastSquares,
+    /// The negative log-likelihood of the cases as Poisson counts.
+    Poisson,
+    /// The negative log-likelihood of the cases as negative binomial counts,
+    /// fitting their dispersion too. Suits data noisier than Poisson.
+    NegativeBinomial,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calibration {
+    pub compartments: Compartments,
+    pub population: f64,
+    pub objective: Objective,
+    ///starting guess for `beta`
+    pub beta: f64,
+    ///starting guess for `gamma`
+    pub gamma: f64,
+    ///starting guess for the people infected the day before the first row
+    pub initial_infected: f64,
+    pub solver: Solver,
+    pub max_iterations: usize,
+    ///the search stops once the objective varies by less than this, relative
+    ///to its size, across the simplex
+    pub tolerance: f64,
+}
+
+/// The best parameters found, and how well they fit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fit {
+    pub beta: f64,
+    pub gamma: f64,
+    pub initial_infected: f64,
+    ///negative binomial size, smaller means more overdispersed
+    pub dispersion: Option<f64>,
+    ///sum of squares or negative log-likelihood at the estimate
+    pub objective: f64,
+    ///root mean squared difference between the cases and the fit
+    pub rmse: f64,
+    ///fraction of the variance in the cases the fit explains
+    pub r_squared: f64,
+    ///Akaike information criterion, for comparing fits to the same data
+    pub aic: f64,
+    pub iterations: usize,
+    ///whether the search met `tolerance` within `max_iterations`
+    pub converged: bool,
+    ///new infections in the model for each row of the data
+    pub fitted: Vec<f64>,
+    ///the fitted model day by day, from the day before the first row
+    pub trajectory: Output,
+}
+
+impl Fit {
+    pub fn basic_reproduction_number(&self) -> f64 {
+        self.beta / self.gamma
+    }
+
+    /// Writes `day,cases,fitted` for each row of `data`.
+    pub fn write_csv<W: Write>(&self, mut writer: W, data: &Incidence) -> io::Result<()> {
+        writeln!(writer, "day,cases,fitted")?;
+        for ((day, cases), fitted) in data.days.iter().zip(&data.cases).zip(&self.fitted) {
+            writeln!(writer, "{},{},{}", day, cases, fitted)?;
+        }
+        Ok(())
+    }
+}
+
+impl Calibration {
+    /// Fits `compartments` to data from a population of `population`, by
+    /// Poisson likelihood starting from `beta = 0.5`, `gamma = 0.2` and one
+    /// initial case.
+    pub fn new(compartments: Compartments, population: f64) -> Calibration {
+        Calibration {
+            compartments,
+            population,
+            objective: Objective::Poisson,
+            beta: 0.5,
+            gamma: 0.2,
+            initial_infected: 1.0,
+            solver: Solver::default(),
+            max_iterations: 2000,
+            tolerance: 1e-10,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ParamError> {
+        if let Compartments::Seir { sigma } = self.compartments {
+            error::positive("sigma", sigma)?;
+        }
+        error::positive("population", self.population)?;
+        error::positive("beta", self.beta)?;
+        error::positive("gamma", self.gamma)?;
+        error::positive("initial_infected", self.initial_infected)?;
+        error::within_population("initial_infected", self.initial_infected, self.population)?;
+        self.solver.validate()?;
+        error::positive("max_iterations", self.max_iterations as f64)?;
+        error::positive("tolerance", self.tolerance)?;
+        Ok(())
+    }
+
+    /// Finds the parameters that best explain `data`.
+    pub fn fit(&self, data: &Incidence) -> Result<Fit, CalibrationError> {
+        self.validate()?;
+        let mut times = vec![0.0];
+        times.extend(data.days.iter().map(|day| day - data.start()));
+
+        let mut start = vec![
+            libm::log(self.beta),
+            libm::log(self.gamma),
+            libm::log(self.initial_infected),
+        ];
+        if self.objective == Objective::NegativeBinomial {
+            start.push(libm::log(10.0));
+        }
+        let objective = |x: &[f64]| {
+            let (beta, gamma, initial_infected) = parameters(x);
+            let dispersion = x.get(3).copied().map(libm::exp);
+            match self.solve(beta, gamma, initial_infected, &times) {
+                Ok(output) => self.distance(&data.cases, &new_infections(&output), dispersion),
+                Err(_) => f64::INFINITY,
+            }
+        };
+
+        // Restarting once from the first estimate guards against the simplex
+        // collapsing before it reaches the minimum.
+        let first = nelder_mead(objective, &start, 0.5, self.max_iterations, self.tolerance);
+        let best = nelder_mead(
+            objective,
+            &first.x,
+            0.1,
+            self.max_iterations,
+            self.tolerance,
+        );
+
+        let (beta, gamma, initial_infected) = parameters(&best.x);
+        let fitted = new_infections(&self.solve(beta, gamma, initial_infected, &times)?);
+        let span = (data.days[data.len() - 1] - data.start()).ceil() as usize;
+        let trajectory = shift(
+            self.solve(beta, gamma, initial_infected, &daily_times(0.0, span))?,
+            data.start(),
+        );
+
+        let n = data.len() as f64;
+        let squares: f64 = data
+            .cases
+            .iter()
+            .zip(&fitted)
+            .map(|(cases, fitted)| (cases - fitted).powi(2))
+            .sum();
+        let mean = data.cases.iter().sum::<f64>() / n;
+        let total: f64 = data.cases.iter().map(|cases| (cases - mean).powi(2)).sum();
+        let parameters = best.x.len() as f64;
+        let aic = match self.objective {
+            Objective::LeastSquares => n * libm::log(squares / n) + 2.0 * parameters,
+            _ => 2.0 * best.value + 2.0 * parameters,
+        };
+
+        Ok(Fit {
+            beta,
+            gamma,
+            initial_infected,
+            dispersion: best.x.get(3).copied().map(libm::exp),
+            objective: best.value,
+            rmse: (squares / n).sqrt(),
+            r_squared: 1.0 - squares / total,
+            aic,
+            iterations: first.iterations + best.iterations,
+            converged: best.converged,
+            fitted,
+            trajectory,
+        })
+    }
+
+    fn solve(
+        &self,
+        beta: f64,
+        gamma: f64,
+        initial_infected: f64,
+        times: &[f64],
+    ) -> Result<Output, CalibrationError> {
+        Ok(match self.compartments {
+            Compartments::Sir => Output::Sir(
+                SirModel::new(self.population, beta, gamma, initial_infected)?
+                    .solve(self.solver, times)?,
+            ),
+            Compartments::Seir { sigma } => Output::Seir(
+                SeirModel::new(self.population, beta, sigma, gamma, initial_infected)?
+                    .solve(self.solver, times)?,
+            ),
+        })
+    }
+
+    fn distance(&self, cases: &[f64], fitted: &[f64], dispersion: Option<f64>) -> f64 {
+        // Keep the likelihoods finite where the model predicts no cases.
+        let pairs = cases.iter().zip(fitted).map(|(&y, &mu)| (y, mu.max(1e-9)));
+        let distance: f64 = match (self.objective, dispersion) {
+            (Objective::LeastSquares, _) => pairs.map(|(y, mu)| (y - mu).powi(2)).sum(),
+            (Objective::Poisson, _) => pairs
+                .map(|(y, mu)| mu - y * libm::log(mu) + libm::lgamma(y + 1.0))
+                .sum(),
+            (Objective::NegativeBinomial, Some(k)) => pairs
+                .map(|(y, mu)| {
+                    -(libm::lgamma(y + k) - libm::lgamma(k) - libm::lgamma(y + 1.0)
+                        + k * libm::log(k / (k + mu))
+                        + y * libm::log(mu / (k + mu)))
+                })
+                .sum(),
+            (Objective::NegativeBinomial, None) => unreachable!("dispersion is always fitted"),
+        };
+        if distance.is_nan() {
+            f64::INFINITY
+        } else {
+            distance
+        }
+    }
+}
+
+/// `beta`, `gamma` and `initial_infected` from the point searched over.
+fn parameters(x: &[f64]) -> (f64, f64, f64) {
+    (libm::exp(x[0]), libm::exp(x[1]), libm::exp(x[2]))
+}
+
+/// The fall in susceptible people between consecutive samples.
+fn new_infections(output: &Output) -> Vec<f64> {
+    let susceptible: Vec<f64> = match output {
+        Output::Sir(trajectory) => trajectory.iter().map(|sample| sample.s).collect(),
+        Output::Seir(trajectory) => trajectory.iter().map(|sample| sample.s).collect(),
+        Output::AgeSeir { trajectory, .. } => {
+            let total = trajectory.iter().map(|sample| sample.total());
+            total.map(|total| total.s + total.v).collect()
+        }
+        Output::World(trajectory) => trajectory
+            .iter()
+            .map(|sample| sample.susceptible as f64)
+            .collect(),
+        Output::Network(trajectory) => trajectory
+            .iter()
+            .map(|sample| sample.susceptible as f64)
+            .collect(),
+    };
+    susceptible.windows(2).map(|w| w[0] - w[1]).collect()
+}
+
+/// Moves every sample `offset` days later.
+fn shift(output: Output, offset: f64) -> Output {
+    fn later<S: Sample>(trajectory: Trajectory<S>, offset: f64) -> Trajectory<S> {
+        let samples: Vec<S> = trajectory
+            .iter()
+            .map(|sample| sample.at_time(sample.time() + offset))
+            .collect();
+        samples.into()
+    }
+    match output {
+        Output::Sir(trajectory) => Output::Sir(later(trajectory, offset)),
+        Output::Seir(trajectory) => Output::Seir(later(trajectory, offset)),
+        Output::AgeSeir { bands, trajectory } => Output::AgeSeir {
+            bands,
+            trajectory: later(trajectory, offset),
+        },
+        Output::World(trajectory) => Output::World(later(trajectory, offset)),
+        Output::Network(trajectory) => Output::Network(later(trajectory, offset)),
+    }
+}
+
+/// The lowest point found by `nelder_mead`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Minimum {
+    pub x: Vec<f64>,
+    pub value: f64,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+/// Minimises `f` with the Nelder–Mead simplex method, starting from a
+/// simplex around `start` with sides of length `step`. Stops after
+/// `max_iterations`, or once the values at the corners of the simplex are
+/// within `tolerance` of each other relative to their size.
+pub fn nelder_mead<F: Fn(&[f64]) -> f64>(
+    f: F,
+    start: &[f64],
+    step: f64,
+    max_iterations: usize,
+    tolerance: f64,
+) -> Minimum {
+    let n = start.len();
+    let mut simplex: Vec<(Vec<f64>, f64)> = (0..=n)
+        .map(|corner| {
+            let mut x = start.to_vec();
+            if corner > 0 {
+                x[corner - 1] += step;
+            }
+            let value = f(&x);
+            (x, value)
+        })
+        .collect();
+    // Points along the line from the centroid through the worst corner.
+    let along = |centroid: &[f64], worst: &[f64], t: f64| -> Vec<f64> {
+        centroid
+            .iter()
+            .zip(worst)
+            .map(|(c, w)| c + t * (w - c))
+            .collect()
+    };
+
+    let mut iterations = 0;
+    let mut converged = false;
+    while iterations < max_iterations {
+        simplex.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        let (best, worst) = (simplex[0].1, simplex[n].1);
+        if worst - best <= tolerance * (1.0 + best.abs()) {
+            converged = true;
+            break;
+        }
+        iterations += 1;
+
+        let mut centroid = vec![0.0; n];
+        for (x, _) in &simplex[..n] {
+            for (c, x) in centroid.iter_mut().zip(x) {
+                *c += x / n as f64;
+            }
+        }
+
+        let reflected = along(&centroid, &simplex[n].0, -1.0);
+        let reflected_value = f(&reflected);
+        if reflected_value < best {
+            let expanded = along(&centroid, &simplex[n].0, -2.0);
+            let expanded_value = f(&expanded);
+            simplex[n] = if expanded_value < reflected_value {
+                (expanded, expanded_value)
+            } else {
+                (reflected, reflected_value)
+            };
+        } else if reflected_value < simplex[n - 1].1 {
+            simplex[n] = (reflected, reflected_value);
+        } else {
+            let contracted = if reflected_value < worst {
+                along(&centroid, &simplex[n].0, -0.5)
+            } else {
+                along(&centroid, &simplex[n].0, 0.5)
+            };
+            let contracted_value = f(&contracted);
+            if contracted_value < reflected_value.min(worst) {
+                simplex[n] = (contracted, contracted_value);
+            } else {
+                // Shrink every corner towards the best one.
+                let best = simplex[0].0.clone();
+                for (x, value) in &mut simplex[1..] {
+                    *x = along(&best, x, 0.5);
+                    *value = f(x);
+                }
+            }
+        }
+    }
+
+    simplex.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+    let (x, value) = simplex.swap_remove(0);
+    Minimum {
+        x,
+        value,
+        iterations,
+        converged,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimises_rosenbrock() {
+        let rosenbrock = |x: &[f64]| (1.0 - x[0]).powi(2) + 100.0 * (x[1] - x[0] * x[0]).powi(2);
+        let minimum = nelder_mead(rosenbrock, &[-1.2, 1.0], 0.5, 5000, 1e-14);
+        assert!(minimum.converged);
+        assert!((minimum.x[0] - 1.0).abs() < 1e-3 && (minimum.x[1] - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn reads_incidence_csv() {
+        let csv = "# cases by report date\nweek,day,cases\n1,7,3\n2,14, 5\n\n3,21,12\n";
+        let data = Incidence::from_csv(csv.as_bytes()).unwrap();
+        assert_eq!(data.days, [7.0, 14.0, 21.0]);
+        assert_eq!(data.cases, [3.0, 5.0, 12.0]);
+
+        let error = |csv: &str| Incidence::from_csv(csv.as_bytes()).unwrap_err().to_string();
+        assert_eq!(
+            error("day,count\n1,2\n"),
+            "line 1: the header has no `cases` column"
+        );
+        assert_eq!(
+            error("day,cases\n1,2\n2,lots\n"),
+            "line 3: `lots` is not a number"
+        );
+        assert_eq!(
+            error("day,cases\n2,1\n1,2\n"),
+            "invalid `days`: must be finite and increasing"
+        );
+        assert!(error("day,cases\n").contains("at least one day"));
+    }
+
+    // A fixed step keeps the thousands of solves in a fit quick.
+    const SOLVER: Solver = Solver::Rk4 { step: 0.25 };
+
+    /// Daily new infections of an SIR model, rounded to whole cases.
+    fn synthetic(days: usize) -> Incidence {
+        let model = SirModel::new(10_000.0, 0.4, 0.15, 5.0).unwrap();
+        let output = Output::Sir(model.solve(SOLVER, &daily_times(0.0, days)).unwrap());
+        let cases = new_infections(&output).iter().map(|x| x.round()).collect();
+        Incidence::new((1..=days).map(|day| day as f64).collect(), cases).unwrap()
+    }
+
+    #[test]
+    fn recovers_sir_parameters() {
+        let data = synthetic(120);
+        for &objective in &[
+            Objective::LeastSquares,
+            Objective::Poisson,
+            Objective::NegativeBinomial,
+        ] {
+            let calibration = Calibration {
+                objective,
+                solver: SOLVER,
+                ..Calibration::new(Compartments::Sir, 10_000.0)
+            };
+            let fit = calibration.fit(&data).unwrap();
+            assert!(
+                (fit.beta - 0.4).abs() < 0.01,
+                "{:?}: {}",
+                objective,
+                fit.beta
+            );
+            assert!(
+                (fit.gamma - 0.15).abs() < 0.01,
+                "{:?}: {}",
+                objective,
+                fit.gamma
+            );
+            assert!((fit.initial_infected - 5.0).abs() < 1.0, "{:?}", objective);
+            assert!(fit.r_squared > 0.99);
+            assert_eq!(fit.fitted.len(), 120);
+            assert_eq!(
+                fit.dispersion.is_some(),
+                objective == Objective::NegativeBinomial
+            );
+            match &fit.trajectory {
+                Output::Sir(trajectory) => {
+                    assert_eq!(trajectory.len(), 121);
+                    assert_eq!(trajectory.first().unwrap().t, 0.0);
+                }
+                output => panic!("unexpected output {:?}", output),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_settings() {
+        let data = synthetic(10);
+        let calibration = Calibration {
+            initial_infected: 20_000.0,
+            ..Calibration::new(Compartments::Seir { sigma: 0.2 }, 10_000.0)
+        };
+        assert_eq!(
+            calibration.fit(&data).unwrap_err().to_string(),
+            "invalid `initial_infected`: must not exceed the population"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! How long people stay in each stage of an infection.
+//!
+//! Compartment models with constant rates imply exponentially distributed
+//! durations, where most people recover on the first day. Real incubation and
+//! infectious periods cluster around their mean, so the agent-based model can
+//! draw each person's durations from one of these distributions instead.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{self, ParamError};
+use crate::rng;
+
+/// A distribution of durations in days, written in scenario files as, for
+/// example, `{ gamma = { mean = 5.2, sd = 2.8 } }` or `{ fixed = 5.0 }`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Distribution {
+    /// The same duration for everyone.
+    Fixed(f64),
+    Exponential {
+        mean: f64,
+    },
+    Gamma {
+        mean: f64,
+        sd: f64,
+    },
+    ///the mean and standard deviation of the duration itself, not its log
+    LogNormal {
+        mean: f64,
+        sd: f64,
+    },
+    Weibull {
+        shape: f64,
+        scale: f64,
+    },
+}
+
+impl Distribution {
+    /// Checks the parameters, reporting problems against `field`.
+    pub fn validate(&self, field: &'static str) -> Result<(), ParamError> {
+        let positive = |x: f64| x.is_finite() && x > 0.0;
+        match *self {
+            Distribution::Fixed(days) => error::non_negative(field, days).map(drop),
+            Distribution::Exponential { mean } => error::positive(field, mean).map(drop),
+            Distribution::Gamma { mean, sd } | Distribution::LogNormal { mean, sd } => {
+                if positive(mean) && positive(sd) {
+                    Ok(())
+                } else {
+                    Err(ParamError::new(
+                        field,
+                        "needs a `mean` and `sd` greater than zero",
+                    ))
+                }
+            }
+            Distribution::Weibull { shape, scale } => {
+                if positive(shape) && positive(scale) {
+                    Ok(())
+                } else {
+                    Err(ParamError::new(
+                        field,
+                        "needs a `shape` and `scale` greater than zero",
+                    ))
+                }
+            }
+        }
+    }
+
+    /// The name used for this distribution in scenario files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Distribution::Fixed(_) => "fixed",
+            Distribution::Exponential { .. } => "exponential",
+            Distribution::Gamma { .. } => "gamma",
+            Distribution::LogNormal { .. } => "log_normal",
+            Distribution::Weibull { .. } => "weibull",
+        }
+    }
+
+    /// The parameters as written in scenario files, with `days` for `fixed`.
+    pub fn parameters(&self) -> Vec<(&'static str, f64)> {
+        match *self {
+            Distribution::Fixed(days) => vec![("days", days)],
+            Distribution::Exponential { mean } => vec![("mean", mean)],
+            Distribution::Gamma { mean, sd } | Distribution::LogNormal { mean, sd } => {
+                vec![("mean", mean), ("sd", sd)]
+            }
+            Distribution::Weibull { shape, scale } => vec![("shape", shape), ("scale", scale)],
+        }
+    }
+
+    pub fn mean(&self) -> f64 {
+        match *self {
+            Distribution::Fixed(days) => days,
+            Distribution::Exponential { mean }
+            | Distribution::Gamma { mean, .. }
+            | Distribution::LogNormal { mean, .. } => mean,
+            Distribution::Weibull { shape, scale } => scale * libm::tgamma(1.0 + 1.0 / shape),
+        }
+    }
+
+    /// Draws a duration. `Fixed` does not consume any randomness.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match *self {
+            Distribution::Fixed(days) => days,
+            Distribution::Exponential { mean } => rng::exponential(rng, 1.0 / mean),
+            Distribution::Gamma { mean, sd } => {
+                rng::gamma(rng, (mean / sd).powi(2), sd * sd / mean)
+            }
+            Distribution::LogNormal { mean, sd } => {
+                let variance = libm::log(1.0 + (sd / mean).powi(2));
+                let mu = libm::log(mean) - variance / 2.0;
+                libm::exp(mu + variance.sqrt() * rng::standard_normal(rng))
+            }
+            Distribution::Weibull { shape, scale } => {
+                scale * libm::pow(rng::exponential(rng, 1.0), 1.0 / shape)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::seeded;
+
+    fn moments(distribution: Distribution) -> (f64, f64) {
+        let mut rng = seeded(4);
+        let draws: Vec<f64> = (0..20_000).map(|_| distribution.sample(&mut rng)).collect();
+        let mean = draws.iter().sum::<f64>() / draws.len() as f64;
+        let variance =
+            draws.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (draws.len() - 1) as f64;
+        assert!(draws.iter().all(|&x| x >= 0.0));
+        (mean, variance.sqrt())
+    }
+
+    #[test]
+    fn samples_have_the_requested_moments() {
+        for &(distribution, sd) in &[
+            (Distribution::Exponential { mean: 4.0 }, 4.0),
+            (Distribution::Gamma { mean: 5.0, sd: 2.0 }, 2.0),
+            (Distribution::Gamma { mean: 1.0, sd: 2.0 }, 2.0),
+            (Distribution::LogNormal { mean: 6.0, sd: 3.0 }, 3.0),
+            // shape 2, scale 5: sd = 5 * sqrt(Γ(2) - Γ(1.5)²)
+            (
+                Distribution::Weibull {
+                    shape: 2.0,
+                    scale: 5.0,
+                },
+                2.3163,
+            ),
+        ] {
+            let (sample_mean, sample_sd) = moments(distribution);
+            let mean = distribution.mean();
+            assert!(
+                (sample_mean - mean).abs() < 0.05 * mean,
+                "{:?}",
+                distribution
+            );
+            assert!((sample_sd - sd).abs() < 0.05 * sd, "{:?}", distribution);
+        }
+        assert_eq!(moments(Distribution::Fixed(3.0)), (3.0, 0.0));
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        let field = |d: Distribution| d.validate("incubation_period").unwrap_err().field;
+        assert_eq!(field(Distribution::Fixed(-1.0)), "incubation_period");
+        assert_eq!(
+            field(Distribution::Exponential { mean: 0.0 }),
+            "incubation_period"
+        );
+        assert!(Distribution::Gamma { mean: 5.0, sd: 0.0 }
+            .validate("x")
+            .is_err());
+        assert!(Distribution::Weibull {
+            shape: f64::NAN,
+            scale: 1.0
+        }
+        .validate("x")
+        .is_err());
+        assert!(Distribution::LogNormal { mean: 5.0, sd: 1.0 }
+            .validate("x")
+            .is_ok());
+    }
+}
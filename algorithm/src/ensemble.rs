@@ -0,0 +1,365 @@
+//! Many replicates of a stochastic model, summarised.
+//!
+//! A single stochastic run says little about what to expect, so an `Ensemble`
+//! runs the same model with a range of seeds and reports, at every time step,
+//! the mean, median and percentile bands of every compartment, along with
+//! the distribution of the epidemic's peak and final size.
+//!
+//! With the `parallel` feature replicates run on rayon's thread pool. On wasm
+//! they always run one after another.
+
+use crate::error::{self, ParamError};
+use crate::gillespie::{self, Reactions};
+use crate::solver::daily_times;
+use crate::trajectory::{Sample, Trajectory};
+use crate::world::{World, WorldConfig, WorldSample};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ensemble {
+    pub replicates: usize,
+    ///replicate `n` is seeded with `seed + n`
+    pub seed: u64,
+    ///percentiles reported besides the median, between 0 and 100
+    pub percentiles: Vec<f64>,
+}
+
+impl Default for Ensemble {
+    fn default() -> Ensemble {
+        Ensemble {
+            replicates: 100,
+            seed: 0,
+            percentiles: vec![2.5, 25.0, 75.0, 97.5],
+        }
+    }
+}
+
+impl Ensemble {
+    pub fn validate(&self) -> Result<(), ParamError> {
+        error::positive("replicates", self.replicates as f64)?;
+        for &p in &self.percentiles {
+            if !(0.0..=100.0).contains(&p) {
+                return Err(ParamError::new("percentiles", "must be between 0 and 100"));
+            }
+        }
+        Ok(())
+    }
+
+    /// The seed of every replicate, in order.
+    pub fn seeds(&self) -> Vec<u64> {
+        (0..self.replicates as u64)
+            .map(|n| self.seed.wrapping_add(n))
+            .collect()
+    }
+
+    /// Calls `simulate` with the seed of every replicate, resamples the
+    /// resulting trajectories onto `times` and summarises them.
+    pub fn run<S, F>(&self, times: &[f64], simulate: F) -> Result<EnsembleResult<S>, ParamError>
+    where
+        S: Sample + Send,
+        F: Fn(u64) -> Trajectory<S> + Sync,
+    {
+        self.validate()?;
+        if times.is_empty() {
+            return Err(ParamError::new("times", "must not be empty"));
+        }
+
+        let runs = map_seeds(self.seeds(), |seed| simulate(seed).resample(times));
+
+        let summary = Summary::new(&runs, &self.percentiles);
+        Ok(EnsembleResult { runs, summary })
+    }
+
+    /// Replicates of `model` simulated with Gillespie's method, reported at
+    /// the start of each day.
+    pub fn gillespie<M>(
+        &self,
+        model: &M,
+        days: usize,
+    ) -> Result<EnsembleResult<M::Sample>, ParamError>
+    where
+        M: Reactions + Sync,
+        M::Sample: Sample + Send,
+    {
+        let times = daily_times(model.initial_time(), days);
+        let t_end = *times.last().unwrap();
+        self.run(&times, |seed| gillespie::simulate(model, t_end, seed))
+    }
+
+    /// Replicates of a `World` built from `config`, run for `days`.
+    pub fn world(
+        &self,
+        config: &WorldConfig,
+        days: usize,
+    ) -> Result<EnsembleResult<WorldSample>, ParamError> {
+        config.validate()?;
+        let times = daily_times(0.0, days);
+        self.run(&times, |seed| {
+            World::new(config.clone(), seed)
+                .expect("config was validated")
+                .run(days)
+        })
+    }
+}
+
+/// Calls `f` with every seed, on rayon's thread pool with the `parallel`
+/// feature, and collects the results in order.
+pub(crate) fn map_seeds<T, F>(seeds: Vec<u64>, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(u64) -> T + Sync,
+{
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    {
+        use rayon::prelude::*;
+        seeds.into_par_iter().map(&f).collect()
+    }
+    #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+    {
+        seeds.into_iter().map(f).collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EnsembleResult<S> {
+    ///every replicate, in the order of `Ensemble::seeds`
+    pub runs: Vec<Trajectory<S>>,
+    pub summary: Summary,
+}
+
+/// Statistics across the replicates of an ensemble.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub times: Vec<f64>,
+    ///one band per compartment, in the order of `Sample::COMPARTMENTS`
+    pub bands: Vec<Band>,
+    ///the largest number of people infectious at once
+    pub peak_size: OutcomeDistribution,
+    ///when the number of people infectious first peaked
+    pub peak_time: OutcomeDistribution,
+    ///how many people had been removed by the end
+    pub final_size: OutcomeDistribution,
+}
+
+/// The spread of one compartment over time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Band {
+    pub compartment: &'static str,
+    pub mean: Vec<f64>,
+    pub median: Vec<f64>,
+    ///`(percentile, values over time)` for each requested percentile
+    pub percentiles: Vec<(f64, Vec<f64>)>,
+}
+
+/// The spread of a single value per replicate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutcomeDistribution {
+    ///one value per replicate, sorted in ascending order
+    pub values: Vec<f64>,
+    pub mean: f64,
+    pub median: f64,
+    pub percentiles: Vec<(f64, f64)>,
+}
+
+impl Summary {
+    /// Summarises `runs`, which must all be sampled at the same times.
+    pub fn new<S: Sample>(runs: &[Trajectory<S>], percentiles: &[f64]) -> Summary {
+        let times: Vec<f64> = runs
+            .first()
+            .map(|run| run.iter().map(Sample::time).collect())
+            .unwrap_or_default();
+        let values: Vec<Vec<Vec<f64>>> = runs
+            .iter()
+            .map(|run| run.iter().map(Sample::values).collect())
+            .collect();
+
+        let bands = S::COMPARTMENTS
+            .iter()
+            .enumerate()
+            .map(|(c, &compartment)| {
+                let mut band = Band {
+                    compartment,
+                    mean: Vec::with_capacity(times.len()),
+                    median: Vec::with_capacity(times.len()),
+                    percentiles: percentiles
+                        .iter()
+                        .map(|&p| (p, Vec::with_capacity(times.len())))
+                        .collect(),
+                };
+                for step in 0..times.len() {
+                    let across = OutcomeDistribution::new(
+                        values.iter().map(|run| run[step][c]).collect(),
+                        percentiles,
+                    );
+                    band.mean.push(across.mean);
+                    band.median.push(across.median);
+                    let series = band.percentiles.iter_mut().map(|(_, series)| series);
+                    for (series, &(_, value)) in series.zip(&across.percentiles) {
+                        series.push(value);
+                    }
+                }
+                band
+            })
+            .collect();
+
+        let outcomes: Vec<Outcome> = runs.iter().map(Outcome::of).collect();
+        let (peak_size, peak_time, final_size) = Outcome::distributions(&outcomes, percentiles);
+        Summary {
+            times,
+            bands,
+            peak_size,
+            peak_time,
+            final_size,
+        }
+    }
+
+    pub fn band(&self, compartment: &str) -> Option<&Band> {
+        self.bands
+            .iter()
+            .find(|band| band.compartment == compartment)
+    }
+}
+
+/// The headline numbers of a single run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Outcome {
+    ///the largest number of people infectious at once
+    pub peak_size: f64,
+    ///when the number of people infectious first peaked
+    pub peak_time: f64,
+    ///how many people had been removed by the end
+    pub final_size: f64,
+}
+
+impl Outcome {
+    pub fn of<S: Sample>(trajectory: &Trajectory<S>) -> Outcome {
+        let mut outcome = Outcome {
+            peak_size: 0.0,
+            peak_time: trajectory.first().map_or(0.0, Sample::time),
+            final_size: trajectory.last().map_or(0.0, Sample::removed),
+        };
+        for sample in trajectory {
+            if sample.infectious() > outcome.peak_size {
+                outcome.peak_size = sample.infectious();
+                outcome.peak_time = sample.time();
+            }
+        }
+        outcome
+    }
+
+    /// The spread of peak size, peak time and final size over `outcomes`.
+    pub fn distributions(
+        outcomes: &[Outcome],
+        percentiles: &[f64],
+    ) -> (
+        OutcomeDistribution,
+        OutcomeDistribution,
+        OutcomeDistribution,
+    ) {
+        let spread = |f: fn(&Outcome) -> f64| {
+            OutcomeDistribution::new(outcomes.iter().map(f).collect(), percentiles)
+        };
+        (
+            spread(|o| o.peak_size),
+            spread(|o| o.peak_time),
+            spread(|o| o.final_size),
+        )
+    }
+}
+
+impl OutcomeDistribution {
+    pub fn new(mut values: Vec<f64>, percentiles: &[f64]) -> OutcomeDistribution {
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mean = if values.is_empty() {
+            f64::NAN
+        } else {
+            values.iter().sum::<f64>() / values.len() as f64
+        };
+        OutcomeDistribution {
+            mean,
+            median: percentile(&values, 50.0),
+            percentiles: percentiles
+                .iter()
+                .map(|&p| (p, percentile(&values, p)))
+                .collect(),
+            values,
+        }
+    }
+}
+
+/// The `p`th percentile of `sorted`, interpolating linearly between the two
+/// closest values. NaN when `sorted` is empty.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let below = rank.floor() as usize;
+    let above = rank.ceil() as usize;
+    sorted[below] + (sorted[above] - sorted[below]) * (rank - below as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sir::SirModel;
+
+    #[test]
+    fn percentiles_interpolate() {
+        let sorted = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 50.0), 3.0);
+        assert_eq!(percentile(&sorted, 100.0), 5.0);
+        assert_eq!(percentile(&sorted, 12.5), 1.5);
+        assert!(percentile(&[], 50.0).is_nan());
+    }
+
+    #[test]
+    fn bands_are_ordered() {
+        let model = SirModel::new(300.0, 0.5, 0.2, 3.0).unwrap();
+        let ensemble = Ensemble {
+            replicates: 40,
+            ..Ensemble::default()
+        };
+        let result = ensemble.gillespie(&model, 60).unwrap();
+        let summary = &result.summary;
+
+        assert_eq!(result.runs.len(), 40);
+        assert_eq!(summary.times.len(), 61);
+        assert_eq!(summary.bands.len(), 3);
+        let infectious = summary.band("i").unwrap();
+        for step in 0..summary.times.len() {
+            let low = infectious.percentiles[0].1[step];
+            let high = infectious.percentiles[3].1[step];
+            assert!(low <= infectious.median[step] && infectious.median[step] <= high);
+            assert!(low <= infectious.mean[step] && infectious.mean[step] <= high);
+        }
+        assert_eq!(summary.final_size.values.len(), 40);
+        assert!(summary.peak_size.median <= summary.final_size.median);
+    }
+
+    #[test]
+    fn same_seed_same_summary() {
+        let config = WorldConfig {
+            population: 100,
+            ..WorldConfig::default()
+        };
+        let ensemble = Ensemble {
+            replicates: 4,
+            seed: 9,
+            ..Ensemble::default()
+        };
+        let a = ensemble.world(&config, 10).unwrap();
+        let b = ensemble.world(&config, 10).unwrap();
+        assert_eq!(a.summary, b.summary);
+        assert_eq!(a.runs[1], World::new(config, 10).unwrap().run(10));
+    }
+
+    #[test]
+    fn rejects_bad_percentiles() {
+        let ensemble = Ensemble {
+            percentiles: vec![50.0, 150.0],
+            ..Ensemble::default()
+        };
+        assert_eq!(ensemble.validate().unwrap_err().field, "percentiles");
+    }
+}
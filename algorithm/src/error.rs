@@ -0,0 +1,70 @@
+use std::fmt;
+
+/// A model parameter was outside of its valid range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamError {
+    /// Name of the offending parameter.
+    pub field: &'static str,
+    /// Why the value was rejected.
+    pub reason: &'static str,
+}
+
+impl ParamError {
+    pub(crate) fn new(field: &'static str, reason: &'static str) -> ParamError {
+        ParamError { field, reason }
+    }
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid `{}`: {}", self.field, self.reason)
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+pub(crate) fn positive(field: &'static str, value: f64) -> Result<f64, ParamError> {
+    if value.is_finite() && value > 0.0 {
+        Ok(value)
+    } else {
+        Err(ParamError::new(
+            field,
+            "must be a finite number greater than zero",
+        ))
+    }
+}
+
+pub(crate) fn non_negative(field: &'static str, value: f64) -> Result<f64, ParamError> {
+    if value.is_finite() && value >= 0.0 {
+        Ok(value)
+    } else {
+        Err(ParamError::new(
+            field,
+            "must be a finite, non-negative number",
+        ))
+    }
+}
+
+pub(crate) fn within_population(
+    field: &'static str,
+    value: f64,
+    population: f64,
+) -> Result<f64, ParamError> {
+    non_negative(field, value)?;
+    if value <= population {
+        Ok(value)
+    } else {
+        Err(ParamError::new(field, "must not exceed the population"))
+    }
+}
+
+pub(crate) fn probability(field: &'static str, value: f64) -> Result<f64, ParamError> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(ParamError::new(
+            field,
+            "must be a probability between 0 and 1",
+        ))
+    }
+}
@@ -0,0 +1,343 @@
+//! Writing trajectories out for analysis elsewhere.
+//!
+//! CSV files start with `#` comment lines describing the model, its
+//! parameters and the seed, which pandas skips with `comment="#"` and R with
+//! `comment.char = "#"`. JSON files carry the same metadata as an object
+//! next to the samples.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::error::ParamError;
+use crate::trajectory::{Sample, Trajectory};
+use crate::vaccination::VaccinationConfig;
+
+/// The layouts trajectories can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    WideCsv,
+    LongCsv,
+    Json,
+}
+
+impl Format {
+    /// The file extension usually used for this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::WideCsv | Format::LongCsv => "csv",
+            Format::Json => "json",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = ParamError;
+
+    /// Parses `wide`, `long` or `json`.
+    fn from_str(s: &str) -> Result<Format, ParamError> {
+        match s {
+            "wide" => Ok(Format::WideCsv),
+            "long" => Ok(Format::LongCsv),
+            "json" => Ok(Format::Json),
+            _ => Err(ParamError::new(
+                "format",
+                "must be `wide`, `long` or `json`",
+            )),
+        }
+    }
+}
+
+/// Writes `trajectory` in `format`.
+pub fn write<S: Sample + Serialize, W: Write>(
+    writer: W,
+    format: Format,
+    metadata: &Metadata,
+    trajectory: &Trajectory<S>,
+) -> io::Result<()> {
+    match format {
+        Format::WideCsv => write_wide_csv(writer, metadata, trajectory),
+        Format::LongCsv => write_long_csv(writer, metadata, trajectory),
+        Format::Json => write_json(writer, metadata, trajectory),
+    }
+}
+
+/// Describes how a trajectory was produced.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Metadata {
+    pub model: String,
+    ///seed of the random number generator, for stochastic models
+    pub seed: Option<u64>,
+    #[serde(serialize_with = "serialize_parameters")]
+    pub parameters: Vec<(String, Parameter)>,
+}
+
+/// The value of a model parameter, kept at the precision the model uses so
+/// an `f32` is written as `0.2` rather than `0.20000000298023224`.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(untagged)]
+pub enum Parameter {
+    F32(f32),
+    F64(f64),
+}
+
+impl From<f32> for Parameter {
+    fn from(value: f32) -> Parameter {
+        Parameter::F32(value)
+    }
+}
+
+impl From<f64> for Parameter {
+    fn from(value: f64) -> Parameter {
+        Parameter::F64(value)
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Parameter::F32(value) => value.fmt(f),
+            Parameter::F64(value) => value.fmt(f),
+        }
+    }
+}
+
+impl Metadata {
+    pub fn new(model: &str) -> Metadata {
+        Metadata {
+            model: model.to_string(),
+            seed: None,
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Metadata {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn parameter<V: Into<Parameter>>(mut self, name: &str, value: V) -> Metadata {
+        self.parameters.push((name.to_string(), value.into()));
+        self
+    }
+
+    /// Adds a number of Erlang stages, leaving out the usual single stage.
+    pub(crate) fn stages(self, name: &str, stages: usize) -> Metadata {
+        match stages {
+            1 => self,
+            stages => self.parameter(name, stages as f64),
+        }
+    }
+
+    /// Adds the parameters of a vaccination campaign, if there is one.
+    pub(crate) fn vaccination(self, vaccination: Option<&VaccinationConfig>) -> Metadata {
+        let vaccination = match vaccination {
+            Some(vaccination) => vaccination,
+            None => return self,
+        };
+        // Metadata only holds numbers, so the priority is left out.
+        let metadata = self
+            .parameter("vaccination.start", vaccination.start)
+            .parameter("vaccination.daily_doses", vaccination.daily_doses)
+            .parameter(
+                "vaccination.efficacy_against_infection",
+                vaccination.efficacy_against_infection,
+            )
+            .parameter(
+                "vaccination.efficacy_against_symptoms",
+                vaccination.efficacy_against_symptoms,
+            );
+        match vaccination.second_dose_delay {
+            Some(delay) => metadata
+                .parameter("vaccination.second_dose_delay", delay)
+                .parameter(
+                    "vaccination.first_dose_efficacy",
+                    vaccination.first_dose_efficacy,
+                ),
+            None => metadata,
+        }
+    }
+
+    pub(crate) fn write_header<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "# model: {}", self.model)?;
+        if let Some(seed) = self.seed {
+            writeln!(writer, "# seed: {}", seed)?;
+        }
+        for (name, value) in &self.parameters {
+            writeln!(writer, "# {}: {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the parameters in order while writing them as a JSON object.
+fn serialize_parameters<S: Serializer>(
+    parameters: &[(String, Parameter)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(parameters.len()))?;
+    for (name, value) in parameters {
+        map.serialize_entry(name, value)?;
+    }
+    map.end()
+}
+
+/// Writes one row per sample with a column per compartment:
+///
+/// ```text
+/// t,s,i,r
+/// 0,999,1,0
+/// ```
+pub fn write_wide_csv<S: Sample, W: Write>(
+    mut writer: W,
+    metadata: &Metadata,
+    trajectory: &Trajectory<S>,
+) -> io::Result<()> {
+    metadata.write_header(&mut writer)?;
+    write!(writer, "t")?;
+    for compartment in S::COMPARTMENTS {
+        write!(writer, ",{}", compartment)?;
+    }
+    writeln!(writer)?;
+
+    for sample in trajectory {
+        write!(writer, "{}", sample.time())?;
+        for value in sample.values() {
+            write!(writer, ",{}", value)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Writes one row per sample and compartment, grouped by compartment:
+///
+/// ```text
+/// t,compartment,value
+/// 0,s,999
+/// ```
+pub fn write_long_csv<S: Sample, W: Write>(
+    mut writer: W,
+    metadata: &Metadata,
+    trajectory: &Trajectory<S>,
+) -> io::Result<()> {
+    metadata.write_header(&mut writer)?;
+    writeln!(writer, "t,compartment,value")?;
+
+    let values: Vec<Vec<f64>> = trajectory.iter().map(Sample::values).collect();
+    for (c, compartment) in S::COMPARTMENTS.iter().enumerate() {
+        for (sample, values) in trajectory.iter().zip(&values) {
+            writeln!(writer, "{},{},{}", sample.time(), compartment, values[c])?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes `{"metadata": {...}, "samples": [...]}`.
+pub fn write_json<S: Serialize, W: Write>(
+    mut writer: W,
+    metadata: &Metadata,
+    trajectory: &Trajectory<S>,
+) -> io::Result<()> {
+    #[derive(serde::Serialize)]
+    struct Document<'a, S> {
+        metadata: &'a Metadata,
+        samples: &'a Trajectory<S>,
+    }
+
+    serde_json::to_writer_pretty(
+        &mut writer,
+        &Document {
+            metadata,
+            samples: trajectory,
+        },
+    )?;
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sir::SirModel;
+    use crate::world::{World, WorldConfig, WorldSample};
+
+    fn write<F: FnOnce(&mut Vec<u8>) -> io::Result<()>>(f: F) -> String {
+        let mut buffer = Vec::new();
+        f(&mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn wide_and_long_csv() {
+        let mut model = SirModel::new(100.0, 0.5, 0.25, 4.0).unwrap();
+        let metadata = model.metadata();
+        let trajectory = model.run(1);
+
+        let wide = write(|w| write_wide_csv(w, &metadata, &trajectory));
+        assert_eq!(
+            wide,
+            "# model: sir\n\
+             # population: 100\n\
+             # beta: 0.5\n\
+             # gamma: 0.25\n\
+             t,s,i,r\n\
+             0,96,4,0\n\
+             1,94.08,4.92,1\n"
+        );
+
+        let long = write(|w| write_long_csv(w, &metadata, &trajectory));
+        let rows: Vec<&str> = long.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(
+            rows,
+            [
+                "t,compartment,value",
+                "0,s,96",
+                "1,s,94.08",
+                "0,i,4",
+                "1,i,4.92",
+                "0,r,0",
+                "1,r,1",
+            ]
+        );
+    }
+
+    #[test]
+    fn single_precision_parameters_print_as_written() {
+        let config = WorldConfig {
+            transmission_probability: 0.2,
+            ..WorldConfig::default()
+        };
+        let header = write(|w| config.metadata(0).write_header(w));
+        assert!(header.contains("# transmission_probability: 0.2\n"));
+        assert!(header.lines().all(|line| !line.contains("0000000")));
+
+        let json = write(|w| {
+            write_json(
+                w,
+                &config.metadata(0),
+                &Trajectory::<WorldSample>::default(),
+            )
+        });
+        assert!(json.contains("\"transmission_probability\": 0.2,"));
+    }
+
+    #[test]
+    fn json_carries_metadata() {
+        let config = WorldConfig {
+            population: 50,
+            ..WorldConfig::default()
+        };
+        let metadata = config.metadata(7);
+        let trajectory = World::new(config, 7).unwrap().run(2);
+
+        let json = write(|w| write_json(w, &metadata, &trajectory));
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["metadata"]["model"], "world");
+        assert_eq!(value["metadata"]["seed"], 7);
+        assert_eq!(value["metadata"]["parameters"]["population"], 50.0);
+        assert_eq!(value["samples"].as_array().unwrap().len(), 3);
+        assert_eq!(value["samples"][2]["t"], 2.0);
+    }
+}
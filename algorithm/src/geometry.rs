@@ -0,0 +1,126 @@
+use std::ops::{Add, AddAssign, Mul, Sub};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// A point or displacement in the 2D arena.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vec2 {
+    pub const ZERO: Vec2 = Vec2 { x: 0.0, y: 0.0 };
+
+    pub fn new(x: f32, y: f32) -> Vec2 {
+        Vec2 { x, y }
+    }
+
+    pub fn length_squared(self) -> f32 {
+        self.x * self.x + self.y * self.y
+    }
+
+    pub fn length(self) -> f32 {
+        self.length_squared().sqrt()
+    }
+
+    pub fn dot(self, other: Vec2) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn distance_squared(self, other: Vec2) -> f32 {
+        (self - other).length_squared()
+    }
+
+    /// A uniformly distributed direction of unit length.
+    ///
+    /// Rejection sampling keeps this free of trigonometry, whose results are
+    /// not guaranteed to match bit for bit between platforms.
+    pub fn random_unit<R: Rng + ?Sized>(rng: &mut R) -> Vec2 {
+        loop {
+            let v = Vec2::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0));
+            let length_squared = v.length_squared();
+            if length_squared > 1e-6 && length_squared <= 1.0 {
+                return v * (1.0 / length_squared.sqrt());
+            }
+        }
+    }
+}
+
+/// An axis-aligned rectangle with its top-left corner at `(x, y)`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn min(&self) -> Vec2 {
+        Vec2::new(self.x, self.y)
+    }
+
+    pub fn max(&self) -> Vec2 {
+        Vec2::new(self.x + self.width, self.y + self.height)
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        let (min, max) = (self.min(), self.max());
+        point.x >= min.x && point.x <= max.x && point.y >= min.y && point.y <= max.y
+    }
+
+    /// Whether the two rectangles share any area; touching edges do not count.
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        let (a_min, a_max) = (self.min(), self.max());
+        let (b_min, b_max) = (other.min(), other.max());
+        a_min.x < b_max.x && b_min.x < a_max.x && a_min.y < b_max.y && b_min.y < a_max.y
+    }
+
+    pub fn random_point<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec2 {
+        let (min, max) = (self.min(), self.max());
+        Vec2::new(rng.gen_range(min.x, max.x), rng.gen_range(min.y, max.y))
+    }
+}
+
+impl Add for Vec2 {
+    type Output = Vec2;
+
+    fn add(self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl AddAssign for Vec2 {
+    fn add_assign(&mut self, other: Vec2) {
+        self.x += other.x;
+        self.y += other.y;
+    }
+}
+
+impl Sub for Vec2 {
+    type Output = Vec2;
+
+    fn sub(self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Mul<f32> for Vec2 {
+    type Output = Vec2;
+
+    fn mul(self, scale: f32) -> Vec2 {
+        Vec2::new(self.x * scale, self.y * scale)
+    }
+}
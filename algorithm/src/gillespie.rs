@@ -0,0 +1,243 @@
+//! Exact stochastic simulation of compartment models with Gillespie's direct
+//! method.
+//!
+//! Unlike the deterministic solvers, every infection and recovery is an
+//! individual random event, so small populations show the variance between
+//! outbreaks and the chance of early extinction.
+
+use rand::Rng;
+
+use crate::rng::{self, RngCore};
+use crate::seir::{SeirModel, SeirSample};
+use crate::sir::{SirModel, SirSample};
+use crate::solver::OdeModel;
+use crate::trajectory::Trajectory;
+
+/// A compartment model described as a set of reactions, each moving one
+/// person between compartments.
+pub trait Reactions {
+    type Sample;
+
+    fn initial_time(&self) -> f64;
+
+    /// Head counts per compartment at `initial_time`.
+    fn initial_counts(&self) -> Vec<u64>;
+
+    fn reaction_count(&self) -> usize;
+
+    /// Writes the rate at which each reaction currently happens into `rates`.
+    fn propensities(&self, counts: &[u64], rates: &mut [f64]);
+
+    /// Updates `counts` for one occurrence of `reaction`.
+    fn apply(&self, reaction: usize, counts: &mut [u64]);
+
+    fn sample(&self, t: f64, counts: &[u64]) -> Self::Sample;
+}
+
+/// Simulates `model` until no reaction can happen or time `t_end` is reached.
+///
+/// The trajectory holds the initial state, the state after every event and
+/// the state at `t_end`, so it can be resampled onto a regular grid with
+/// `Trajectory::resample`.
+pub fn simulate<M: Reactions>(model: &M, t_end: f64, seed: u64) -> Trajectory<M::Sample> {
+    simulate_with_rng(model, t_end, &mut rng::seeded(seed))
+}
+
+/// Like `simulate`, but draws all randomness from `rng`.
+pub fn simulate_with_rng<M: Reactions>(
+    model: &M,
+    t_end: f64,
+    rng: &mut dyn RngCore,
+) -> Trajectory<M::Sample> {
+    let mut t = model.initial_time();
+    let mut counts = model.initial_counts();
+    let mut rates = vec![0.0; model.reaction_count()];
+
+    let mut trajectory = Trajectory::new();
+    trajectory.push(model.sample(t, &counts));
+    loop {
+        model.propensities(&counts, &mut rates);
+        let total: f64 = rates.iter().sum();
+        if total <= 0.0 {
+            break;
+        }
+
+        let wait = rng::exponential(rng, total);
+        if t + wait > t_end {
+            break;
+        }
+        t += wait;
+
+        let mut target = rng.gen::<f64>() * total;
+        let mut reaction = rates.len() - 1;
+        for (n, &rate) in rates.iter().enumerate() {
+            if target < rate {
+                reaction = n;
+                break;
+            }
+            target -= rate;
+        }
+        model.apply(reaction, &mut counts);
+        trajectory.push(model.sample(t, &counts));
+    }
+
+    if t < t_end && t_end.is_finite() {
+        trajectory.push(model.sample(t_end, &counts));
+    }
+    trajectory
+}
+
+fn round(x: f64) -> u64 {
+    x.round() as u64
+}
+
+/// The rate at which people leave each stage of a chain with a total mean
+/// duration of `1 / rate`.
+fn stage_propensities(stages: &[u64], rates: &mut [f64], rate: f64) {
+    let rate = rate * stages.len() as f64;
+    for (stage, propensity) in stages.iter().zip(rates) {
+        *propensity = rate * *stage as f64;
+    }
+}
+
+impl Reactions for SirModel {
+    type Sample = SirSample;
+
+    fn initial_time(&self) -> f64 {
+        self.state().t
+    }
+
+    fn initial_counts(&self) -> Vec<u64> {
+        self.initial_state().into_iter().map(round).collect()
+    }
+
+    /// Infection, then moving on from each infectious stage.
+    fn reaction_count(&self) -> usize {
+        1 + self.infectious_stages()
+    }
+
+    fn propensities(&self, counts: &[u64], rates: &mut [f64]) {
+        let k = self.infectious_stages();
+        let s = counts[0] as f64;
+        let i = counts[1..=k].iter().sum::<u64>() as f64;
+        rates[0] = self.beta() * s * i / self.population();
+        stage_propensities(&counts[1..=k], &mut rates[1..], self.gamma());
+    }
+
+    fn apply(&self, reaction: usize, counts: &mut [u64]) {
+        counts[reaction] -= 1;
+        counts[reaction + 1] += 1;
+    }
+
+    fn sample(&self, t: f64, counts: &[u64]) -> SirSample {
+        let counts: Vec<f64> = counts.iter().map(|&n| n as f64).collect();
+        OdeModel::sample(self, t, &counts)
+    }
+}
+
+impl Reactions for SeirModel {
+    type Sample = SeirSample;
+
+    fn initial_time(&self) -> f64 {
+        self.state().t
+    }
+
+    fn initial_counts(&self) -> Vec<u64> {
+        self.initial_state().into_iter().map(round).collect()
+    }
+
+    /// Exposure, then moving on from each exposed and infectious stage.
+    fn reaction_count(&self) -> usize {
+        1 + self.exposed_stages() + self.infectious_stages()
+    }
+
+    fn propensities(&self, counts: &[u64], rates: &mut [f64]) {
+        let m = self.exposed_stages();
+        let k = self.infectious_stages();
+        let s = counts[0] as f64;
+        let i = counts[m + 1..=m + k].iter().sum::<u64>() as f64;
+        rates[0] = self.beta() * s * i / self.population();
+        stage_propensities(&counts[1..=m], &mut rates[1..=m], self.sigma());
+        stage_propensities(&counts[m + 1..=m + k], &mut rates[m + 1..], self.gamma());
+    }
+
+    fn apply(&self, reaction: usize, counts: &mut [u64]) {
+        counts[reaction] -= 1;
+        counts[reaction + 1] += 1;
+    }
+
+    fn sample(&self, t: f64, counts: &[u64]) -> SeirSample {
+        let counts: Vec<f64> = counts.iter().map(|&n| n as f64).collect();
+        OdeModel::sample(self, t, &counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::daily_times;
+
+    #[test]
+    fn every_event_moves_one_person() {
+        let model = SeirModel::new(200.0, 0.8, 0.3, 0.2, 2.0).unwrap();
+        let trajectory = simulate(&model, 365.0, 11);
+
+        for pair in trajectory.samples().windows(2) {
+            assert!(pair[1].t >= pair[0].t);
+            let moved = (pair[0].s - pair[1].s).abs()
+                + (pair[0].e - pair[1].e).abs()
+                + (pair[0].i - pair[1].i).abs()
+                + (pair[0].r - pair[1].r).abs();
+            assert!(moved == 0.0 || moved == 2.0);
+            assert_eq!(pair[1].s + pair[1].e + pair[1].i + pair[1].r, 200.0);
+        }
+    }
+
+    #[test]
+    fn staged_models_keep_everyone() {
+        let model = SeirModel::new(200.0, 0.8, 0.3, 0.2, 2.0)
+            .unwrap()
+            .with_stages(2, 4)
+            .unwrap();
+        let trajectory = simulate(&model, 365.0, 11);
+        assert_eq!(trajectory.first().unwrap(), &model.state());
+        for sample in &trajectory {
+            assert_eq!(sample.s + sample.e + sample.i + sample.r, 200.0);
+        }
+        assert_eq!(
+            trajectory.last().unwrap().e + trajectory.last().unwrap().i,
+            0.0
+        );
+    }
+
+    #[test]
+    fn same_seed_same_events() {
+        let model = SirModel::new(100.0, 0.6, 0.2, 1.0).unwrap();
+        assert_eq!(simulate(&model, 100.0, 3), simulate(&model, 100.0, 3));
+    }
+
+    #[test]
+    fn small_outbreaks_can_go_extinct() {
+        // With R0 = 1.5 a single case fizzles out about two times in three.
+        let model = SirModel::new(500.0, 0.3, 0.2, 1.0).unwrap();
+        let final_sizes: Vec<f64> = (0..40)
+            .map(|seed| simulate(&model, 1000.0, seed).last().unwrap().r)
+            .collect();
+
+        assert!(final_sizes.iter().any(|&r| r < 10.0));
+        assert!(final_sizes.iter().any(|&r| r > 100.0));
+    }
+
+    #[test]
+    fn resamples_onto_days() {
+        let model = SirModel::new(300.0, 0.5, 0.1, 3.0).unwrap();
+        let daily = simulate(&model, 30.0, 8).resample(&daily_times(0.0, 30));
+
+        assert_eq!(daily.len(), 31);
+        assert_eq!(daily.first().unwrap(), &model.state());
+        for (day, sample) in daily.iter().enumerate() {
+            assert_eq!(sample.t, day as f64);
+            assert_eq!(sample.s + sample.i + sample.r, 300.0);
+        }
+    }
+}
@@ -0,0 +1,451 @@
+//! Contact networks for the network model.
+//!
+//! A `Network` is an undirected graph whose nodes are people and whose edges
+//! are the contacts infections can pass along. It can be generated as an
+//! Erdős–Rényi random graph, a Watts–Strogatz small world, a Barabási–Albert
+//! scale-free graph or a configuration model with a given degree sequence,
+//! or read from a CSV edge list with a `source` and `target` column and an
+//! optional `rate` column overriding the model's transmission rate:
+//!
+//! ```text
+//! source,target,rate
+//! 0,1,0.3
+//! 1,2,
+//! ```
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::error::{self, ParamError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge {
+    pub a: usize,
+    pub b: usize,
+    ///infections per day from an infectious end to a susceptible one, instead
+    ///of the model's `transmission_rate`
+    pub rate: Option<f64>,
+}
+
+impl Edge {
+    /// The end of the edge that is not `node`.
+    pub fn other(&self, node: usize) -> usize {
+        if self.a == node {
+            self.b
+        } else {
+            self.a
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Network {
+    edges: Vec<Edge>,
+    ///indices into `edges` for each node
+    adjacency: Vec<Vec<usize>>,
+}
+
+#[derive(Debug)]
+pub enum NetworkError {
+    Io(io::Error),
+    /// A line of the edge list could not be read.
+    Csv {
+        line: usize,
+        reason: String,
+    },
+    /// A setting or an edge is out of range.
+    Invalid(ParamError),
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::Io(error) => write!(f, "could not read edge list: {}", error),
+            NetworkError::Csv { line, reason } => write!(f, "line {}: {}", line, reason),
+            NetworkError::Invalid(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+impl From<io::Error> for NetworkError {
+    fn from(error: io::Error) -> NetworkError {
+        NetworkError::Io(error)
+    }
+}
+
+impl From<ParamError> for NetworkError {
+    fn from(error: ParamError) -> NetworkError {
+        NetworkError::Invalid(error)
+    }
+}
+
+impl Network {
+    /// A network of `nodes` people without any contacts.
+    pub fn new(nodes: usize) -> Network {
+        Network {
+            edges: Vec::new(),
+            adjacency: vec![Vec::new(); nodes],
+        }
+    }
+
+    /// Connects `a` and `b`, with their own transmission rate if `rate` is
+    /// given. Self-loops are rejected, but the same pair can be connected
+    /// more than once.
+    pub fn add_edge(&mut self, a: usize, b: usize, rate: Option<f64>) -> Result<(), ParamError> {
+        if a >= self.nodes() || b >= self.nodes() {
+            return Err(ParamError::new("edge", "must connect nodes of the network"));
+        }
+        if a == b {
+            return Err(ParamError::new("edge", "must connect two different nodes"));
+        }
+        if let Some(rate) = rate {
+            error::non_negative("edge.rate", rate)?;
+        }
+        self.adjacency[a].push(self.edges.len());
+        self.adjacency[b].push(self.edges.len());
+        self.edges.push(Edge { a, b, rate });
+        Ok(())
+    }
+
+    /// Builds a network from pairs known to be valid, without rates.
+    fn from_pairs(nodes: usize, pairs: &[(usize, usize)]) -> Network {
+        let mut network = Network::new(nodes);
+        for &(a, b) in pairs {
+            network.add_edge(a, b, None).unwrap();
+        }
+        network
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.adjacency.len()
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    pub fn degree(&self, node: usize) -> usize {
+        self.adjacency[node].len()
+    }
+
+    pub fn mean_degree(&self) -> f64 {
+        2.0 * self.edges.len() as f64 / self.nodes() as f64
+    }
+
+    /// The edges of `node`, as indices into `edges`.
+    pub fn edges_of(&self, node: usize) -> &[usize] {
+        &self.adjacency[node]
+    }
+
+    pub fn has_edge(&self, a: usize, b: usize) -> bool {
+        self.adjacency[a]
+            .iter()
+            .any(|&edge| self.edges[edge].other(a) == b)
+    }
+
+    /// Connects every pair of nodes with chance `p`.
+    pub fn erdos_renyi<R: Rng + ?Sized>(
+        nodes: usize,
+        p: f64,
+        rng: &mut R,
+    ) -> Result<Network, ParamError> {
+        error::probability("graph.erdos_renyi.p", p)?;
+        let mut pairs = Vec::new();
+        for a in 0..nodes {
+            for b in a + 1..nodes {
+                if rng.gen::<f64>() < p {
+                    pairs.push((a, b));
+                }
+            }
+        }
+        Ok(Network::from_pairs(nodes, &pairs))
+    }
+
+    /// A ring where everybody knows their `k` nearest neighbours, `k / 2` on
+    /// either side, with each contact rewired to a random node with chance
+    /// `beta`.
+    pub fn watts_strogatz<R: Rng + ?Sized>(
+        nodes: usize,
+        k: usize,
+        beta: f64,
+        rng: &mut R,
+    ) -> Result<Network, ParamError> {
+        if !k.is_multiple_of(2) || k >= nodes {
+            return Err(ParamError::new(
+                "graph.watts_strogatz.k",
+                "must be even and less than the number of nodes",
+            ));
+        }
+        error::probability("graph.watts_strogatz.beta", beta)?;
+
+        let mut neighbours = vec![Vec::new(); nodes];
+        let mut pairs = Vec::with_capacity(nodes * k / 2);
+        for a in 0..nodes {
+            for offset in 1..=k / 2 {
+                let b = (a + offset) % nodes;
+                neighbours[a].push(b);
+                neighbours[b].push(a);
+                pairs.push((a, b));
+            }
+        }
+        for pair in &mut pairs {
+            let (a, b) = *pair;
+            // Someone who already knows everybody has nobody to rewire to.
+            if rng.gen::<f64>() >= beta || neighbours[a].len() + 1 >= nodes {
+                continue;
+            }
+            let c = loop {
+                let c = rng.gen_range(0, nodes);
+                if c != a && !neighbours[a].contains(&c) {
+                    break c;
+                }
+            };
+            neighbours[a].retain(|&n| n != b);
+            neighbours[b].retain(|&n| n != a);
+            neighbours[a].push(c);
+            neighbours[c].push(a);
+            *pair = (a, c);
+        }
+        Ok(Network::from_pairs(nodes, &pairs))
+    }
+
+    /// Grows a network by preferential attachment: starting from `m + 1`
+    /// people who all know each other, every newcomer connects to `m`
+    /// different people picked in proportion to how many contacts they
+    /// already have.
+    pub fn barabasi_albert<R: Rng + ?Sized>(
+        nodes: usize,
+        m: usize,
+        rng: &mut R,
+    ) -> Result<Network, ParamError> {
+        if m == 0 || m >= nodes {
+            return Err(ParamError::new(
+                "graph.barabasi_albert.m",
+                "must be at least one and less than the number of nodes",
+            ));
+        }
+
+        let mut pairs = Vec::new();
+        // Every node appears once for each of its edges, so a uniform draw
+        // from here picks nodes in proportion to their degree.
+        let mut ends = Vec::new();
+        for a in 0..=m {
+            for b in a + 1..=m {
+                pairs.push((a, b));
+                ends.extend_from_slice(&[a, b]);
+            }
+        }
+        let mut targets = Vec::with_capacity(m);
+        for node in m + 1..nodes {
+            targets.clear();
+            while targets.len() < m {
+                let target = ends[rng.gen_range(0, ends.len())];
+                if !targets.contains(&target) {
+                    targets.push(target);
+                }
+            }
+            for &target in &targets {
+                pairs.push((target, node));
+                ends.extend_from_slice(&[target, node]);
+            }
+        }
+        Ok(Network::from_pairs(nodes, &pairs))
+    }
+
+    /// Pairs up the ends of everybody's contacts at random, so that node `n`
+    /// has `degrees[n]` contacts. Self-loops and repeated pairs are dropped,
+    /// which leaves a few nodes short of their degree.
+    pub fn configuration<R: Rng + ?Sized>(
+        degrees: &[usize],
+        rng: &mut R,
+    ) -> Result<Network, ParamError> {
+        if !degrees.iter().sum::<usize>().is_multiple_of(2) {
+            return Err(ParamError::new(
+                "graph.configuration",
+                "the degrees must add up to an even number",
+            ));
+        }
+        let mut stubs: Vec<usize> = degrees
+            .iter()
+            .enumerate()
+            .flat_map(|(node, &degree)| std::iter::repeat_n(node, degree))
+            .collect();
+        stubs.shuffle(rng);
+
+        let mut network = Network::new(degrees.len());
+        for pair in stubs.chunks(2) {
+            let (a, b) = (pair[0], pair[1]);
+            if a != b && !network.has_edge(a, b) {
+                network.add_edge(a, b, None).unwrap();
+            }
+        }
+        Ok(network)
+    }
+
+    /// Reads an edge list with a header naming the `source` and `target`
+    /// columns, and optionally a `rate` column where blank cells fall back to
+    /// the model's rate. Nodes are numbered from 0, and the network has
+    /// `nodes` of them, or just enough for the highest one listed.
+    pub fn from_csv<R: BufRead>(reader: R, nodes: Option<usize>) -> Result<Network, NetworkError> {
+        let mut columns = None;
+        let mut edges = Vec::new();
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let csv = |reason: String| NetworkError::Csv {
+                line: n + 1,
+                reason,
+            };
+
+            let (source, target, rate) = match columns {
+                Some(columns) => columns,
+                None => {
+                    let find = |name: &str| fields.iter().position(|&f| f == name);
+                    let source = find("source")
+                        .ok_or_else(|| csv("the header has no `source` column".into()))?;
+                    let target = find("target")
+                        .ok_or_else(|| csv("the header has no `target` column".into()))?;
+                    columns = Some((source, target, find("rate")));
+                    continue;
+                }
+            };
+            let field = |column: usize| fields.get(column).copied().unwrap_or_default();
+            let node = |column: usize| {
+                field(column)
+                    .parse::<usize>()
+                    .map_err(|_| csv(format!("`{}` is not a node", field(column))))
+            };
+            let rate = match rate.map(field) {
+                None | Some("") => None,
+                Some(rate) => Some(
+                    rate.parse::<f64>()
+                        .map_err(|_| csv(format!("`{}` is not a number", rate)))?,
+                ),
+            };
+            edges.push((n + 1, node(source)?, node(target)?, rate));
+        }
+
+        let highest = edges.iter().map(|&(_, a, b, _)| a.max(b) + 1).max();
+        let mut network = Network::new(nodes.or(highest).unwrap_or_default());
+        for (line, a, b, rate) in edges {
+            network
+                .add_edge(a, b, rate)
+                .map_err(|error| NetworkError::Csv {
+                    line,
+                    reason: error.to_string(),
+                })?;
+        }
+        Ok(network)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, nodes: Option<usize>) -> Result<Network, NetworkError> {
+        Network::from_csv(BufReader::new(File::open(path)?), nodes)
+    }
+
+    /// Writes the edge list in the format `from_csv` reads.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "source,target,rate")?;
+        for edge in &self.edges {
+            match edge.rate {
+                Some(rate) => writeln!(writer, "{},{},{}", edge.a, edge.b, rate)?,
+                None => writeln!(writer, "{},{},", edge.a, edge.b)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::seeded;
+
+    #[test]
+    fn generates_graphs_with_the_expected_degrees() {
+        let mut rng = seeded(1);
+        let er = Network::erdos_renyi(1000, 0.01, &mut rng).unwrap();
+        assert!(
+            (er.mean_degree() - 9.99).abs() < 0.5,
+            "{}",
+            er.mean_degree()
+        );
+
+        let ring = Network::watts_strogatz(100, 4, 0.0, &mut rng).unwrap();
+        assert!((0..100).all(|node| ring.degree(node) == 4));
+        assert!(ring.has_edge(0, 98) && ring.has_edge(0, 2) && !ring.has_edge(0, 3));
+        let small_world = Network::watts_strogatz(1000, 6, 0.2, &mut rng).unwrap();
+        assert_eq!(small_world.edges().len(), 3000);
+        assert!(small_world
+            .edges()
+            .iter()
+            .all(|edge| edge.a != edge.b && small_world.has_edge(edge.b, edge.a)));
+
+        let ba = Network::barabasi_albert(2000, 2, &mut rng).unwrap();
+        assert_eq!(ba.edges().len(), 3 + 2 * (2000 - 3));
+        let hub = (0..2000).map(|node| ba.degree(node)).max().unwrap();
+        assert!(hub > 40, "{}", hub);
+        assert!((0..2000).all(|node| ba.degree(node) >= 2));
+
+        let degrees: Vec<usize> = (0..1000).map(|n| 1 + n % 5).collect();
+        let configuration = Network::configuration(&degrees, &mut rng).unwrap();
+        let lost: usize = (0..1000)
+            .map(|node| degrees[node] - configuration.degree(node))
+            .sum();
+        assert!(lost < 30, "{}", lost);
+
+        assert!(Network::watts_strogatz(10, 3, 0.1, &mut rng).is_err());
+        assert!(Network::barabasi_albert(10, 0, &mut rng).is_err());
+        assert!(Network::configuration(&[1, 2], &mut rng).is_err());
+    }
+
+    #[test]
+    fn reads_and_writes_edge_lists() {
+        let text = "# contacts\nsource,target,rate\n0,1,0.5\n\n1,3,\n";
+        let network = Network::from_csv(text.as_bytes(), None).unwrap();
+        assert_eq!(network.nodes(), 4);
+        assert_eq!(
+            network.edges(),
+            [
+                Edge {
+                    a: 0,
+                    b: 1,
+                    rate: Some(0.5)
+                },
+                Edge {
+                    a: 1,
+                    b: 3,
+                    rate: None
+                }
+            ]
+        );
+        assert_eq!(network.degree(2), 0);
+
+        let mut buffer = Vec::new();
+        network.write_csv(&mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        assert_eq!(text, "source,target,rate\n0,1,0.5\n1,3,\n");
+        let copy = Network::from_csv(text.as_bytes(), Some(10)).unwrap();
+        assert_eq!(copy.nodes(), 10);
+        assert_eq!(copy.edges(), network.edges());
+
+        let line = |text: &str| match Network::from_csv(text.as_bytes(), Some(3)) {
+            Err(NetworkError::Csv { line, .. }) => line,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(line("a,b\n0,1\n"), 1);
+        assert_eq!(line("source,target\n0,1\n1,x\n"), 3);
+        assert_eq!(line("source,target\n0,1\n1,1\n"), 3);
+        assert_eq!(line("source,target\n0,5\n"), 2);
+    }
+}
@@ -0,0 +1,266 @@
+//! Households in the agent-based model.
+//!
+//! Everybody can belong to a household with a home they keep returning to.
+//! Members of a household infect each other with their own daily
+//! probability wherever they are, standing in for the close contact of living
+//! together, on top of the transmission between anyone who comes close.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{self, ParamError};
+use crate::geometry::{Rect, Vec2};
+use crate::transmission::Setting;
+use crate::world::World;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HouseholdConfig {
+    ///relative frequency of households with 1, 2, 3, ... members
+    pub size_distribution: Vec<f64>,
+    ///chance per day that an infectious member infects each susceptible member
+    pub transmission_probability: f32,
+    ///how far people wander from home before they turn back
+    pub roam_radius: f32,
+}
+
+impl HouseholdConfig {
+    pub fn validate(&self) -> Result<(), ParamError> {
+        for &weight in &self.size_distribution {
+            error::non_negative("households.size_distribution", weight)?;
+        }
+        error::positive(
+            "households.size_distribution",
+            self.size_distribution.iter().sum(),
+        )?;
+        error::probability(
+            "households.transmission_probability",
+            self.transmission_probability.into(),
+        )?;
+        error::non_negative("households.roam_radius", self.roam_radius.into())?;
+        Ok(())
+    }
+
+    /// The expected number of members of a household.
+    pub fn mean_size(&self) -> f64 {
+        let total: f64 = self.size_distribution.iter().sum();
+        self.size_distribution
+            .iter()
+            .enumerate()
+            .map(|(n, weight)| (n + 1) as f64 * weight)
+            .sum::<f64>()
+            / total
+    }
+
+    /// The chance per tick that matches `transmission_probability` per day.
+    pub(crate) fn probability_per_tick(&self, ticks_per_day: u32) -> f64 {
+        let p = f64::from(self.transmission_probability);
+        1.0 - libm::pow(1.0 - p, 1.0 / f64::from(ticks_per_day))
+    }
+
+    /// Splits `population` people, in order, into households with sizes drawn
+    /// from `size_distribution` and homes scattered over `arena`. The last
+    /// household is cut short to fit.
+    pub(crate) fn assign<R: Rng + ?Sized>(
+        &self,
+        population: usize,
+        arena: &Rect,
+        rng: &mut R,
+    ) -> Vec<Household> {
+        let total: f64 = self.size_distribution.iter().sum();
+        let mut households = Vec::new();
+        let mut next = 0;
+        while next < population {
+            let mut u = rng.gen::<f64>() * total;
+            let mut size = self.size_distribution.len();
+            for (n, &weight) in self.size_distribution.iter().enumerate() {
+                if u < weight {
+                    size = n + 1;
+                    break;
+                }
+                u -= weight;
+            }
+            let end = (next + size).min(population);
+            households.push(Household {
+                home: arena.random_point(rng),
+                members: (next..end).collect(),
+            });
+            next = end;
+        }
+        households
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Household {
+    pub home: Vec2,
+    ///indices into `World::people`
+    pub members: Vec<usize>,
+}
+
+impl World {
+    /// The fraction of household members infected at home by a case in
+    /// their household: infections within households over the number of
+    /// other members of every household that had a case. `None` without
+    /// households, or before any household with several members had a case.
+    pub fn household_secondary_attack_rate(&self) -> Option<f64> {
+        let tree = self.transmission_tree();
+        let mut contacts = 0;
+        for household in self.households() {
+            if household
+                .members
+                .iter()
+                .any(|&member| tree.infection_of(member).is_some())
+            {
+                contacts += household.members.len() - 1;
+            }
+        }
+        let secondary = tree
+            .infections()
+            .iter()
+            .filter(|infection| infection.setting == Setting::Household)
+            .count();
+        if contacts == 0 {
+            None
+        } else {
+            Some(secondary as f64 / contacts as f64)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::seeded;
+    use crate::scenario::{Model, Scenario};
+    use crate::world::WorldConfig;
+    use crate::Status;
+
+    fn config() -> HouseholdConfig {
+        HouseholdConfig {
+            size_distribution: vec![0.0, 1.0, 0.0, 1.0],
+            transmission_probability: 0.3,
+            roam_radius: 50.0,
+        }
+    }
+
+    #[test]
+    fn splits_everyone_into_households() {
+        let config = config();
+        assert_eq!(config.mean_size(), 3.0);
+
+        let arena = Rect::new(0.0, 0.0, 100.0, 100.0);
+        let households = config.assign(1001, &arena, &mut seeded(1));
+        let members: Vec<usize> = households
+            .iter()
+            .flat_map(|household| household.members.iter().copied())
+            .collect();
+        assert_eq!(members, (0..1001).collect::<Vec<_>>());
+        let (last, full) = households.split_last().unwrap();
+        assert!(full
+            .iter()
+            .all(|household| [2, 4].contains(&household.members.len())));
+        assert!(last.members.len() <= 4);
+        let mean = 1001.0 / households.len() as f64;
+        assert!((mean - 3.0).abs() < 0.2, "{}", mean);
+
+        let invalid = HouseholdConfig {
+            size_distribution: vec![0.0, 0.0],
+            ..config
+        };
+        assert_eq!(
+            invalid.validate().unwrap_err().field,
+            "households.size_distribution"
+        );
+    }
+
+    #[test]
+    fn infects_within_households() {
+        let config = WorldConfig {
+            households: Some(config()),
+            ..WorldConfig::default()
+        };
+        let mut world = World::new(config, 2).unwrap();
+        for (h, household) in world.households().iter().enumerate() {
+            for &member in &household.members {
+                assert_eq!(world.people()[member].household(), Some(h));
+                assert_eq!(world.people()[member].position(), household.home);
+            }
+        }
+        world.run(30);
+
+        let at_home = world
+            .transmission_tree()
+            .infections()
+            .iter()
+            .filter(|infection| infection.setting == Setting::Household)
+            .count();
+        assert!(at_home > 0);
+        let rate = world.household_secondary_attack_rate().unwrap();
+        assert!(rate > 0.0 && rate <= 1.0, "{}", rate);
+        assert_eq!(
+            World::new(WorldConfig::default(), 2)
+                .unwrap()
+                .household_secondary_attack_rate(),
+            None
+        );
+    }
+
+    #[test]
+    fn initial_cases_are_spread_over_households() {
+        let config = WorldConfig {
+            initial_infected: 10,
+            households: Some(config()),
+            ..WorldConfig::default()
+        };
+        let world = World::new(config, 4).unwrap();
+        let mut homes: Vec<usize> = world
+            .people()
+            .iter()
+            .filter(|person| person.status() == Status::Infectious)
+            .map(|person| person.household().unwrap())
+            .collect();
+        assert_eq!(homes.len(), 10);
+        homes.dedup();
+        assert!(homes.len() > 5, "{:?}", homes);
+    }
+
+    #[test]
+    fn people_stay_near_home() {
+        let config = WorldConfig {
+            households: Some(config()),
+            ..WorldConfig::default()
+        };
+        let mut world = World::new(config, 3).unwrap();
+        for _ in 0..500 {
+            world.step();
+        }
+        for person in world.people() {
+            let home = world.households()[person.household().unwrap()].home;
+            let step = person.max_speed() / world.config().ticks_per_day as f32;
+            assert!(person.position().distance_squared(home) <= (50.0 + step).powi(2));
+        }
+    }
+
+    #[test]
+    fn reads_households_from_scenarios() {
+        let scenario = Scenario::from_toml(
+            r#"
+            days = 30
+            [model.world.households]
+            size_distribution = [0.3, 0.35, 0.15, 0.15, 0.05]
+            transmission_probability = 0.1
+            roam_radius = 150
+            "#,
+        )
+        .unwrap();
+        match scenario.model {
+            Model::World(config) => {
+                let households = config.households.unwrap();
+                assert!((households.mean_size() - 2.3).abs() < 1e-12);
+                assert_eq!(households.roam_radius, 150.0);
+            }
+            model => panic!("{:?}", model),
+        }
+    }
+}
@@ -0,0 +1,613 @@
+//! Interventions that switch on and off during a run.
+//!
+//! A `Schedule` holds a list of interventions, each pairing a `Trigger` with
+//! an `Action`. It is updated as the model advances, switching interventions
+//! on at fixed days or when a metric crosses a threshold, and logs every
+//! change so it can be annotated on a chart.
+
+use serde::{Deserialize, Serialize};
+
+use crate::age::{AgeSeirModel, AgeSeirSample};
+use crate::error::ParamError;
+use crate::location::{Closures, LocationKind};
+use crate::network::{NetworkModel, NetworkSample};
+use crate::seir::{SeirModel, SeirSample};
+use crate::sir::{SirModel, SirSample};
+use crate::trajectory::{Sample, Trajectory};
+use crate::world::{World, WorldSample};
+
+/// A quantity of the model that a threshold trigger watches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Susceptible,
+    Exposed,
+    Infectious,
+    Removed,
+    ///people in the quarantine region
+    Quarantined,
+    ///detected cases waiting for room in quarantine
+    AwaitingQuarantine,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Trigger {
+    /// Active from day `start` until day `end`, or for good without one.
+    Days {
+        start: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        end: Option<f64>,
+    },
+    /// Switches on once `metric` reaches `on` and off again once it falls to
+    /// `off`. Keeping `off` below `on` stops the intervention flapping.
+    Threshold { metric: Metric, on: f64, off: f64 },
+}
+
+/// What an intervention changes while it is active. Once it switches off,
+/// the value returns to what it was before the schedule started.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// `beta` of compartmental models, `transmission_probability` of the
+    /// agent-based world, or `transmission_rate` of the network model.
+    Beta(f64),
+    /// Fraction of people following social distancing.
+    DistancingCompliance(f32),
+    /// Fraction of `max_speed` people following social distancing move at.
+    DistancingMobility(f32),
+    /// Chance per day that an undetected case tests positive.
+    TestingRate(f32),
+    /// Whether detected cases are sent to quarantine.
+    Quarantine(bool),
+    /// Closes every location of one kind, sending its visitors home.
+    Close(LocationKind),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Intervention {
+    pub name: String,
+    pub trigger: Trigger,
+    pub action: Action,
+}
+
+/// A record of an intervention switching on or off.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Firing {
+    pub t: f64,
+    ///index into the schedule's interventions
+    pub intervention: usize,
+    pub name: String,
+    pub active: bool,
+}
+
+/// The settings interventions act on. Models ignore the ones they do not
+/// have, such as distancing in a compartmental model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Levers {
+    pub beta: f64,
+    pub distancing_compliance: f32,
+    pub distancing_mobility: f32,
+    pub testing_rate: f32,
+    pub quarantine: bool,
+    pub closures: Closures,
+}
+
+impl Levers {
+    fn with(mut self, action: Action) -> Levers {
+        match action {
+            Action::Beta(beta) => self.beta = beta,
+            Action::DistancingCompliance(compliance) => self.distancing_compliance = compliance,
+            Action::DistancingMobility(mobility) => self.distancing_mobility = mobility,
+            Action::TestingRate(rate) => self.testing_rate = rate,
+            Action::Quarantine(enabled) => self.quarantine = enabled,
+            Action::Close(kind) => self.closures.set(kind, true),
+        }
+        self
+    }
+}
+
+/// A model that interventions can be applied to.
+pub trait Intervene {
+    fn time(&self) -> f64;
+
+    fn metric(&self, metric: Metric) -> f64;
+
+    fn levers(&self) -> Levers;
+
+    fn set_levers(&mut self, levers: Levers) -> Result<(), ParamError>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    interventions: Vec<Intervention>,
+    ///when each intervention last switched on, if it is active
+    active_since: Vec<Option<f64>>,
+    ///the levers before any intervention was applied
+    baseline: Option<Levers>,
+    log: Vec<Firing>,
+}
+
+impl Schedule {
+    pub fn new(interventions: Vec<Intervention>) -> Schedule {
+        let active_since = vec![None; interventions.len()];
+        Schedule {
+            interventions,
+            active_since,
+            baseline: None,
+            log: Vec::new(),
+        }
+    }
+
+    pub fn interventions(&self) -> &[Intervention] {
+        &self.interventions
+    }
+
+    pub fn is_active(&self, intervention: usize) -> bool {
+        self.active_since[intervention].is_some()
+    }
+
+    /// Every time an intervention switched on or off, in order.
+    pub fn log(&self) -> &[Firing] {
+        &self.log
+    }
+
+    /// Re-evaluates every trigger against the current state of `target` and
+    /// updates its levers to match the active interventions.
+    ///
+    /// When several active interventions pull the same lever, the one that
+    /// switched on last wins.
+    pub fn update<T: Intervene>(&mut self, target: &mut T) -> Result<(), ParamError> {
+        let baseline = *self.baseline.get_or_insert_with(|| target.levers());
+        let t = target.time();
+
+        let mut changed = false;
+        for (n, intervention) in self.interventions.iter().enumerate() {
+            let was_active = self.active_since[n].is_some();
+            let active = match intervention.trigger {
+                Trigger::Days { start, end } => t >= start && !matches!(end, Some(end) if t >= end),
+                Trigger::Threshold { metric, on, off } => {
+                    let value = target.metric(metric);
+                    if was_active {
+                        value > off
+                    } else {
+                        value >= on
+                    }
+                }
+            };
+            if active != was_active {
+                self.active_since[n] = if active { Some(t) } else { None };
+                self.log.push(Firing {
+                    t,
+                    intervention: n,
+                    name: intervention.name.clone(),
+                    active,
+                });
+                changed = true;
+            }
+        }
+        if !changed {
+            return Ok(());
+        }
+        target.set_levers(self.levers(baseline))
+    }
+
+    /// Changes the levers interventions are applied on top of, such as when
+    /// the user moves a slider mid-run, and updates `target` to match.
+    ///
+    /// Active interventions keep overriding the levers they pull, and the
+    /// adjusted value takes over again once they switch off.
+    pub fn adjust_baseline<T, F>(&mut self, target: &mut T, adjust: F) -> Result<(), ParamError>
+    where
+        T: Intervene,
+        F: FnOnce(&mut Levers),
+    {
+        let baseline = self.baseline.get_or_insert_with(|| target.levers());
+        adjust(baseline);
+        let baseline = *baseline;
+        target.set_levers(self.levers(baseline))
+    }
+
+    /// Applies the active interventions to `baseline` in the order they
+    /// switched on.
+    fn levers(&self, baseline: Levers) -> Levers {
+        let mut order: Vec<(f64, usize)> = self
+            .active_since
+            .iter()
+            .enumerate()
+            .filter_map(|(n, since)| since.map(|since| (since, n)))
+            .collect();
+        order.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        order.into_iter().fold(baseline, |levers, (_, n)| {
+            levers.with(self.interventions[n].action)
+        })
+    }
+}
+
+impl Intervene for World {
+    fn time(&self) -> f64 {
+        World::time(self)
+    }
+
+    fn metric(&self, metric: Metric) -> f64 {
+        let WorldSample {
+            susceptible,
+            exposed,
+            infectious,
+            removed,
+            quarantined,
+            awaiting_quarantine,
+            ..
+        } = self.sample();
+        let value = match metric {
+            Metric::Susceptible => susceptible,
+            Metric::Exposed => exposed,
+            Metric::Infectious => infectious,
+            Metric::Removed => removed,
+            Metric::Quarantined => quarantined,
+            Metric::AwaitingQuarantine => awaiting_quarantine,
+        };
+        value as f64
+    }
+
+    fn levers(&self) -> Levers {
+        let config = self.config();
+        Levers {
+            beta: config.transmission_probability.into(),
+            distancing_compliance: config.distancing_compliance,
+            distancing_mobility: config.distancing_mobility,
+            testing_rate: config.quarantine.as_ref().map_or(0.0, |q| q.testing_rate),
+            quarantine: self.is_quarantine_enabled(),
+            closures: self.closures(),
+        }
+    }
+
+    fn set_levers(&mut self, levers: Levers) -> Result<(), ParamError> {
+        self.set_transmission_probability(levers.beta as f32)?;
+        self.set_distancing_compliance(levers.distancing_compliance)?;
+        self.set_distancing_mobility(levers.distancing_mobility)?;
+        self.set_testing_rate(levers.testing_rate)?;
+        self.set_quarantine_enabled(levers.quarantine);
+        self.set_closures(levers.closures)?;
+        Ok(())
+    }
+}
+
+impl Intervene for NetworkModel {
+    fn time(&self) -> f64 {
+        NetworkModel::time(self)
+    }
+
+    fn metric(&self, metric: Metric) -> f64 {
+        let sample = self.sample();
+        let value = match metric {
+            Metric::Susceptible => sample.susceptible,
+            Metric::Exposed => sample.exposed,
+            Metric::Infectious => sample.infectious,
+            Metric::Removed => sample.removed,
+            Metric::Quarantined | Metric::AwaitingQuarantine => 0,
+        };
+        value as f64
+    }
+
+    fn levers(&self) -> Levers {
+        Levers {
+            beta: self.config().transmission_rate,
+            distancing_compliance: 0.0,
+            distancing_mobility: 0.0,
+            testing_rate: 0.0,
+            quarantine: false,
+            closures: Closures::default(),
+        }
+    }
+
+    // Scenarios reject the other actions for network models.
+    fn set_levers(&mut self, levers: Levers) -> Result<(), ParamError> {
+        self.set_transmission_rate(levers.beta)
+    }
+}
+
+impl NetworkModel {
+    /// Like `run`, but updates `schedule` every tick.
+    pub fn run_scheduled(
+        &mut self,
+        days: usize,
+        schedule: &mut Schedule,
+    ) -> Result<Trajectory<NetworkSample>, ParamError> {
+        schedule.update(self)?;
+        let mut trajectory = Trajectory::with_capacity(days + 1);
+        trajectory.push(self.sample());
+        for _ in 0..days {
+            for _ in 0..self.config().ticks_per_day {
+                self.step();
+                schedule.update(self)?;
+            }
+            trajectory.push(self.sample());
+        }
+        Ok(trajectory)
+    }
+}
+
+impl World {
+    /// Like `run`, but updates `schedule` every tick.
+    pub fn run_scheduled(
+        &mut self,
+        days: usize,
+        schedule: &mut Schedule,
+    ) -> Result<Trajectory<WorldSample>, ParamError> {
+        schedule.update(self)?;
+        let mut trajectory = Trajectory::with_capacity(days + 1);
+        trajectory.push(self.sample());
+        for _ in 0..days {
+            for _ in 0..self.config().ticks_per_day {
+                self.step();
+                schedule.update(self)?;
+            }
+            trajectory.push(self.sample());
+        }
+        Ok(trajectory)
+    }
+}
+
+impl Intervene for AgeSeirModel {
+    fn time(&self) -> f64 {
+        self.state().t
+    }
+
+    fn metric(&self, metric: Metric) -> f64 {
+        let total = self.state().total();
+        match metric {
+            Metric::Susceptible => total.s + total.v,
+            Metric::Exposed => total.e,
+            Metric::Infectious => total.infectious(),
+            Metric::Removed => total.removed(),
+            Metric::Quarantined | Metric::AwaitingQuarantine => 0.0,
+        }
+    }
+
+    fn levers(&self) -> Levers {
+        Levers {
+            beta: self.beta(),
+            distancing_compliance: 0.0,
+            distancing_mobility: 0.0,
+            testing_rate: 0.0,
+            quarantine: false,
+            closures: Closures::default(),
+        }
+    }
+
+    fn set_levers(&mut self, levers: Levers) -> Result<(), ParamError> {
+        self.set_beta(levers.beta)
+    }
+}
+
+impl AgeSeirModel {
+    /// Like `run`, but updates `schedule` at the start of every day.
+    pub fn run_scheduled(
+        &mut self,
+        days: usize,
+        schedule: &mut Schedule,
+    ) -> Result<Trajectory<AgeSeirSample>, ParamError> {
+        let mut trajectory = Trajectory::with_capacity(days + 1);
+        trajectory.push(self.state());
+        for _ in 0..days {
+            schedule.update(self)?;
+            trajectory.push(self.step());
+        }
+        Ok(trajectory)
+    }
+}
+
+macro_rules! compartmental {
+    ($model:ident, $sample:ident, $exposed:expr) => {
+        impl Intervene for $model {
+            fn time(&self) -> f64 {
+                self.state().t
+            }
+
+            fn metric(&self, metric: Metric) -> f64 {
+                let state = self.state();
+                match metric {
+                    Metric::Susceptible => state.s,
+                    Metric::Exposed => $exposed(&state),
+                    Metric::Infectious => state.i,
+                    Metric::Removed => state.r,
+                    Metric::Quarantined | Metric::AwaitingQuarantine => 0.0,
+                }
+            }
+
+            fn levers(&self) -> Levers {
+                Levers {
+                    beta: self.beta(),
+                    distancing_compliance: 0.0,
+                    distancing_mobility: 0.0,
+                    testing_rate: 0.0,
+                    quarantine: false,
+                    closures: Closures::default(),
+                }
+            }
+
+            fn set_levers(&mut self, levers: Levers) -> Result<(), ParamError> {
+                self.set_beta(levers.beta)
+            }
+        }
+
+        impl $model {
+            /// Like `run`, but updates `schedule` at the start of every day.
+            pub fn run_scheduled(
+                &mut self,
+                days: usize,
+                schedule: &mut Schedule,
+            ) -> Result<Trajectory<$sample>, ParamError> {
+                let mut trajectory = Trajectory::with_capacity(days + 1);
+                trajectory.push(self.state());
+                for _ in 0..days {
+                    schedule.update(self)?;
+                    trajectory.push(self.step());
+                }
+                Ok(trajectory)
+            }
+        }
+    };
+}
+
+compartmental!(SirModel, SirSample, |_: &SirSample| 0.0);
+compartmental!(SeirModel, SeirSample, |state: &SeirSample| state.e);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Rect;
+    use crate::quarantine::QuarantineConfig;
+    use crate::world::WorldConfig;
+
+    fn lockdown(trigger: Trigger) -> Schedule {
+        Schedule::new(vec![Intervention {
+            name: "lockdown".to_string(),
+            trigger,
+            action: Action::Beta(0.1),
+        }])
+    }
+
+    #[test]
+    fn fixed_days_switch_on_and_off() {
+        let mut model = SirModel::new(1000.0, 0.5, 0.2, 1.0).unwrap();
+        let mut schedule = lockdown(Trigger::Days {
+            start: 10.0,
+            end: Some(20.0),
+        });
+        model.run_scheduled(30, &mut schedule).unwrap();
+
+        let log: Vec<(f64, bool)> = schedule.log().iter().map(|f| (f.t, f.active)).collect();
+        assert_eq!(log, [(10.0, true), (20.0, false)]);
+        assert_eq!(model.beta(), 0.5);
+    }
+
+    #[test]
+    fn thresholds_have_hysteresis() {
+        let mut model = SirModel::new(10_000.0, 0.6, 0.2, 1.0).unwrap();
+        let mut schedule = lockdown(Trigger::Threshold {
+            metric: Metric::Infectious,
+            on: 200.0,
+            off: 50.0,
+        });
+        let trajectory = model.run_scheduled(300, &mut schedule).unwrap();
+
+        let log = schedule.log();
+        assert!(log.len() >= 2);
+        for (n, firing) in log.iter().enumerate() {
+            assert_eq!(firing.active, n % 2 == 0);
+            let i = trajectory.samples()[firing.t as usize].i;
+            if firing.active {
+                assert!(i >= 200.0);
+            } else {
+                assert!(i <= 50.0);
+            }
+        }
+    }
+
+    #[test]
+    fn latest_intervention_wins() {
+        let mut model = SeirModel::new(1000.0, 0.5, 0.3, 0.2, 1.0).unwrap();
+        let mut schedule = Schedule::new(vec![
+            Intervention {
+                name: "distancing".to_string(),
+                trigger: Trigger::Days {
+                    start: 5.0,
+                    end: None,
+                },
+                action: Action::Beta(0.3),
+            },
+            Intervention {
+                name: "lockdown".to_string(),
+                trigger: Trigger::Days {
+                    start: 10.0,
+                    end: Some(15.0),
+                },
+                action: Action::Beta(0.1),
+            },
+        ]);
+
+        let mut betas = Vec::new();
+        for _ in 0..20 {
+            schedule.update(&mut model).unwrap();
+            betas.push(model.beta());
+            model.step();
+        }
+        assert_eq!(betas[4], 0.5);
+        assert_eq!(betas[5], 0.3);
+        assert_eq!(betas[12], 0.1);
+        assert_eq!(betas[19], 0.3);
+    }
+
+    #[test]
+    fn adjusted_baseline_survives_interventions() {
+        let mut world = World::new(WorldConfig::default(), 3).unwrap();
+        let mut schedule = Schedule::new(vec![Intervention {
+            name: "stay home".to_string(),
+            trigger: Trigger::Days {
+                start: 2.0,
+                end: Some(4.0),
+            },
+            action: Action::DistancingCompliance(0.75),
+        }]);
+        schedule
+            .adjust_baseline(&mut world, |levers| levers.distancing_compliance = 0.25)
+            .unwrap();
+        assert_eq!(world.config().distancing_compliance, 0.25);
+
+        world.run_scheduled(3, &mut schedule).unwrap();
+        assert_eq!(world.config().distancing_compliance, 0.75);
+        world.run_scheduled(2, &mut schedule).unwrap();
+        assert_eq!(world.config().distancing_compliance, 0.25);
+    }
+
+    #[test]
+    fn world_levers() {
+        let config = WorldConfig {
+            initial_infected: 20,
+            quarantine: Some(QuarantineConfig {
+                region: Rect::new(820.0, 0.0, 100.0, 100.0),
+                capacity: 100,
+                detection_delay_days: 1.0,
+                testing_rate: 0.0,
+            }),
+            ..WorldConfig::default()
+        };
+        let mut world = World::new(config, 3).unwrap();
+        let mut schedule = Schedule::new(vec![
+            Intervention {
+                name: "stay home".to_string(),
+                trigger: Trigger::Days {
+                    start: 2.0,
+                    end: None,
+                },
+                action: Action::DistancingCompliance(0.75),
+            },
+            Intervention {
+                name: "mass testing".to_string(),
+                trigger: Trigger::Threshold {
+                    metric: Metric::Infectious,
+                    on: 25.0,
+                    off: 0.0,
+                },
+                action: Action::TestingRate(0.5),
+            },
+            Intervention {
+                name: "no quarantine".to_string(),
+                trigger: Trigger::Days {
+                    start: 30.0,
+                    end: None,
+                },
+                action: Action::Quarantine(false),
+            },
+        ]);
+        world.run_scheduled(40, &mut schedule).unwrap();
+
+        assert_eq!(world.config().distancing_compliance, 0.75);
+        assert!(!world.is_quarantine_enabled());
+        assert_eq!(world.sample().quarantined, 0);
+        assert!(schedule.log().iter().any(|f| f.name == "mass testing"));
+    }
+}
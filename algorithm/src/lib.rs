@@ -1,4 +1,60 @@
-#[derive(Debug)]
+pub mod age;
+pub mod calibration;
+pub mod distribution;
+pub mod ensemble;
+pub mod error;
+pub mod export;
+pub mod geometry;
+pub mod gillespie;
+pub mod graph;
+pub mod household;
+pub mod intervention;
+pub mod location;
+pub mod network;
+pub mod quarantine;
+pub mod rng;
+pub mod rt;
+pub mod scenario;
+pub mod seir;
+pub mod sir;
+pub mod solver;
+pub mod spatial;
+pub mod sweep;
+pub mod trajectory;
+pub mod transmission;
+pub mod vaccination;
+pub mod world;
+
+pub use age::{
+    AgeBand, AgeSeirModel, AgeSeirSample, BandSample, ContactMatrix, ContactMatrixError,
+};
+pub use calibration::{Calibration, Compartments, Fit, Incidence, Objective};
+pub use distribution::Distribution;
+pub use ensemble::{Ensemble, EnsembleResult, OutcomeDistribution, Summary};
+pub use error::ParamError;
+pub use export::{Metadata, Parameter};
+pub use geometry::{Rect, Vec2};
+pub use graph::{Edge, Network, NetworkError};
+pub use household::{Household, HouseholdConfig};
+pub use intervention::{Action, Intervention, Metric, Schedule, Trigger};
+pub use location::{Closures, Location, LocationKind, LocationsConfig, SiteConfig};
+pub use network::{GraphConfig, NetworkConfig, NetworkModel, NetworkSample};
+pub use quarantine::QuarantineConfig;
+pub use rt::{Cori, RtEstimate, SerialInterval, TrueRt};
+pub use scenario::{Scenario, ScenarioError};
+pub use seir::{SeirModel, SeirSample};
+pub use sir::{SirModel, SirSample};
+pub use solver::{OdeModel, SolveError, Solver};
+pub use sweep::{Sweep, SweepTable};
+pub use trajectory::{Sample, Trajectory};
+pub use transmission::{Infection, Setting, TransmissionTree};
+pub use vaccination::{AgeGroup, Immunity, Priority, VaccinationConfig};
+pub use world::{World, WorldConfig, WorldSample};
+
+use rand::Rng;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Status {
     ///Not infected
     Susceptible,
@@ -8,17 +64,172 @@ pub enum Status {
     Removed,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Person {
     status: Status,
     infection_radius: f32,
     symptomatic: bool,
     p_symptomatic_on_infection: f32,
     max_speed: f32,
+    ///fraction of `max_speed` this person currently moves at, 0 stays put
+    mobility: f32,
+    position: Vec2,
+    velocity: Vec2,
+    ///time in days at which the current status was entered
+    status_since: f64,
+    ///days this person stays exposed once infected
+    incubation_days: f64,
+    ///days this person stays infectious
+    infectious_days: f64,
+    ///found to be infected, by symptoms or by a test
+    detected: bool,
+    ///isolated in the quarantine region
+    quarantined: bool,
+    ///index into `World::households`
+    household: Option<usize>,
+    ///age in years, only drawn when the world has `age_groups`
+    age: f32,
+    high_risk: bool,
+    immunity: Immunity,
+}
+
+impl Person {
+    /// Creates a susceptible, stationary person at `position`.
+    pub fn new(
+        infection_radius: f32,
+        p_symptomatic_on_infection: f32,
+        max_speed: f32,
+        position: Vec2,
+    ) -> Person {
+        Person {
+            status: Status::Susceptible,
+            infection_radius,
+            symptomatic: false,
+            p_symptomatic_on_infection,
+            max_speed,
+            mobility: 1.0,
+            position,
+            velocity: Vec2::ZERO,
+            status_since: 0.0,
+            incubation_days: 0.0,
+            infectious_days: 0.0,
+            detected: false,
+            quarantined: false,
+            household: None,
+            age: 0.0,
+            high_risk: false,
+            immunity: Immunity::default(),
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn infection_radius(&self) -> f32 {
+        self.infection_radius
+    }
+
+    /// Whether this person's infection is on the symptomatic branch. Drawn
+    /// when they are infected and kept after they are removed.
+    pub fn is_symptomatic(&self) -> bool {
+        self.symptomatic
+    }
+
+    pub fn p_symptomatic_on_infection(&self) -> f32 {
+        self.p_symptomatic_on_infection
+    }
+
+    pub fn max_speed(&self) -> f32 {
+        self.max_speed
+    }
+
+    pub fn mobility(&self) -> f32 {
+        self.mobility
+    }
+
+    /// Whether this person stays put, and so acts as an immovable obstacle.
+    pub fn is_stationary(&self) -> bool {
+        self.mobility == 0.0
+    }
+
+    pub fn position(&self) -> Vec2 {
+        self.position
+    }
+
+    /// The current velocity, scaled down by `mobility`.
+    pub fn velocity(&self) -> Vec2 {
+        self.velocity * self.mobility
+    }
+
+    pub fn status_since(&self) -> f64 {
+        self.status_since
+    }
+
+    /// How long this person's incubation period lasts, in days. Only
+    /// meaningful once they have been infected.
+    pub fn incubation_days(&self) -> f64 {
+        self.incubation_days
+    }
+
+    /// How long this person stays infectious, in days. Only meaningful once
+    /// they have been infected.
+    pub fn infectious_days(&self) -> f64 {
+        self.infectious_days
+    }
+
+    pub fn is_detected(&self) -> bool {
+        self.detected
+    }
+
+    pub fn is_quarantined(&self) -> bool {
+        self.quarantined
+    }
+
+    /// The household this person belongs to, as an index into
+    /// `World::households`.
+    pub fn household(&self) -> Option<usize> {
+        self.household
+    }
+
+    pub fn age(&self) -> f32 {
+        self.age
+    }
+
+    pub fn is_high_risk(&self) -> bool {
+        self.high_risk
+    }
+
+    pub fn immunity(&self) -> Immunity {
+        self.immunity
+    }
+
+    /// The chance of being infected by a contact, relative to somebody who
+    /// has not been vaccinated.
+    pub fn susceptibility(&self) -> f32 {
+        1.0 - self.immunity.against_infection
+    }
+
+    fn set_status(&mut self, status: Status, t: f64) {
+        self.status = status;
+        self.status_since = t;
+    }
+
+    /// Moves a susceptible person into `status`, deciding whether the
+    /// infection will be symptomatic from `p_symptomatic_on_infection` and
+    /// their vaccine's protection against symptoms.
+    fn infect<R: Rng + ?Sized>(&mut self, status: Status, t: f64, rng: &mut R) {
+        let p_symptomatic =
+            self.p_symptomatic_on_infection * (1.0 - self.immunity.against_symptoms);
+        self.symptomatic = rng.gen::<f32>() < p_symptomatic;
+        self.set_status(status, t);
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
@@ -26,40 +237,39 @@ mod tests {
 
     #[test]
     fn sim() {
-        const N: f32 = 1000.0;
-        const iter: usize = 50;
-
-        let mut I = 1.0;
-        let mut S = N - I;
-        let mut R = 0.0;
-
         //avg contact per person
         let beta = 1.0;
         //the avg # of recovery per day
         let recovery = 5.0;
-        let gamma = 1.0 / recovery;
-        println!("time, num, group");
-        let mut res = vec![];
-        for _ in 0..iter {
-            let s0 = S as f32;
-            let i0 = I as f32;
-            let r0 = R as f32;
-            S += -beta * (I / N) as f32 * s0;
-            // println!("{}, S", S);
-            I += beta * (I / N) as f32 * s0 - gamma * i0;
-            // println!("{}, I", I);
-            R += gamma * i0;
-            // println!("{}, R", R);
-            res.push((S, I, R));
-        }
-        for (i, (s, _, _)) in res.iter().enumerate() {
-            println!("{}, {}, S", i, s)
-        }
-        for (i, (_, inf, _)) in res.iter().enumerate() {
-            println!("{}, {}, I", i, inf)
-        }
-        for (i, (_, _, r)) in res.iter().enumerate() {
-            println!("{}, {}, R", i, r)
+        let mut model = SirModel::new(1000.0, beta, 1.0 / recovery, 1.0).unwrap();
+
+        let res = model.run(50);
+        let mut csv = Vec::new();
+        export::write_long_csv(&mut csv, &model.metadata(), &res).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("# model: sir"));
+        assert_eq!(lines.next(), Some("# population: 1000"));
+        assert_eq!(lines.next(), Some("# beta: 1"));
+        assert_eq!(lines.next(), Some("# gamma: 0.2"));
+        assert_eq!(lines.next(), Some("t,compartment,value"));
+
+        //rows are grouped by compartment, one per day
+        let rows: Vec<Vec<&str>> = lines.map(|l| l.split(',').collect()).collect();
+        assert_eq!(rows.len(), 3 * 51);
+        for day in 0..=50 {
+            let (s, i, r) = (&rows[day], &rows[51 + day], &rows[102 + day]);
+            assert_eq!((s[1], i[1], r[1]), ("s", "i", "r"));
+            assert!(s[0] == i[0] && i[0] == r[0]);
+            let total: f64 = [s, i, r]
+                .iter()
+                .map(|row| row[2].parse::<f64>().unwrap())
+                .sum();
+            assert!((total - 1000.0).abs() < 1e-6);
         }
+        //with R0 = 5 nearly everybody catches it
+        let recovered: f64 = rows[152][2].parse().unwrap();
+        assert!(recovered > 900.0);
     }
 }
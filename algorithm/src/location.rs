@@ -0,0 +1,582 @@
+//! Places people go during the day.
+//!
+//! Without locations people wander the whole arena at random. With them,
+//! everyone keeps a home and follows a daily schedule: out to their workplace
+//! or school during the day, perhaps on to a market in the evening, and back
+//! home for the night. Visitors inside a location meet each other at its
+//! contact rate wherever they stand, and a full location turns latecomers
+//! away. Interventions can close each kind of location on its own, sending
+//! everyone who would be there home.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{self, ParamError};
+use crate::geometry::{Rect, Vec2};
+use crate::Person;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationKind {
+    Work,
+    School,
+    Market,
+}
+
+impl LocationKind {
+    pub const ALL: [LocationKind; 3] = [
+        LocationKind::Work,
+        LocationKind::School,
+        LocationKind::Market,
+    ];
+
+    /// The name used for this kind in scenario files.
+    pub fn name(self) -> &'static str {
+        match self {
+            LocationKind::Work => "work",
+            LocationKind::School => "school",
+            LocationKind::Market => "market",
+        }
+    }
+}
+
+/// The locations of one kind.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SiteConfig {
+    ///how many are scattered over the arena
+    pub count: usize,
+    ///most visitors each holds at once, anyone beyond that stays home
+    pub capacity: usize,
+    ///people each visitor comes into contact with per day
+    pub contact_rate: f32,
+    ///fraction of people who work or study at one, or for markets the chance
+    ///per day that somebody visits one in the evening
+    pub share: f32,
+}
+
+impl SiteConfig {
+    fn validate(&self, kind: LocationKind) -> Result<(), ParamError> {
+        let [count, capacity, contact_rate, share] = match kind {
+            LocationKind::Work => [
+                "locations.work.count",
+                "locations.work.capacity",
+                "locations.work.contact_rate",
+                "locations.work.share",
+            ],
+            LocationKind::School => [
+                "locations.school.count",
+                "locations.school.capacity",
+                "locations.school.contact_rate",
+                "locations.school.share",
+            ],
+            LocationKind::Market => [
+                "locations.market.count",
+                "locations.market.capacity",
+                "locations.market.contact_rate",
+                "locations.market.share",
+            ],
+        };
+        error::positive(count, self.count as f64)?;
+        error::positive(capacity, self.capacity as f64)?;
+        error::non_negative(contact_rate, self.contact_rate.into())?;
+        error::probability(share, self.share.into())?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LocationsConfig {
+    pub work: Option<SiteConfig>,
+    pub school: Option<SiteConfig>,
+    pub market: Option<SiteConfig>,
+    ///chance that a contact with an infectious visitor infects a susceptible one
+    pub transmission_probability: f32,
+    ///time of day, as a fraction of a day, people set off for work or school
+    pub day_start: f32,
+    ///time of day people leave work and school for a market or home
+    pub day_end: f32,
+    ///time of day markets close and everybody heads home
+    pub evening_end: f32,
+    ///distance units per day people travel between places
+    pub commute_speed: f32,
+    ///how far people wander from the middle of a location, or from home
+    ///without households
+    pub radius: f32,
+}
+
+impl LocationsConfig {
+    pub fn validate(&self) -> Result<(), ParamError> {
+        for &kind in &LocationKind::ALL {
+            if let Some(site) = self.site(kind) {
+                site.validate(kind)?;
+            }
+        }
+        let shares = self.work.as_ref().map_or(0.0, |work| work.share)
+            + self.school.as_ref().map_or(0.0, |school| school.share);
+        if shares > 1.0 {
+            return Err(ParamError::new(
+                "locations.school.share",
+                "must not exceed one together with `work.share`",
+            ));
+        }
+        error::probability(
+            "locations.transmission_probability",
+            self.transmission_probability.into(),
+        )?;
+        error::probability("locations.day_start", self.day_start.into())?;
+        error::probability("locations.day_end", self.day_end.into())?;
+        error::probability("locations.evening_end", self.evening_end.into())?;
+        if self.day_end <= self.day_start {
+            return Err(ParamError::new(
+                "locations.day_end",
+                "must be after `day_start`",
+            ));
+        }
+        if self.evening_end < self.day_end {
+            return Err(ParamError::new(
+                "locations.evening_end",
+                "must not be before `day_end`",
+            ));
+        }
+        error::positive("locations.commute_speed", self.commute_speed.into())?;
+        error::non_negative("locations.radius", self.radius.into())?;
+        Ok(())
+    }
+
+    /// The configuration of the locations of `kind`, if there are any.
+    pub fn site(&self, kind: LocationKind) -> Option<&SiteConfig> {
+        match kind {
+            LocationKind::Work => self.work.as_ref(),
+            LocationKind::School => self.school.as_ref(),
+            LocationKind::Market => self.market.as_ref(),
+        }
+    }
+
+    fn phase(&self, t: f64) -> Phase {
+        let time_of_day = t.fract() as f32;
+        if time_of_day < self.day_start || time_of_day >= self.evening_end {
+            Phase::Night
+        } else if time_of_day < self.day_end {
+            Phase::Day
+        } else {
+            Phase::Evening
+        }
+    }
+}
+
+/// Which kinds of location are closed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Closures {
+    pub work: bool,
+    pub school: bool,
+    pub market: bool,
+}
+
+impl Closures {
+    pub fn is_closed(&self, kind: LocationKind) -> bool {
+        match kind {
+            LocationKind::Work => self.work,
+            LocationKind::School => self.school,
+            LocationKind::Market => self.market,
+        }
+    }
+
+    pub fn set(&mut self, kind: LocationKind, closed: bool) {
+        match kind {
+            LocationKind::Work => self.work = closed,
+            LocationKind::School => self.school = closed,
+            LocationKind::Market => self.market = closed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub kind: LocationKind,
+    pub position: Vec2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Night,
+    Day,
+    Evening,
+}
+
+/// Where everybody lives and where they are headed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Locations {
+    sites: Vec<Location>,
+    homes: Vec<Vec2>,
+    ///each person's workplace or school
+    regular: Vec<Option<usize>>,
+    ///where each person is headed, `None` for home
+    destination: Vec<Option<usize>>,
+    ///whether each person has reached their destination
+    arrived: Vec<bool>,
+    ///people headed to each site
+    visitors: Vec<usize>,
+    phase: Option<Phase>,
+}
+
+impl Locations {
+    /// Scatters the sites over `arena` and hands out workplaces and schools
+    /// to the people living at `homes`, who all start out at home.
+    pub(crate) fn new<R: Rng + ?Sized>(
+        config: &LocationsConfig,
+        arena: &Rect,
+        homes: Vec<Vec2>,
+        rng: &mut R,
+    ) -> Locations {
+        let mut sites = Vec::new();
+        for &kind in &LocationKind::ALL {
+            if let Some(site) = config.site(kind) {
+                for _ in 0..site.count {
+                    sites.push(Location {
+                        kind,
+                        position: arena.random_point(rng),
+                    });
+                }
+            }
+        }
+
+        let mut locations = Locations {
+            visitors: vec![0; sites.len()],
+            sites,
+            regular: Vec::with_capacity(homes.len()),
+            destination: vec![None; homes.len()],
+            arrived: vec![true; homes.len()],
+            homes,
+            phase: None,
+        };
+        let work = config.work.as_ref().map_or(0.0, |work| work.share);
+        let school = work + config.school.as_ref().map_or(0.0, |school| school.share);
+        for _ in 0..locations.homes.len() {
+            let draw = rng.gen::<f32>();
+            let regular = if draw < work {
+                locations.random_site(LocationKind::Work, rng)
+            } else if draw < school {
+                locations.random_site(LocationKind::School, rng)
+            } else {
+                None
+            };
+            locations.regular.push(regular);
+        }
+        locations
+    }
+
+    fn random_site<R: Rng + ?Sized>(&self, kind: LocationKind, rng: &mut R) -> Option<usize> {
+        let first = self.sites.iter().position(|site| site.kind == kind)?;
+        let count = self.sites[first..]
+            .iter()
+            .take_while(|site| site.kind == kind)
+            .count();
+        Some(first + rng.gen_range(0, count))
+    }
+
+    pub(crate) fn sites(&self) -> &[Location] {
+        &self.sites
+    }
+
+    /// The site `person` is at, if they have reached one.
+    pub(crate) fn present(&self, person: usize) -> Option<usize> {
+        match self.destination.get(person) {
+            Some(&Some(site)) if self.arrived[person] => Some(site),
+            _ => None,
+        }
+    }
+
+    /// Sends everybody on their way when a new part of the day starts.
+    /// Nobody new goes to a closed or full location, and quarantined people
+    /// stay where they are. Markets are picked afresh every evening.
+    pub(crate) fn plan<R: Rng + ?Sized>(
+        &mut self,
+        config: &LocationsConfig,
+        t: f64,
+        closures: &Closures,
+        people: &[Person],
+        rng: &mut R,
+    ) {
+        let phase = config.phase(t);
+        if self.phase == Some(phase) {
+            return;
+        }
+        self.phase = Some(phase);
+
+        let market = config
+            .market
+            .as_ref()
+            .filter(|_| !closures.is_closed(LocationKind::Market));
+        for count in &mut self.visitors {
+            *count = 0;
+        }
+        for (i, person) in people.iter().enumerate() {
+            let wanted = match phase {
+                _ if person.quarantined => None,
+                Phase::Night => None,
+                Phase::Day => self.regular[i],
+                Phase::Evening => match market {
+                    Some(market) if rng.gen::<f32>() < market.share => {
+                        self.random_site(LocationKind::Market, rng)
+                    }
+                    _ => None,
+                },
+            };
+            let destination = wanted.filter(|&site| {
+                let kind = self.sites[site].kind;
+                let capacity = config.site(kind).map_or(0, |site| site.capacity);
+                !closures.is_closed(kind) && self.visitors[site] < capacity
+            });
+            if let Some(site) = destination {
+                self.visitors[site] += 1;
+            }
+            self.head_for(i, destination);
+        }
+    }
+
+    /// Sends everybody headed to a location that has just closed home.
+    pub(crate) fn close(&mut self, closures: &Closures) {
+        for i in 0..self.destination.len() {
+            if let Some(site) = self.destination[i] {
+                if closures.is_closed(self.sites[site].kind) {
+                    self.visitors[site] -= 1;
+                    self.head_for(i, None);
+                }
+            }
+        }
+    }
+
+    fn head_for(&mut self, person: usize, destination: Option<usize>) {
+        if self.destination[person] != destination {
+            self.destination[person] = destination;
+            self.arrived[person] = false;
+        }
+    }
+
+    /// Points `person` towards where they are headed, at `commute_speed`
+    /// until they arrive and at their own speed once they are there, turning
+    /// back whenever they wander more than `radius` away.
+    pub(crate) fn steer(
+        &mut self,
+        i: usize,
+        person: &mut Person,
+        config: &LocationsConfig,
+        home_radius: f32,
+        dt: f32,
+    ) {
+        let (target, radius) = match self.destination[i] {
+            Some(site) => (self.sites[site].position, config.radius),
+            None => (self.homes[i], home_radius),
+        };
+        if self.arrived[i] {
+            turn_back(person, target, radius);
+            return;
+        }
+
+        let offset = target - person.position;
+        let distance = offset.length();
+        if distance <= radius {
+            self.arrived[i] = true;
+            let speed = person.velocity.length();
+            if speed > 0.0 {
+                person.velocity = person.velocity * (person.max_speed / speed);
+            }
+        } else {
+            // Stop at the target rather than overshooting it.
+            let speed = config.commute_speed.min(distance / dt);
+            person.velocity = offset * (speed / distance);
+        }
+    }
+}
+
+/// Turns someone who has strayed more than `radius` from `centre` back
+/// towards it, reflecting their velocity like off the inside of a circle.
+pub(crate) fn turn_back(person: &mut Person, centre: Vec2, radius: f32) {
+    let offset = person.position - centre;
+    let distance_squared = offset.length_squared();
+    if distance_squared <= radius * radius || distance_squared == 0.0 {
+        return;
+    }
+    let normal = offset * (1.0 / distance_squared.sqrt());
+    let outward = person.velocity.dot(normal);
+    if outward > 0.0 {
+        person.velocity += normal * (-2.0 * outward);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intervention::{Action, Intervention, Trigger};
+    use crate::scenario::Scenario;
+    use crate::transmission::Setting;
+    use crate::world::{World, WorldConfig};
+
+    fn site(count: usize, capacity: usize, share: f32) -> Option<SiteConfig> {
+        Some(SiteConfig {
+            count,
+            capacity,
+            contact_rate: 10.0,
+            share,
+        })
+    }
+
+    fn town() -> WorldConfig {
+        WorldConfig {
+            initial_infected: 20,
+            locations: Some(LocationsConfig {
+                work: site(4, 100, 0.5),
+                school: site(2, 40, 0.3),
+                market: site(1, 400, 0.5),
+                transmission_probability: 0.1,
+                day_start: 0.3,
+                day_end: 0.7,
+                evening_end: 0.9,
+                commute_speed: 5000.0,
+                radius: 20.0,
+            }),
+            ..WorldConfig::default()
+        }
+    }
+
+    /// How many people are at each kind of location.
+    fn visitors(world: &World) -> [usize; 3] {
+        let mut visitors = [0; 3];
+        for i in 0..world.people().len() {
+            if let Some(site) = world.location_of(i) {
+                let kind = world.locations()[site].kind;
+                visitors[LocationKind::ALL.iter().position(|&k| k == kind).unwrap()] += 1;
+            }
+        }
+        visitors
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        let invalid = |f: fn(&mut LocationsConfig)| {
+            let mut config = town().locations.unwrap();
+            f(&mut config);
+            config.validate().unwrap_err().field
+        };
+        assert_eq!(
+            invalid(|c| c.school.as_mut().unwrap().share = 0.6),
+            "locations.school.share"
+        );
+        assert_eq!(
+            invalid(|c| c.work.as_mut().unwrap().capacity = 0),
+            "locations.work.capacity"
+        );
+        assert_eq!(invalid(|c| c.day_end = 0.2), "locations.day_end");
+        assert_eq!(invalid(|c| c.evening_end = 0.5), "locations.evening_end");
+        assert_eq!(
+            invalid(|c| c.commute_speed = 0.0),
+            "locations.commute_speed"
+        );
+    }
+
+    #[test]
+    fn people_follow_the_schedule() {
+        let mut world = World::new(town(), 1).unwrap();
+        let homes: Vec<Vec2> = world.people().iter().map(Person::position).collect();
+        assert_eq!(world.locations().len(), 7);
+
+        // Midday: workers and pupils are in, up to the schools' capacity.
+        for _ in 0..6 {
+            world.step();
+        }
+        let [work, school, market] = visitors(&world);
+        assert!(work > 150, "{}", work);
+        assert_eq!(school, 80);
+        assert_eq!(market, 0);
+
+        // Evening: some go shopping.
+        for _ in 0..2 {
+            world.step();
+        }
+        let [work, school, market] = visitors(&world);
+        assert_eq!((work, school), (0, 0));
+        assert!(market > 100, "{}", market);
+
+        // Night: everybody is back home.
+        for _ in 0..2 {
+            world.step();
+        }
+        assert_eq!(visitors(&world), [0, 0, 0]);
+        for (person, home) in world.people().iter().zip(&homes) {
+            let step = person.max_speed() / world.config().ticks_per_day as f32;
+            assert!(person.position().distance_squared(*home) <= (20.0 + step).powi(2));
+        }
+        assert!(world
+            .transmission_tree()
+            .infections()
+            .iter()
+            .any(|infection| infection.setting == Setting::Work));
+    }
+
+    #[test]
+    fn closed_locations_send_people_home() {
+        let mut world = World::new(town(), 2).unwrap();
+        for _ in 0..6 {
+            world.step();
+        }
+        assert!(visitors(&world)[1] > 0);
+
+        let closures = Closures {
+            school: true,
+            ..Closures::default()
+        };
+        world.set_closures(closures).unwrap();
+        assert_eq!(visitors(&world)[1], 0);
+        let infections = world.transmission_tree().infections().len();
+        world.run(3);
+        assert_eq!(visitors(&world)[1], 0);
+        assert!(world.transmission_tree().infections()[infections..]
+            .iter()
+            .all(|infection| infection.setting != Setting::School));
+
+        let mut world = World::new(WorldConfig::default(), 2).unwrap();
+        assert_eq!(world.set_closures(closures).unwrap_err().field, "closures");
+    }
+
+    #[test]
+    fn interventions_close_schools() {
+        let scenario = Scenario::from_toml(
+            r#"
+            days = 10
+            [model.world.locations]
+            school = { count = 2, capacity = 100, contact_rate = 20, share = 0.3 }
+            transmission_probability = 0.1
+            day_start = 0.3
+            day_end = 0.7
+            evening_end = 0.9
+            commute_speed = 5000
+            radius = 20
+
+            [[interventions]]
+            name = "close schools"
+            trigger = { days = { start = 2, end = 5 } }
+            action = { close = "school" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            scenario.interventions[0],
+            Intervention {
+                name: "close schools".to_string(),
+                trigger: Trigger::Days {
+                    start: 2.0,
+                    end: Some(5.0)
+                },
+                action: Action::Close(LocationKind::School),
+            }
+        );
+        let run = scenario.run(1).unwrap();
+        assert_eq!(run.firings.len(), 2);
+
+        let mut closing_work = scenario;
+        closing_work.interventions[0].action = Action::Close(LocationKind::Work);
+        assert!(closing_work.validate().is_err());
+    }
+}
@@ -0,0 +1,573 @@
+//! Epidemics on a contact network.
+//!
+//! For comparison with the spatial `World`, people here do not move at all:
+//! they are the nodes of a `Network`, and an infectious person infects each
+//! susceptible contact at the rate of the edge between them. People progress
+//! through `Status::Exposed` and `Status::Infectious` like in the world, one
+//! tick at a time.
+
+use std::fmt;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{self, ParamError};
+use crate::export::Metadata;
+use crate::geometry::Vec2;
+use crate::graph::{Network, NetworkError};
+use crate::rng::{self, ModelRng};
+use crate::trajectory::{Sample, Trajectory};
+use crate::transmission::{Infection, Setting, TransmissionTree};
+use crate::{Person, Status};
+
+/// How the contact network is built, written in scenario files as, for
+/// example, `{ watts_strogatz = { k = 6, beta = 0.1 } }`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum GraphConfig {
+    /// Every pair of people is in contact with chance `p`.
+    ErdosRenyi { p: f64 },
+    /// A ring of people who know their `k` nearest neighbours, with each
+    /// contact rewired at random with chance `beta`.
+    WattsStrogatz { k: usize, beta: f64 },
+    /// Preferential attachment, where each newcomer makes `m` contacts.
+    BarabasiAlbert { m: usize },
+    /// Random contacts with degrees drawn from `degree_distribution`, the
+    /// relative frequency of people with 0, 1, 2, ... contacts.
+    Configuration { degree_distribution: Vec<f64> },
+    /// An edge list read from a CSV file, see `Network::from_csv`. In a
+    /// scenario file the path is relative to the scenario.
+    EdgeList(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub population: usize,
+    pub graph: GraphConfig,
+    ///people who start out infectious, picked at random
+    pub initial_infected: usize,
+    ///infections per day from an infectious person to each susceptible
+    ///contact, unless their edge has a rate of its own
+    pub transmission_rate: f64,
+    pub p_symptomatic_on_infection: f32,
+    ///how infectious asymptomatic carriers are relative to symptomatic ones
+    pub asymptomatic_infectiousness: f32,
+    ///days spent exposed before becoming infectious
+    pub incubation_days: f32,
+    ///days spent infectious before being removed
+    pub infectious_days: f32,
+    ///days asymptomatic carriers spend infectious before being removed
+    pub asymptomatic_infectious_days: f32,
+    pub ticks_per_day: u32,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> NetworkConfig {
+        NetworkConfig {
+            population: 400,
+            graph: GraphConfig::WattsStrogatz { k: 6, beta: 0.1 },
+            initial_infected: 3,
+            transmission_rate: 0.1,
+            p_symptomatic_on_infection: 0.7,
+            asymptomatic_infectiousness: 0.5,
+            incubation_days: 3.0,
+            infectious_days: 10.0,
+            asymptomatic_infectious_days: 7.0,
+            ticks_per_day: 10,
+        }
+    }
+}
+
+impl NetworkConfig {
+    pub fn validate(&self) -> Result<(), ParamError> {
+        error::positive("population", self.population as f64)?;
+        error::within_population(
+            "initial_infected",
+            self.initial_infected as f64,
+            self.population as f64,
+        )?;
+        error::non_negative("transmission_rate", self.transmission_rate)?;
+        error::probability(
+            "p_symptomatic_on_infection",
+            self.p_symptomatic_on_infection.into(),
+        )?;
+        error::probability(
+            "asymptomatic_infectiousness",
+            self.asymptomatic_infectiousness.into(),
+        )?;
+        error::non_negative("incubation_days", self.incubation_days.into())?;
+        error::non_negative("infectious_days", self.infectious_days.into())?;
+        error::non_negative(
+            "asymptomatic_infectious_days",
+            self.asymptomatic_infectious_days.into(),
+        )?;
+        error::positive("ticks_per_day", self.ticks_per_day.into())?;
+        if let GraphConfig::Configuration {
+            degree_distribution,
+        } = &self.graph
+        {
+            for &weight in degree_distribution {
+                error::non_negative("graph.configuration.degree_distribution", weight)?;
+            }
+            error::positive(
+                "graph.configuration.degree_distribution",
+                degree_distribution.iter().sum(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Builds the contact network of `population` people, reading it from
+    /// disk for an edge list.
+    pub fn network<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<Network, NetworkError> {
+        let nodes = self.population;
+        let network = match &self.graph {
+            GraphConfig::ErdosRenyi { p } => Network::erdos_renyi(nodes, *p, rng)?,
+            GraphConfig::WattsStrogatz { k, beta } => {
+                Network::watts_strogatz(nodes, *k, *beta, rng)?
+            }
+            GraphConfig::BarabasiAlbert { m } => Network::barabasi_albert(nodes, *m, rng)?,
+            GraphConfig::Configuration {
+                degree_distribution,
+            } => {
+                let total: f64 = degree_distribution.iter().sum();
+                let mut degrees: Vec<usize> = (0..nodes)
+                    .map(|_| {
+                        let mut u = rng.gen::<f64>() * total;
+                        for (degree, &weight) in degree_distribution.iter().enumerate() {
+                            if u < weight {
+                                return degree;
+                            }
+                            u -= weight;
+                        }
+                        degree_distribution.len() - 1
+                    })
+                    .collect();
+                // Every edge has two ends, so the degrees must add up to an
+                // even number.
+                if !degrees.iter().sum::<usize>().is_multiple_of(2) {
+                    degrees[0] += 1;
+                }
+                Network::configuration(&degrees, rng)?
+            }
+            GraphConfig::EdgeList(path) => Network::load(path, Some(nodes))?,
+        };
+        if network.nodes() != nodes {
+            return Err(ParamError::new("graph", "must have a node for everybody").into());
+        }
+        Ok(network)
+    }
+
+    pub fn metadata(&self, seed: u64) -> Metadata {
+        let mut metadata = Metadata::new("network")
+            .seed(seed)
+            .parameter("population", self.population as f64)
+            .parameter("initial_infected", self.initial_infected as f64)
+            .parameter("transmission_rate", self.transmission_rate)
+            .parameter(
+                "p_symptomatic_on_infection",
+                self.p_symptomatic_on_infection,
+            )
+            .parameter(
+                "asymptomatic_infectiousness",
+                self.asymptomatic_infectiousness,
+            )
+            .parameter("incubation_days", self.incubation_days)
+            .parameter("infectious_days", self.infectious_days)
+            .parameter(
+                "asymptomatic_infectious_days",
+                self.asymptomatic_infectious_days,
+            )
+            .parameter("ticks_per_day", f64::from(self.ticks_per_day));
+        metadata = match &self.graph {
+            GraphConfig::ErdosRenyi { p } => metadata.parameter("graph.erdos_renyi.p", *p),
+            GraphConfig::WattsStrogatz { k, beta } => metadata
+                .parameter("graph.watts_strogatz.k", *k as f64)
+                .parameter("graph.watts_strogatz.beta", *beta),
+            GraphConfig::BarabasiAlbert { m } => {
+                metadata.parameter("graph.barabasi_albert.m", *m as f64)
+            }
+            GraphConfig::Configuration {
+                degree_distribution,
+            } => {
+                for (degree, &weight) in degree_distribution.iter().enumerate() {
+                    let name = format!("graph.configuration.degree_distribution.{}", degree);
+                    metadata = metadata.parameter(&name, weight);
+                }
+                metadata
+            }
+            // Metadata only holds numbers, so the file name is left out.
+            GraphConfig::EdgeList(_) => metadata,
+        };
+        metadata
+    }
+}
+
+/// Head counts of a `NetworkModel` at time `t`, in days.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct NetworkSample {
+    pub t: f64,
+    pub susceptible: usize,
+    pub exposed: usize,
+    pub infectious: usize,
+    pub removed: usize,
+}
+
+impl Sample for NetworkSample {
+    const COMPARTMENTS: &'static [&'static str] =
+        &["susceptible", "exposed", "infectious", "removed"];
+
+    fn time(&self) -> f64 {
+        self.t
+    }
+
+    fn values(&self) -> Vec<f64> {
+        [
+            self.susceptible,
+            self.exposed,
+            self.infectious,
+            self.removed,
+        ]
+        .iter()
+        .map(|&n| n as f64)
+        .collect()
+    }
+
+    fn infectious(&self) -> f64 {
+        self.infectious as f64
+    }
+
+    fn removed(&self) -> f64 {
+        self.removed as f64
+    }
+
+    fn at_time(&self, t: f64) -> NetworkSample {
+        NetworkSample { t, ..*self }
+    }
+}
+
+#[derive(Clone)]
+pub struct NetworkModel {
+    config: NetworkConfig,
+    network: Network,
+    people: Vec<Person>,
+    rng: Box<dyn ModelRng>,
+    tick: u64,
+    tree: TransmissionTree,
+}
+
+impl NetworkModel {
+    /// Builds the network described by `config` and infects
+    /// `initial_infected` people picked at random.
+    pub fn new(config: NetworkConfig, seed: u64) -> Result<NetworkModel, NetworkError> {
+        config.validate()?;
+        let mut rng = rng::seeded(seed);
+        let network = config.network(&mut rng)?;
+        Ok(NetworkModel::with_network(config, network, Box::new(rng))?)
+    }
+
+    /// Spreads the infection over `network` instead of the one described by
+    /// `config.graph`, drawing all randomness from `rng`.
+    pub fn with_network(
+        config: NetworkConfig,
+        network: Network,
+        mut rng: Box<dyn ModelRng>,
+    ) -> Result<NetworkModel, ParamError> {
+        config.validate()?;
+        if network.nodes() != config.population {
+            return Err(ParamError::new("graph", "must have a node for everybody"));
+        }
+
+        let mut people: Vec<Person> = (0..config.population)
+            .map(|_| Person::new(0.0, config.p_symptomatic_on_infection, 0.0, Vec2::ZERO))
+            .collect();
+        let mut tree = TransmissionTree::new(config.population);
+        let mut order: Vec<usize> = (0..config.population).collect();
+        for n in 0..config.initial_infected {
+            let pick = rng.gen_range(n, order.len());
+            order.swap(n, pick);
+            let person = &mut people[order[n]];
+            person.infect(Status::Infectious, 0.0, &mut rng);
+            tree.record(Infection {
+                t: 0.0,
+                infector: None,
+                infectee: order[n],
+                location: Vec2::ZERO,
+                setting: Setting::Initial,
+                infector_status: None,
+                infector_symptomatic: None,
+            });
+        }
+        let mut model = NetworkModel {
+            config,
+            network,
+            people,
+            rng,
+            tick: 0,
+            tree,
+        };
+        for n in 0..model.people.len() {
+            model.set_durations(n);
+        }
+        Ok(model)
+    }
+
+    fn set_durations(&mut self, person: usize) {
+        let config = &self.config;
+        let person = &mut self.people[person];
+        person.incubation_days = config.incubation_days.into();
+        person.infectious_days = if person.symptomatic {
+            config.infectious_days.into()
+        } else {
+            config.asymptomatic_infectious_days.into()
+        };
+    }
+
+    pub fn config(&self) -> &NetworkConfig {
+        &self.config
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    /// Everybody, in the order of the network's nodes.
+    pub fn people(&self) -> &[Person] {
+        &self.people
+    }
+
+    /// Who infected whom so far.
+    pub fn transmission_tree(&self) -> &TransmissionTree {
+        &self.tree
+    }
+
+    /// Changes the rate of edges without a rate of their own.
+    pub fn set_transmission_rate(&mut self, rate: f64) -> Result<(), ParamError> {
+        error::non_negative("transmission_rate", rate)?;
+        self.config.transmission_rate = rate;
+        Ok(())
+    }
+
+    /// Simulation time in days.
+    pub fn time(&self) -> f64 {
+        self.tick as f64 / f64::from(self.config.ticks_per_day)
+    }
+
+    pub fn sample(&self) -> NetworkSample {
+        let mut sample = NetworkSample {
+            t: self.time(),
+            susceptible: 0,
+            exposed: 0,
+            infectious: 0,
+            removed: 0,
+        };
+        for person in &self.people {
+            match person.status {
+                Status::Susceptible => sample.susceptible += 1,
+                Status::Exposed => sample.exposed += 1,
+                Status::Infectious => sample.infectious += 1,
+                Status::Removed => sample.removed += 1,
+            }
+        }
+        sample
+    }
+
+    /// Whether nobody is left who could still infect anyone.
+    pub fn is_over(&self) -> bool {
+        self.people
+            .iter()
+            .all(|p| !matches!(p.status, Status::Exposed | Status::Infectious))
+    }
+
+    /// Advances the simulation by a single tick.
+    pub fn step(&mut self) {
+        self.tick += 1;
+        self.transmit();
+        self.progress();
+    }
+
+    /// Advances the simulation by `days`, returning the current counts
+    /// followed by the counts at the end of each day.
+    pub fn run(&mut self, days: usize) -> Trajectory<NetworkSample> {
+        let mut trajectory = Trajectory::with_capacity(days + 1);
+        trajectory.push(self.sample());
+        for _ in 0..days {
+            for _ in 0..self.config.ticks_per_day {
+                self.step();
+            }
+            trajectory.push(self.sample());
+        }
+        trajectory
+    }
+
+    fn transmit(&mut self) {
+        let t = self.time();
+        let dt = 1.0 / f64::from(self.config.ticks_per_day);
+        let asymptomatic = f64::from(self.config.asymptomatic_infectiousness);
+        let infectious: Vec<usize> = (0..self.people.len())
+            .filter(|&i| self.people[i].status == Status::Infectious)
+            .collect();
+
+        let mut infected = Vec::new();
+        for i in infectious {
+            let (status, symptomatic) = (self.people[i].status, self.people[i].symptomatic);
+            let infectiousness = if symptomatic { 1.0 } else { asymptomatic };
+            for &e in self.network.edges_of(i) {
+                let edge = self.network.edges()[e];
+                let j = edge.other(i);
+                let rate = edge.rate.unwrap_or(self.config.transmission_rate);
+                let p = 1.0 - libm::exp(-rate * infectiousness * dt);
+                let target = &mut self.people[j];
+                if target.status == Status::Susceptible && self.rng.gen::<f64>() < p {
+                    target.infect(Status::Exposed, t, &mut self.rng);
+                    infected.push(j);
+                    self.tree.record(Infection {
+                        t,
+                        infector: Some(i),
+                        infectee: j,
+                        location: Vec2::ZERO,
+                        setting: Setting::Network,
+                        infector_status: Some(status),
+                        infector_symptomatic: Some(symptomatic),
+                    });
+                }
+            }
+        }
+        for j in infected {
+            self.set_durations(j);
+        }
+    }
+
+    fn progress(&mut self) {
+        // Compare in ticks so whole-day periods are not cut short by rounding.
+        let t = self.time();
+        let ticks_per_day = f64::from(self.config.ticks_per_day);
+        let ticks = |days: f64| (days * ticks_per_day).round();
+        let elapsed = |person: &Person| ticks(t - person.status_since);
+
+        for person in &mut self.people {
+            if person.status == Status::Exposed && elapsed(person) >= ticks(person.incubation_days)
+            {
+                person.set_status(Status::Infectious, t);
+            }
+            if person.status == Status::Infectious
+                && elapsed(person) >= ticks(person.infectious_days)
+            {
+                person.set_status(Status::Removed, t);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for NetworkModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NetworkModel")
+            .field("config", &self.config)
+            .field("tick", &self.tick)
+            .field("edges", &self.network.edges().len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::seeded;
+
+    fn model(graph: GraphConfig, seed: u64) -> NetworkModel {
+        let config = NetworkConfig {
+            population: 1000,
+            graph,
+            initial_infected: 5,
+            transmission_rate: 0.3,
+            ..NetworkConfig::default()
+        };
+        NetworkModel::new(config, seed).unwrap()
+    }
+
+    #[test]
+    fn spreads_along_edges_only() {
+        let mut model = model(GraphConfig::BarabasiAlbert { m: 3 }, 1);
+        let trajectory = model.run(60);
+        let last = trajectory.last().unwrap();
+        assert_eq!(
+            last.susceptible + last.exposed + last.infectious + last.removed,
+            1000
+        );
+        assert!(last.removed > 100, "{:?}", last);
+
+        let infections = model.transmission_tree().infections();
+        assert_eq!(
+            infections
+                .iter()
+                .filter(|infection| infection.setting == Setting::Initial)
+                .count(),
+            5
+        );
+        for infection in infections {
+            if let Some(infector) = infection.infector {
+                assert_eq!(infection.setting, Setting::Network);
+                assert!(model.network().has_edge(infector, infection.infectee));
+            }
+        }
+    }
+
+    #[test]
+    fn uses_per_edge_rates() {
+        // A star whose centre only infects the leaves with a rate of their
+        // own.
+        let mut network = Network::new(11);
+        for leaf in 1..11 {
+            let rate = if leaf <= 5 { Some(100.0) } else { Some(0.0) };
+            network.add_edge(0, leaf, rate).unwrap();
+        }
+        let config = NetworkConfig {
+            population: 11,
+            initial_infected: 0,
+            p_symptomatic_on_infection: 1.0,
+            ..NetworkConfig::default()
+        };
+        let mut model = NetworkModel::with_network(config, network, Box::new(seeded(2))).unwrap();
+        model.people[0].infect(Status::Infectious, 0.0, &mut seeded(3));
+        model.set_durations(0);
+        model.run(5);
+
+        let infected: Vec<usize> = model
+            .transmission_tree()
+            .infections()
+            .iter()
+            .map(|infection| infection.infectee)
+            .collect();
+        let mut sorted = infected.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn rejects_mismatched_networks() {
+        let config = NetworkConfig {
+            population: 10,
+            ..NetworkConfig::default()
+        };
+        let error =
+            NetworkModel::with_network(config, Network::new(5), Box::new(seeded(1))).unwrap_err();
+        assert_eq!(error.field, "graph");
+
+        let config = NetworkConfig {
+            graph: GraphConfig::Configuration {
+                degree_distribution: vec![0.0, 0.0],
+            },
+            ..NetworkConfig::default()
+        };
+        assert!(NetworkModel::new(config, 1).is_err());
+    }
+
+    #[test]
+    fn same_seed_same_outbreak() {
+        let graph = GraphConfig::ErdosRenyi { p: 0.005 };
+        let a = model(graph.clone(), 7).run(30);
+        let b = model(graph, 7).run(30);
+        assert_eq!(a, b);
+    }
+}
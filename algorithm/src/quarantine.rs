@@ -0,0 +1,129 @@
+//! The lazaretto: an isolation area for detected cases.
+//!
+//! Infectious people are detected once they have shown symptoms for a while,
+//! or at random through testing. Detected cases queue for a place in the
+//! quarantine region, which lies outside the arena, and live there without
+//! being able to infect anyone until they are removed and sent back.
+
+use std::collections::VecDeque;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{self, ParamError};
+use crate::geometry::{Rect, Vec2};
+use crate::{Person, Status};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuarantineConfig {
+    ///where quarantined people are kept, must not overlap the arena
+    pub region: Rect,
+    ///most people the region holds at once
+    pub capacity: usize,
+    ///days a symptomatic case is infectious before they are detected
+    pub detection_delay_days: f32,
+    ///chance per day that an undetected infectious person tests positive
+    pub testing_rate: f32,
+}
+
+impl QuarantineConfig {
+    pub fn validate(&self, arena: &Rect) -> Result<(), ParamError> {
+        error::positive("quarantine.region.width", self.region.width.into())?;
+        error::positive("quarantine.region.height", self.region.height.into())?;
+        if self.region.overlaps(arena) {
+            return Err(ParamError::new(
+                "quarantine.region",
+                "must not overlap the arena",
+            ));
+        }
+        error::non_negative(
+            "quarantine.detection_delay_days",
+            self.detection_delay_days.into(),
+        )?;
+        error::probability("quarantine.testing_rate", self.testing_rate.into())?;
+        Ok(())
+    }
+}
+
+/// Bookkeeping for the people waiting for, or staying in, quarantine.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Quarantine {
+    ///detected cases, in order of detection, that have not been admitted
+    waiting: VecDeque<usize>,
+    occupancy: usize,
+}
+
+impl Quarantine {
+    pub(crate) fn occupancy(&self) -> usize {
+        self.occupancy
+    }
+
+    /// Detected cases still infectious but turned away for lack of room.
+    pub(crate) fn overflow(&self) -> usize {
+        self.waiting.len()
+    }
+
+    /// Runs one tick of releases, detections and admissions. While not
+    /// `admitting`, people are still released but nobody new is detected and
+    /// the queue is dropped, its people to be detected afresh once admissions
+    /// resume.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn update<R: Rng + ?Sized>(
+        &mut self,
+        config: &QuarantineConfig,
+        arena: &Rect,
+        people: &mut [Person],
+        t: f64,
+        dt: f32,
+        admitting: bool,
+        rng: &mut R,
+    ) {
+        for person in people.iter_mut() {
+            if person.quarantined && person.status == Status::Removed {
+                person.quarantined = false;
+                place(person, arena, rng);
+                self.occupancy -= 1;
+            }
+        }
+
+        if !admitting {
+            for i in self.waiting.drain(..) {
+                people[i].detected = false;
+            }
+            return;
+        }
+
+        let delay = f64::from(config.detection_delay_days);
+        let p_test = config.testing_rate * dt;
+        for (i, person) in people.iter_mut().enumerate() {
+            if person.status != Status::Infectious || person.detected {
+                continue;
+            }
+            let symptoms_noticed = person.symptomatic && t - person.status_since >= delay;
+            if symptoms_noticed || (p_test > 0.0 && rng.gen::<f32>() < p_test) {
+                person.detected = true;
+                self.waiting.push_back(i);
+            }
+        }
+
+        self.waiting
+            .retain(|&i| people[i].status == Status::Infectious);
+        while self.occupancy < config.capacity {
+            match self.waiting.pop_front() {
+                Some(i) => {
+                    people[i].quarantined = true;
+                    place(&mut people[i], &config.region, rng);
+                    self.occupancy += 1;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// Puts `person` somewhere random in `area`, heading in a random direction.
+fn place<R: Rng + ?Sized>(person: &mut Person, area: &Rect, rng: &mut R) {
+    person.position = area.random_point(rng);
+    person.velocity = Vec2::random_unit(rng) * person.max_speed;
+}
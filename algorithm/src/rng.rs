@@ -0,0 +1,130 @@
+//! Random number generation for the stochastic models.
+//!
+//! Every stochastic model can be driven either from a `u64` seed or from a
+//! caller supplied generator (any cloneable `RngCore`). Seeds are expanded
+//! with `SimRng`, a PCG generator whose output is specified independently of
+//! the platform, and draws avoid floating point functions whose last bit may
+//! differ between native and wasm32 builds, so a seed reproduces the same
+//! run everywhere.
+
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+
+pub use rand::RngCore;
+
+/// The generator behind every seeded model.
+pub type SimRng = Pcg64Mcg;
+
+pub fn seeded(seed: u64) -> SimRng {
+    SimRng::seed_from_u64(seed)
+}
+
+/// A generator a stochastic model can own. Unlike a plain `RngCore` trait
+/// object it can be cloned, so the model can be too.
+pub trait ModelRng: RngCore + Send {
+    fn clone_box(&self) -> Box<dyn ModelRng>;
+}
+
+impl<R: RngCore + Send + Clone + 'static> ModelRng for R {
+    fn clone_box(&self) -> Box<dyn ModelRng> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn ModelRng> {
+    fn clone(&self) -> Box<dyn ModelRng> {
+        (**self).clone_box()
+    }
+}
+
+/// An exponentially distributed waiting time for an event with `rate`.
+pub(crate) fn exponential<R: Rng + ?Sized>(rng: &mut R, rate: f64) -> f64 {
+    // `gen` is in [0, 1), so `1 - gen` keeps the logarithm finite. `libm`
+    // gives the same answer on every target, unlike the platform `ln`.
+    -libm::log(1.0 - rng.gen::<f64>()) / rate
+}
+
+/// A standard normal draw, by Marsaglia's polar method so no trigonometry
+/// is involved.
+pub(crate) fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    loop {
+        let u = 2.0 * rng.gen::<f64>() - 1.0;
+        let v = 2.0 * rng.gen::<f64>() - 1.0;
+        let s = u * u + v * v;
+        if s > 0.0 && s < 1.0 {
+            return u * (-2.0 * libm::log(s) / s).sqrt();
+        }
+    }
+}
+
+/// A gamma distributed draw, by Marsaglia and Tsang's method.
+pub(crate) fn gamma<R: Rng + ?Sized>(rng: &mut R, shape: f64, scale: f64) -> f64 {
+    if shape < 1.0 {
+        let u = 1.0 - rng.gen::<f64>();
+        return gamma(rng, shape + 1.0, scale) * libm::pow(u, 1.0 / shape);
+    }
+
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        let x = standard_normal(rng);
+        let v = (1.0 + c * x).powi(3);
+        if v <= 0.0 {
+            continue;
+        }
+        let u = rng.gen::<f64>();
+        if libm::log(u) < 0.5 * x * x + d - d * v + d * libm::log(v) {
+            return d * v * scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gillespie;
+    use crate::{SeirModel, World, WorldConfig};
+
+    // These values must be the same on every target; if they change, seeds in
+    // old bug reports no longer reproduce.
+    #[test]
+    fn seeded_streams_are_stable() {
+        let mut rng = seeded(2020);
+        let drawn: Vec<u64> = (0..3).map(|_| rng.next_u64()).collect();
+        assert_eq!(drawn, GOLDEN_STREAM);
+
+        let model = SeirModel::new(300.0, 0.6, 0.25, 0.2, 2.0).unwrap();
+        let last = *gillespie::simulate(&model, 50.0, 2020).last().unwrap();
+        assert_eq!((last.s, last.e, last.i, last.r), GOLDEN_SEIR);
+    }
+
+    #[test]
+    fn seed_and_supplied_rng_agree() {
+        let model = SeirModel::new(300.0, 0.6, 0.25, 0.2, 2.0).unwrap();
+        assert_eq!(
+            gillespie::simulate(&model, 50.0, 9),
+            gillespie::simulate_with_rng(&model, 50.0, &mut seeded(9))
+        );
+
+        let seeded_world = World::new(WorldConfig::default(), 9).unwrap().run(20);
+        let supplied_world = World::with_rng(WorldConfig::default(), Box::new(seeded(9)))
+            .unwrap()
+            .run(20);
+        assert_eq!(seeded_world, supplied_world);
+    }
+
+    #[test]
+    fn cloned_worlds_continue_identically() {
+        let mut world = World::new(WorldConfig::default(), 9).unwrap();
+        world.run(5);
+        let mut copy = world.clone();
+        assert_eq!(world.run(15), copy.run(15));
+    }
+
+    const GOLDEN_STREAM: [u64; 3] = [
+        15242733305864556375,
+        3254837870881186979,
+        9658138635856116891,
+    ];
+    const GOLDEN_SEIR: (f64, f64, f64, f64) = (16.0, 9.0, 9.0, 266.0);
+}
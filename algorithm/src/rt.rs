@@ -0,0 +1,324 @@
+//! The time-varying reproduction number Rt.
+//!
+//! Agent simulations know exactly who infected whom, so `World::true_rt`
+//! reports the case reproduction number: the mean number of people infected
+//! by those who caught the disease on each day. `Cori` estimates the
+//! instantaneous reproduction number from an incidence series alone, the way
+//! it is done with surveillance data (Cori et al. 2013), so estimators can be
+//! checked against the truth.
+
+use serde::Serialize;
+
+use crate::error::{self, ParamError};
+use crate::transmission::TransmissionTree;
+use crate::world::World;
+use crate::Status;
+
+/// Standard normal quantile of the 97.5th percentile.
+const Z_975: f64 = 1.959_963_984_540_054;
+
+/// The distribution of days between the infection of an infector and of the
+/// people they infect.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SerialInterval {
+    ///`weights[s - 1]` is the probability of an interval of `s` days
+    weights: Vec<f64>,
+}
+
+impl SerialInterval {
+    /// Uses `weights[s - 1]` as the relative probability of an interval of
+    /// `s` days, normalised to sum to one.
+    pub fn new(mut weights: Vec<f64>) -> Result<SerialInterval, ParamError> {
+        for &w in &weights {
+            error::non_negative("serial_interval", w)?;
+        }
+        let total: f64 = weights.iter().sum();
+        error::positive("serial_interval", total)?;
+        for w in &mut weights {
+            *w /= total;
+        }
+        Ok(SerialInterval { weights })
+    }
+
+    /// A gamma distribution with the given mean and standard deviation in
+    /// days, discretised to whole days. Intervals shorter than half a day
+    /// count as one day.
+    pub fn gamma(mean: f64, sd: f64) -> Result<SerialInterval, ParamError> {
+        error::positive("serial_interval.mean", mean)?;
+        error::positive("serial_interval.sd", sd)?;
+        let shape = (mean / sd).powi(2);
+        let scale = sd * sd / mean;
+        let ln_norm = libm::lgamma(shape) + shape * libm::log(scale);
+        let density = |x: f64| {
+            if x <= 0.0 {
+                0.0
+            } else {
+                libm::exp((shape - 1.0) * libm::log(x) - x / scale - ln_norm)
+            }
+        };
+
+        let days = (mean + 10.0 * sd).ceil() as usize;
+        let weights = (1..=days)
+            .map(|s| {
+                let from = if s == 1 { 0.0 } else { s as f64 - 0.5 };
+                simpson(density, from, s as f64 + 0.5)
+            })
+            .collect();
+        SerialInterval::new(weights)
+    }
+
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.weights
+            .iter()
+            .enumerate()
+            .map(|(s, w)| (s + 1) as f64 * w)
+            .sum()
+    }
+}
+
+/// Integrates `f` over `[from, to]` with Simpson's rule.
+fn simpson<F: Fn(f64) -> f64>(f: F, from: f64, to: f64) -> f64 {
+    const STEPS: usize = 32;
+    let h = (to - from) / STEPS as f64;
+    let inner: f64 = (1..STEPS)
+        .map(|n| {
+            let weight = if n % 2 == 1 { 4.0 } else { 2.0 };
+            weight * f(from + n as f64 * h)
+        })
+        .sum();
+    (f(from) + inner + f(to)) * h / 3.0
+}
+
+/// Bayesian estimate of the instantaneous reproduction number over a sliding
+/// window, assuming Rt stays constant within it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cori {
+    pub serial_interval: SerialInterval,
+    ///days of incidence each estimate is based on
+    pub window: usize,
+    ///mean of the gamma prior on Rt
+    pub prior_mean: f64,
+    ///standard deviation of the gamma prior on Rt
+    pub prior_sd: f64,
+}
+
+/// An estimate of Rt with its 95% credible interval.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RtEstimate {
+    ///last day of the window
+    pub t: f64,
+    pub mean: f64,
+    pub sd: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Cori {
+    /// Weekly windows and the same vague prior as EpiEstim, with a mean and
+    /// standard deviation of 5.
+    pub fn new(serial_interval: SerialInterval) -> Cori {
+        Cori {
+            serial_interval,
+            window: 7,
+            prior_mean: 5.0,
+            prior_sd: 5.0,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ParamError> {
+        error::positive("window", self.window as f64)?;
+        error::positive("prior_mean", self.prior_mean)?;
+        error::positive("prior_sd", self.prior_sd)?;
+        Ok(())
+    }
+
+    /// Estimates Rt from the number of new infections on each day, starting
+    /// with day 0. Windows start from day 1, since day 0 has no earlier
+    /// infections to be caused by, and days with no infectious pressure at
+    /// all are skipped.
+    pub fn estimate(&self, incidence: &[f64]) -> Result<Vec<RtEstimate>, ParamError> {
+        self.validate()?;
+        for &cases in incidence {
+            error::non_negative("incidence", cases)?;
+        }
+
+        let weights = self.serial_interval.weights();
+        let pressure: Vec<f64> = (0..incidence.len())
+            .map(|t| {
+                weights
+                    .iter()
+                    .zip((0..t).rev())
+                    .map(|(w, earlier)| w * incidence[earlier])
+                    .sum()
+            })
+            .collect();
+
+        let prior_shape = (self.prior_mean / self.prior_sd).powi(2);
+        let prior_scale = self.prior_sd * self.prior_sd / self.prior_mean;
+        let mut estimates = Vec::new();
+        for end in self.window..incidence.len() {
+            let days = end + 1 - self.window..=end;
+            let cases: f64 = incidence[days.clone()].iter().sum();
+            let pressure: f64 = pressure[days].iter().sum();
+            if pressure <= 0.0 {
+                continue;
+            }
+
+            let shape = prior_shape + cases;
+            let scale = 1.0 / (1.0 / prior_scale + pressure);
+            estimates.push(RtEstimate {
+                t: end as f64,
+                mean: shape * scale,
+                sd: shape.sqrt() * scale,
+                lower: gamma_quantile(shape, scale, -Z_975),
+                upper: gamma_quantile(shape, scale, Z_975),
+            });
+        }
+        Ok(estimates)
+    }
+}
+
+/// The Wilson–Hilferty approximation of the gamma quantile at the standard
+/// normal quantile `z`.
+fn gamma_quantile(shape: f64, scale: f64, z: f64) -> f64 {
+    let c = 1.0 / (9.0 * shape);
+    let root = (1.0 - c + z * c.sqrt()).max(0.0);
+    shape * scale * root.powi(3)
+}
+
+/// The case reproduction number of everyone infected on one day.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TrueRt {
+    pub t: f64,
+    ///people infected that day
+    pub infected: usize,
+    ///mean number of people each of them went on to infect
+    pub rt: f64,
+    ///whether all of them have been removed, so `rt` can no longer change
+    pub complete: bool,
+}
+
+impl TransmissionTree {
+    /// The number of infections on each day up to and including `day`,
+    /// counting the initial cases on day 0.
+    pub fn incidence(&self, day: usize) -> Vec<f64> {
+        let mut incidence = vec![0.0; day + 1];
+        for infection in self.infections() {
+            if let Some(cases) = incidence.get_mut(infection.t.floor() as usize) {
+                *cases += 1.0;
+            }
+        }
+        incidence
+    }
+}
+
+impl World {
+    /// The number of infections on each day so far, counting the initial
+    /// cases on day 0.
+    pub fn incidence(&self) -> Vec<f64> {
+        self.transmission_tree()
+            .incidence(self.time().floor() as usize)
+    }
+
+    /// The case reproduction number for each day anybody was infected on.
+    /// Recent days are biased low until everyone infected on them has been
+    /// removed, as flagged by `complete`.
+    pub fn true_rt(&self) -> Vec<TrueRt> {
+        let tree = self.transmission_tree();
+        let secondary = tree.secondary_cases();
+        let mut days: Vec<TrueRt> = Vec::new();
+        for infection in tree.infections() {
+            let t = infection.t.floor();
+            if days.last().map(|day| day.t) != Some(t) {
+                days.push(TrueRt {
+                    t,
+                    infected: 0,
+                    rt: 0.0,
+                    complete: true,
+                });
+            }
+            let day = days.last_mut().unwrap();
+            day.infected += 1;
+            day.rt += secondary[infection.infectee] as f64;
+            day.complete &= self.people()[infection.infectee].status() == Status::Removed;
+        }
+        for day in &mut days {
+            day.rt /= day.infected as f64;
+        }
+        days
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::WorldConfig;
+
+    #[test]
+    fn discretises_gamma_serial_interval() {
+        let si = SerialInterval::gamma(4.7, 2.9).unwrap();
+        let total: f64 = si.weights().iter().sum();
+        assert!((total - 1.0).abs() < 1e-12);
+        assert!((si.mean() - 4.7).abs() < 0.1, "{}", si.mean());
+
+        assert!(SerialInterval::new(vec![0.0, 0.0]).is_err());
+        assert!(SerialInterval::new(vec![1.0, -1.0]).is_err());
+        assert!(SerialInterval::gamma(4.7, 0.0).is_err());
+        assert_eq!(
+            SerialInterval::new(vec![1.0, 3.0]).unwrap().weights(),
+            [0.25, 0.75]
+        );
+    }
+
+    #[test]
+    fn recovers_rt_of_a_renewal_process() {
+        let si = SerialInterval::new(vec![0.2, 0.5, 0.3]).unwrap();
+        let mut incidence = vec![100.0];
+        for t in 1..40 {
+            let r = if t < 20 { 1.5 } else { 0.8 };
+            let pressure: f64 = si
+                .weights()
+                .iter()
+                .zip((0..t).rev())
+                .map(|(w, s)| w * incidence[s])
+                .sum();
+            incidence.push(r * pressure);
+        }
+
+        let estimates = Cori::new(si).estimate(&incidence).unwrap();
+        assert_eq!(estimates[0].t, 7.0);
+        for estimate in &estimates {
+            let expected = if estimate.t < 20.0 {
+                1.5
+            } else if estimate.t >= 26.0 {
+                0.8
+            } else {
+                continue;
+            };
+            assert!((estimate.mean - expected).abs() < 0.01, "{:?}", estimate);
+            assert!(estimate.lower < estimate.mean && estimate.mean < estimate.upper);
+        }
+    }
+
+    #[test]
+    fn true_rt_accounts_for_every_infection() {
+        let mut world = World::new(WorldConfig::default(), 5).unwrap();
+        world.run(30);
+        let rt = world.true_rt();
+        let incidence = world.incidence();
+
+        assert_eq!(incidence.len(), 31);
+        assert_eq!(incidence[0], 3.0);
+        let infected: usize = rt.iter().map(|day| day.infected).sum();
+        assert_eq!(infected as f64, incidence.iter().sum::<f64>());
+
+        let secondary: f64 = rt.iter().map(|day| day.rt * day.infected as f64).sum();
+        assert!((secondary - (infected - 3) as f64).abs() < 1e-9);
+        assert!(rt[0].complete);
+        assert!(!rt.last().unwrap().complete);
+    }
+}
@@ -0,0 +1,1148 @@
+//! Scenario files describing a complete simulation run.
+//!
+//! A scenario names the model and its parameters, the interventions applied
+//! during the run, the seed and how many days to simulate. It can be written
+//! in TOML:
+//!
+//! ```toml
+//! seed = 42
+//! days = 120
+//!
+//! [model.seir]
+//! population = 10000
+//! beta = 0.5
+//! sigma = 0.2
+//! gamma = 0.1
+//! initial_infected = 10
+//!
+//! [[interventions]]
+//! name = "lockdown"
+//! trigger = { threshold = { metric = "infectious", on = 500, off = 100 } }
+//! action = { beta = 0.2 }
+//! ```
+//!
+//! or the equivalent JSON. Problems are reported with the path to the field
+//! at fault, such as `model.seir.beta` or `interventions[0].action.beta`.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::de::{self, DeserializeOwned, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::age::{self, AgeBand, AgeSeirModel, AgeSeirSample, ContactMatrix, ContactMatrixError};
+use crate::ensemble::Outcome;
+use crate::error::{self, ParamError};
+use crate::export::{self, Format, Metadata};
+use crate::gillespie;
+use crate::graph::{Network, NetworkError};
+use crate::intervention::{Action, Firing, Intervention, Schedule, Trigger};
+use crate::network::{GraphConfig, NetworkConfig, NetworkModel, NetworkSample};
+use crate::rng;
+use crate::seir::{SeirModel, SeirSample};
+use crate::sir::{SirModel, SirSample};
+use crate::solver::{daily_times, SolveError, Solver};
+use crate::trajectory::Trajectory;
+use crate::vaccination::VaccinationConfig;
+use crate::world::{World, WorldConfig, WorldSample};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: String,
+    ///seeds the random number generator of stochastic models
+    #[serde(default)]
+    pub seed: u64,
+    ///how long to simulate for
+    pub days: usize,
+    pub model: Model,
+    #[serde(default)]
+    pub interventions: Vec<Intervention>,
+    ///contact network read from `graph.edge_list` by `load`, shared by every run
+    #[serde(skip)]
+    pub(crate) edge_list: Option<Network>,
+}
+
+/// The model to simulate, written as a table holding one of `sir`, `seir`,
+/// `age_seir`, `world` or `network`.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Model {
+    Sir(SirParameters),
+    Seir(SeirParameters),
+    AgeSeir(AgeSeirParameters),
+    World(WorldConfig),
+    Network(NetworkConfig),
+}
+
+// Written out by hand because the derived implementation cannot read enums
+// from TOML table headers like `[model.sir]`.
+impl<'de> Deserialize<'de> for Model {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Model, D::Error> {
+        const MODELS: &[&str] = &["sir", "seir", "age_seir", "world", "network"];
+
+        struct ModelVisitor;
+
+        impl<'de> Visitor<'de> for ModelVisitor {
+            type Value = Model;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(
+                    "a table holding one of `sir`, `seir`, `age_seir`, `world` or `network`",
+                )
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Model, A::Error> {
+                let model = match map.next_key::<String>()?.as_deref() {
+                    Some("sir") => Model::Sir(map.next_value()?),
+                    Some("seir") => Model::Seir(map.next_value()?),
+                    Some("age_seir") => Model::AgeSeir(map.next_value()?),
+                    Some("world") => Model::World(map.next_value()?),
+                    Some("network") => Model::Network(map.next_value()?),
+                    Some(other) => return Err(de::Error::unknown_variant(other, MODELS)),
+                    None => return Err(de::Error::invalid_length(0, &self)),
+                };
+                if map.next_key::<de::IgnoredAny>()?.is_some() {
+                    return Err(de::Error::custom("only one model can be given"));
+                }
+                Ok(model)
+            }
+        }
+
+        deserializer.deserialize_map(ModelVisitor)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SirParameters {
+    pub population: f64,
+    pub beta: f64,
+    pub gamma: f64,
+    #[serde(default = "one")]
+    pub initial_infected: f64,
+    ///Erlang stages of the infectious period, 1 keeps it exponential
+    #[serde(default = "one_stage")]
+    pub infectious_stages: usize,
+    #[serde(default)]
+    pub method: Method,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeirParameters {
+    pub population: f64,
+    pub beta: f64,
+    pub sigma: f64,
+    pub gamma: f64,
+    #[serde(default = "one")]
+    pub initial_infected: f64,
+    ///Erlang stages of the latent period, 1 keeps it exponential
+    #[serde(default = "one_stage")]
+    pub exposed_stages: usize,
+    ///Erlang stages of the infectious period, 1 keeps it exponential
+    #[serde(default = "one_stage")]
+    pub infectious_stages: usize,
+    #[serde(default)]
+    pub method: Method,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgeSeirParameters {
+    pub bands: Vec<AgeBand>,
+    pub contacts: Contacts,
+    ///chance that a contact transmits the disease
+    pub beta: f64,
+    pub sigma: f64,
+    pub gamma: f64,
+    ///how infectious asymptomatic people are relative to symptomatic ones
+    #[serde(default = "one")]
+    pub asymptomatic_infectiousness: f64,
+    #[serde(default)]
+    pub vaccination: Option<VaccinationConfig>,
+    #[serde(default)]
+    pub method: Method,
+}
+
+/// Daily contacts between age bands, given inline as a row per band or
+/// read from a CSV file, relative to the scenario file, when the scenario
+/// runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Contacts {
+    Matrix(Vec<Vec<f64>>),
+    Csv(String),
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn one_stage() -> usize {
+    1
+}
+
+/// How a compartment model is advanced through time.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Method {
+    /// One explicit Euler step per day, like `SirModel::run`.
+    #[default]
+    Euler,
+    /// Exact stochastic simulation with Gillespie's direct method.
+    Gillespie,
+    Rk4 {
+        step: f64,
+    },
+    Dopri5 {
+        rtol: f64,
+        atol: f64,
+    },
+    Dop853 {
+        rtol: f64,
+        atol: f64,
+    },
+}
+
+impl Method {
+    /// The ODE solver this method uses, if any.
+    pub fn solver(self) -> Option<Solver> {
+        match self {
+            Method::Euler | Method::Gillespie => None,
+            Method::Rk4 { step } => Some(Solver::Rk4 { step }),
+            Method::Dopri5 { rtol, atol } => Some(Solver::Dopri5 { rtol, atol }),
+            Method::Dop853 { rtol, atol } => Some(Solver::Dop853 { rtol, atol }),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(io::Error),
+    /// A file the scenario names, such as an edge list, could not be read.
+    File {
+        ///field naming the file
+        path: String,
+        file: String,
+        error: io::Error,
+    },
+    /// The file is not valid TOML or JSON, or does not have the expected
+    /// shape.
+    Parse {
+        path: String,
+        message: String,
+    },
+    /// A value is out of range.
+    Invalid {
+        path: String,
+        reason: &'static str,
+    },
+    /// The ODE solver failed part way through the run.
+    Solve(SolveError),
+}
+
+impl ScenarioError {
+    pub(crate) fn invalid(prefix: &str, error: ParamError) -> ScenarioError {
+        ScenarioError::Invalid {
+            path: format!("{}.{}", prefix, error.field),
+            reason: error.reason,
+        }
+    }
+
+    /// Reports problems reading the contact matrix of an age-structured model
+    /// from `file`.
+    pub(crate) fn contacts(error: ContactMatrixError, file: &str) -> ScenarioError {
+        match error {
+            ContactMatrixError::Io(error) => ScenarioError::File {
+                path: "model.age_seir.contacts.csv".to_string(),
+                file: file.to_string(),
+                error,
+            },
+            ContactMatrixError::Csv { line, reason } => ScenarioError::Parse {
+                path: "model.age_seir.contacts.csv".to_string(),
+                message: format!("line {}: {}", line, reason),
+            },
+        }
+    }
+
+    /// Reports problems with the contact network of a network model built
+    /// from `graph`.
+    pub(crate) fn network(error: NetworkError, graph: &GraphConfig) -> ScenarioError {
+        match (error, graph) {
+            (NetworkError::Io(error), GraphConfig::EdgeList(file)) => ScenarioError::File {
+                path: "model.network.graph.edge_list".to_string(),
+                file: file.clone(),
+                error,
+            },
+            (NetworkError::Io(error), _) => ScenarioError::Io(error),
+            (NetworkError::Csv { line, reason }, _) => ScenarioError::Parse {
+                path: "model.network.graph.edge_list".to_string(),
+                message: format!("line {}: {}", line, reason),
+            },
+            (NetworkError::Invalid(error), _) => ScenarioError::invalid("model.network", error),
+        }
+    }
+
+    pub(crate) fn parse<E: fmt::Display>(error: serde_path_to_error::Error<E>) -> ScenarioError {
+        ScenarioError::Parse {
+            path: error.path().to_string(),
+            message: error.inner().to_string(),
+        }
+    }
+
+    /// The path to the field at fault, if the problem lies in one field.
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            ScenarioError::Io(_) | ScenarioError::Solve(_) => None,
+            ScenarioError::File { path, .. }
+            | ScenarioError::Parse { path, .. }
+            | ScenarioError::Invalid { path, .. } => Some(path),
+        }
+    }
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioError::Io(error) => write!(f, "could not read scenario: {}", error),
+            ScenarioError::File { path, file, error } => {
+                write!(f, "`{}`: could not read {}: {}", path, file, error)
+            }
+            ScenarioError::Parse { path, message } => write!(f, "`{}`: {}", path, message),
+            ScenarioError::Invalid { path, reason } => write!(f, "invalid `{}`: {}", path, reason),
+            ScenarioError::Solve(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+impl From<ParamError> for ScenarioError {
+    fn from(error: ParamError) -> ScenarioError {
+        ScenarioError::Invalid {
+            path: error.field.to_string(),
+            reason: error.reason,
+        }
+    }
+}
+
+impl From<io::Error> for ScenarioError {
+    fn from(error: io::Error) -> ScenarioError {
+        ScenarioError::Io(error)
+    }
+}
+
+impl Scenario {
+    pub fn from_toml(text: &str) -> Result<Scenario, ScenarioError> {
+        let scenario: Scenario = parse_toml(text)?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    pub fn from_json(text: &str) -> Result<Scenario, ScenarioError> {
+        let scenario: Scenario = parse_json(text)?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    /// Reads a scenario from a `.json` file, or from TOML otherwise. Relative
+    /// paths in it are taken from the directory the file is in, and an edge
+    /// list is read here once rather than on every run.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scenario, ScenarioError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let mut scenario = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Scenario::from_json(&text)?,
+            _ => Scenario::from_toml(&text)?,
+        };
+        if let Some(directory) = path.parent() {
+            scenario.resolve_paths(directory);
+        }
+        if let Model::Network(config) = &scenario.model {
+            if let GraphConfig::EdgeList(path) = &config.graph {
+                let network = Network::load(path, Some(config.population))
+                    .map_err(|error| ScenarioError::network(error, &config.graph))?;
+                scenario.edge_list = Some(network);
+            }
+        }
+        Ok(scenario)
+    }
+
+    /// Makes relative paths to the files the scenario reads relative to
+    /// `directory` rather than the working directory.
+    fn resolve_paths(&mut self, directory: &Path) {
+        let path = match &mut self.model {
+            Model::Network(NetworkConfig {
+                graph: GraphConfig::EdgeList(path),
+                ..
+            }) => path,
+            Model::AgeSeir(AgeSeirParameters {
+                contacts: Contacts::Csv(path),
+                ..
+            }) => path,
+            _ => return,
+        };
+        *path = directory.join(&path).to_string_lossy().into_owned();
+    }
+
+    pub fn validate(&self) -> Result<(), ScenarioError> {
+        error::positive("days", self.days as f64)?;
+        match &self.model {
+            Model::Sir(parameters) => {
+                let model = parameters
+                    .model()
+                    .map_err(|error| ScenarioError::invalid("model.sir", error))?;
+                if parameters.method == Method::Euler {
+                    model
+                        .check_daily_step()
+                        .map_err(|error| ScenarioError::invalid("model.sir", error))?;
+                }
+                validate_method(parameters.method, "model.sir.method")?;
+            }
+            Model::Seir(parameters) => {
+                let model = parameters
+                    .model()
+                    .map_err(|error| ScenarioError::invalid("model.seir", error))?;
+                if parameters.method == Method::Euler {
+                    model
+                        .check_daily_step()
+                        .map_err(|error| ScenarioError::invalid("model.seir", error))?;
+                }
+                validate_method(parameters.method, "model.seir.method")?;
+            }
+            Model::AgeSeir(parameters) => {
+                // A contact matrix read from CSV is only checked once it is
+                // loaded, so stand in an empty one for the other parameters.
+                let contacts = match &parameters.contacts {
+                    Contacts::Matrix(contacts) => contacts.clone(),
+                    Contacts::Csv(_) => {
+                        vec![vec![0.0; parameters.bands.len()]; parameters.bands.len()]
+                    }
+                };
+                parameters
+                    .model(contacts)
+                    .map_err(|error| ScenarioError::invalid("model.age_seir", error))?;
+                if parameters.method == Method::Gillespie {
+                    return Err(ScenarioError::Invalid {
+                        path: "model.age_seir.method".to_string(),
+                        reason: "must be `euler` or an ODE solver",
+                    });
+                }
+                validate_method(parameters.method, "model.age_seir.method")?;
+            }
+            Model::World(config) => config
+                .validate()
+                .map_err(|error| ScenarioError::invalid("model.world", error))?,
+            Model::Network(config) => config
+                .validate()
+                .map_err(|error| ScenarioError::invalid("model.network", error))?,
+        }
+        if !self.interventions.is_empty() && !self.supports_interventions() {
+            return Err(ScenarioError::Invalid {
+                path: "interventions".to_string(),
+                reason: "are only supported by the `euler` method",
+            });
+        }
+        for (n, intervention) in self.interventions.iter().enumerate() {
+            self.validate_intervention(intervention)
+                .map_err(|error| ScenarioError::invalid(&format!("interventions[{}]", n), error))?;
+        }
+        Ok(())
+    }
+
+    /// Whether the model can take interventions part way through a run,
+    /// which the ODE solvers and Gillespie's method cannot.
+    fn supports_interventions(&self) -> bool {
+        match &self.model {
+            Model::Sir(SirParameters { method, .. })
+            | Model::Seir(SeirParameters { method, .. })
+            | Model::AgeSeir(AgeSeirParameters { method, .. }) => *method == Method::Euler,
+            Model::World(_) | Model::Network(_) => true,
+        }
+    }
+
+    fn validate_intervention(&self, intervention: &Intervention) -> Result<(), ParamError> {
+        match intervention.trigger {
+            Trigger::Days { start, end } => {
+                error::non_negative("trigger.days.start", start)?;
+                if let Some(end) = end {
+                    if !(end.is_finite() && end > start) {
+                        return Err(ParamError::new(
+                            "trigger.days.end",
+                            "must be a finite day after the start",
+                        ));
+                    }
+                }
+            }
+            Trigger::Threshold { on, off, .. } => {
+                error::non_negative("trigger.threshold.on", on)?;
+                error::non_negative("trigger.threshold.off", off)?;
+                if off > on {
+                    return Err(ParamError::new(
+                        "trigger.threshold.off",
+                        "must not be above `on`",
+                    ));
+                }
+            }
+        }
+        // A network model has nobody moving about or being tested, so only
+        // its transmission rate can change.
+        let network = matches!(self.model, Model::Network(_));
+        if network && !matches!(intervention.action, Action::Beta(_)) {
+            return Err(ParamError::new(
+                "action",
+                "must be `beta` for a network model",
+            ));
+        }
+        match intervention.action {
+            Action::Beta(beta) => {
+                if let Model::World(_) = self.model {
+                    error::probability("action.beta", beta)?;
+                } else {
+                    error::non_negative("action.beta", beta)?;
+                }
+            }
+            Action::DistancingCompliance(compliance) => {
+                error::probability("action.distancing_compliance", compliance.into())?;
+            }
+            Action::DistancingMobility(mobility) => {
+                error::probability("action.distancing_mobility", mobility.into())?;
+            }
+            Action::TestingRate(rate) => {
+                error::probability("action.testing_rate", rate.into())?;
+                match &self.model {
+                    Model::World(config) if config.quarantine.is_some() => {}
+                    _ if rate == 0.0 => {}
+                    _ => {
+                        return Err(ParamError::new(
+                            "action.testing_rate",
+                            "needs a quarantine region",
+                        ))
+                    }
+                }
+            }
+            Action::Quarantine(_) => {}
+            Action::Close(kind) => match &self.model {
+                Model::World(WorldConfig {
+                    locations: Some(locations),
+                    ..
+                }) if locations.site(kind).is_some() => {}
+                _ => {
+                    return Err(ParamError::new(
+                        "action.close",
+                        "needs locations of that kind",
+                    ))
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// A fresh schedule of this scenario's interventions.
+    pub fn schedule(&self) -> Schedule {
+        Schedule::new(self.interventions.clone())
+    }
+
+    /// Runs the scenario for `days`, seeding stochastic models with `seed`
+    /// rather than the scenario's own.
+    pub fn run(&self, seed: u64) -> Result<Run, ScenarioError> {
+        let mut schedule = self.schedule();
+        let times = daily_times(0.0, self.days);
+        let invalid = |prefix: &'static str| move |error| ScenarioError::invalid(prefix, error);
+
+        let (metadata, output) = match &self.model {
+            Model::Sir(parameters) => {
+                let mut model = parameters.model().map_err(invalid("model.sir"))?;
+                let metadata = model.metadata();
+                let trajectory = match parameters.method {
+                    Method::Euler => model
+                        .run_scheduled(self.days, &mut schedule)
+                        .map_err(invalid("interventions"))?,
+                    Method::Gillespie => {
+                        gillespie::simulate(&model, self.days as f64, seed).resample(&times)
+                    }
+                    method => model
+                        .solve(method.solver().unwrap(), &times)
+                        .map_err(ScenarioError::Solve)?,
+                };
+                (metadata, Output::Sir(trajectory))
+            }
+            Model::Seir(parameters) => {
+                let mut model = parameters.model().map_err(invalid("model.seir"))?;
+                let metadata = model.metadata();
+                let trajectory = match parameters.method {
+                    Method::Euler => model
+                        .run_scheduled(self.days, &mut schedule)
+                        .map_err(invalid("interventions"))?,
+                    Method::Gillespie => {
+                        gillespie::simulate(&model, self.days as f64, seed).resample(&times)
+                    }
+                    method => model
+                        .solve(method.solver().unwrap(), &times)
+                        .map_err(ScenarioError::Solve)?,
+                };
+                (metadata, Output::Seir(trajectory))
+            }
+            Model::AgeSeir(parameters) => {
+                let contacts = parameters.contacts()?;
+                let mut model = parameters
+                    .model(contacts)
+                    .map_err(invalid("model.age_seir"))?;
+                let metadata = model.metadata();
+                let trajectory = match parameters.method.solver() {
+                    None => model
+                        .run_scheduled(self.days, &mut schedule)
+                        .map_err(invalid("interventions"))?,
+                    Some(solver) => model.solve(solver, &times).map_err(ScenarioError::Solve)?,
+                };
+                let bands = parameters
+                    .bands
+                    .iter()
+                    .map(|band| band.name.clone())
+                    .collect();
+                (metadata, Output::AgeSeir { bands, trajectory })
+            }
+            Model::World(config) => {
+                let mut world = World::new(config.clone(), seed).map_err(invalid("model.world"))?;
+                let trajectory = world
+                    .run_scheduled(self.days, &mut schedule)
+                    .map_err(invalid("interventions"))?;
+                (config.metadata(seed), Output::World(trajectory))
+            }
+            Model::Network(config) => {
+                let mut model = match &self.edge_list {
+                    Some(network) => NetworkModel::with_network(
+                        config.clone(),
+                        network.clone(),
+                        Box::new(rng::seeded(seed)),
+                    )
+                    .map_err(invalid("model.network"))?,
+                    None => NetworkModel::new(config.clone(), seed)
+                        .map_err(|error| ScenarioError::network(error, &config.graph))?,
+                };
+                let trajectory = model
+                    .run_scheduled(self.days, &mut schedule)
+                    .map_err(invalid("interventions"))?;
+                (config.metadata(seed), Output::Network(trajectory))
+            }
+        };
+
+        let stochastic = match &self.model {
+            Model::Sir(SirParameters { method, .. })
+            | Model::Seir(SeirParameters { method, .. }) => *method == Method::Gillespie,
+            Model::AgeSeir(_) => false,
+            Model::World(_) | Model::Network(_) => true,
+        };
+        let metadata = if stochastic {
+            metadata.seed(seed)
+        } else {
+            metadata
+        };
+        Ok(Run {
+            metadata,
+            output,
+            firings: schedule.log().to_vec(),
+        })
+    }
+
+    /// The agent-based world this scenario describes, seeded with `seed`.
+    pub fn world(&self) -> Result<World, ScenarioError> {
+        match &self.model {
+            Model::World(config) => World::new(config.clone(), self.seed)
+                .map_err(|error| ScenarioError::invalid("model.world", error)),
+            _ => Err(ScenarioError::Invalid {
+                path: "model".to_string(),
+                reason: "must be a world model",
+            }),
+        }
+    }
+}
+
+pub(crate) fn parse_toml<T: DeserializeOwned>(text: &str) -> Result<T, ScenarioError> {
+    let mut deserializer = toml::Deserializer::new(text);
+    serde_path_to_error::deserialize(&mut deserializer).map_err(ScenarioError::parse)
+}
+
+pub(crate) fn parse_json<T: DeserializeOwned>(text: &str) -> Result<T, ScenarioError> {
+    let mut deserializer = serde_json::Deserializer::from_str(text);
+    serde_path_to_error::deserialize(&mut deserializer).map_err(ScenarioError::parse)
+}
+
+/// The result of running a scenario.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub metadata: Metadata,
+    pub output: Output,
+    ///when each intervention switched on or off
+    pub firings: Vec<Firing>,
+}
+
+/// The daily trajectory of whichever model a scenario ran.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    Sir(Trajectory<SirSample>),
+    Seir(Trajectory<SeirSample>),
+    AgeSeir {
+        ///names of the age bands, in the order of each sample's bands
+        bands: Vec<String>,
+        trajectory: Trajectory<AgeSeirSample>,
+    },
+    World(Trajectory<WorldSample>),
+    Network(Trajectory<NetworkSample>),
+}
+
+impl Output {
+    pub fn outcome(&self) -> Outcome {
+        match self {
+            Output::Sir(trajectory) => Outcome::of(trajectory),
+            Output::Seir(trajectory) => Outcome::of(trajectory),
+            Output::AgeSeir { trajectory, .. } => Outcome::of(trajectory),
+            Output::World(trajectory) => Outcome::of(trajectory),
+            Output::Network(trajectory) => Outcome::of(trajectory),
+        }
+    }
+}
+
+impl Run {
+    pub fn write<W: Write>(&self, writer: W, format: Format) -> io::Result<()> {
+        match &self.output {
+            Output::Sir(trajectory) => export::write(writer, format, &self.metadata, trajectory),
+            Output::Seir(trajectory) => export::write(writer, format, &self.metadata, trajectory),
+            Output::AgeSeir { bands, trajectory } => {
+                age::write(writer, format, &self.metadata, bands, trajectory)
+            }
+            Output::World(trajectory) => export::write(writer, format, &self.metadata, trajectory),
+            Output::Network(trajectory) => {
+                export::write(writer, format, &self.metadata, trajectory)
+            }
+        }
+    }
+}
+
+fn validate_method(method: Method, path: &str) -> Result<(), ScenarioError> {
+    if let Some(solver) = method.solver() {
+        let variant = match method {
+            Method::Rk4 { .. } => "rk4",
+            Method::Dopri5 { .. } => "dopri5",
+            _ => "dop853",
+        };
+        solver
+            .validate()
+            .map_err(|error| ScenarioError::invalid(&format!("{}.{}", path, variant), error))?;
+    }
+    Ok(())
+}
+
+impl SirParameters {
+    pub fn model(&self) -> Result<SirModel, ParamError> {
+        SirModel::new(
+            self.population,
+            self.beta,
+            self.gamma,
+            self.initial_infected,
+        )?
+        .with_infectious_stages(self.infectious_stages)
+    }
+}
+
+impl SeirParameters {
+    pub fn model(&self) -> Result<SeirModel, ParamError> {
+        SeirModel::new(
+            self.population,
+            self.beta,
+            self.sigma,
+            self.gamma,
+            self.initial_infected,
+        )?
+        .with_stages(self.exposed_stages, self.infectious_stages)
+    }
+}
+
+impl AgeSeirParameters {
+    pub fn model(&self, contacts: Vec<Vec<f64>>) -> Result<AgeSeirModel, ParamError> {
+        let model = AgeSeirModel::new(
+            self.bands.clone(),
+            contacts,
+            self.beta,
+            self.sigma,
+            self.gamma,
+        )?
+        .with_asymptomatic_infectiousness(self.asymptomatic_infectiousness)?;
+        match &self.vaccination {
+            Some(vaccination) => model.with_vaccination(vaccination.clone()),
+            None => Ok(model),
+        }
+    }
+
+    /// The contact matrix, read from its CSV file if it has one. The file
+    /// must name the same bands in the same order.
+    pub fn contacts(&self) -> Result<Vec<Vec<f64>>, ScenarioError> {
+        match &self.contacts {
+            Contacts::Matrix(contacts) => Ok(contacts.clone()),
+            Contacts::Csv(path) => {
+                let matrix = ContactMatrix::load(path)
+                    .map_err(|error| ScenarioError::contacts(error, path))?;
+                let names = self.bands.iter().map(|band| &band.name);
+                if !names.eq(matrix.bands.iter()) {
+                    return Err(ScenarioError::Invalid {
+                        path: "model.age_seir.contacts.csv".to_string(),
+                        reason: "must name the same bands as `bands`, in order",
+                    });
+                }
+                Ok(matrix.contacts)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distribution::Distribution;
+    use crate::intervention::Metric;
+
+    const SEIR: &str = r#"
+        seed = 42
+        days = 120
+
+        [model.seir]
+        population = 10000
+        beta = 0.5
+        sigma = 0.2
+        gamma = 0.1
+        initial_infected = 10
+
+        [[interventions]]
+        name = "lockdown"
+        trigger = { threshold = { metric = "infectious", on = 500, off = 100 } }
+        action = { beta = 0.2 }
+    "#;
+
+    #[test]
+    fn loads_toml() {
+        let scenario = Scenario::from_toml(SEIR).unwrap();
+        assert_eq!(scenario.seed, 42);
+        assert_eq!(scenario.days, 120);
+        match &scenario.model {
+            Model::Seir(parameters) => {
+                assert_eq!(parameters.sigma, 0.2);
+                assert_eq!(parameters.method, Method::Euler);
+            }
+            model => panic!("unexpected model {:?}", model),
+        }
+        assert_eq!(
+            scenario.interventions[0].trigger,
+            Trigger::Threshold {
+                metric: Metric::Infectious,
+                on: 500.0,
+                off: 100.0,
+            }
+        );
+        assert_eq!(scenario.interventions[0].action, Action::Beta(0.2));
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let scenario = Scenario::from_toml(SEIR).unwrap();
+        let json = serde_json::to_string(&scenario).unwrap();
+        assert_eq!(Scenario::from_json(&json).unwrap(), scenario);
+    }
+
+    #[test]
+    fn world_fields_default() {
+        let scenario = Scenario::from_toml(
+            r#"
+            days = 30
+            [model.world]
+            population = 100
+            [model.world.quarantine]
+            region = { x = 820, y = 0, width = 100, height = 100 }
+            capacity = 10
+            detection_delay_days = 2
+            testing_rate = 0.1
+            "#,
+        )
+        .unwrap();
+        let world = scenario.world().unwrap();
+        assert_eq!(world.config().population, 100);
+        assert_eq!(world.config().width, WorldConfig::default().width);
+    }
+
+    #[test]
+    fn default_scenario_matches_default_world() {
+        let scenario = Scenario::from_toml(include_str!("../../scenarios/default.toml")).unwrap();
+        assert_eq!(scenario.model, Model::World(WorldConfig::default()));
+    }
+
+    #[test]
+    fn runs_every_model() {
+        let run = Scenario::from_toml(SEIR).unwrap().run(3).unwrap();
+        assert_eq!(run.metadata.seed, None);
+        match &run.output {
+            Output::Seir(trajectory) => assert_eq!(trajectory.len(), 121),
+            output => panic!("unexpected output {:?}", output),
+        }
+        assert_eq!(run.firings[0].name, "lockdown");
+
+        let gillespie = SEIR.replace("gamma = 0.1", "gamma = 0.1\nmethod = \"gillespie\"");
+        let error = Scenario::from_toml(&gillespie).unwrap_err();
+        assert_eq!(error.path(), Some("interventions"));
+        let mut scenario = Scenario::from_toml(SEIR).unwrap();
+        scenario.interventions.clear();
+        if let Model::Seir(parameters) = &mut scenario.model {
+            parameters.method = Method::Gillespie;
+        }
+        let run = scenario.run(3).unwrap();
+        assert_eq!(run.metadata.seed, Some(3));
+        assert_eq!(run, scenario.run(3).unwrap());
+
+        let world = Scenario::from_toml("days = 5\n[model.world]\npopulation = 50").unwrap();
+        let run = world.run(8).unwrap();
+        assert_eq!(run.metadata.seed, Some(8));
+        let mut csv = Vec::new();
+        run.write(&mut csv, Format::WideCsv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("# model: world\n# seed: 8\n"));
+        assert_eq!(csv.lines().filter(|line| !line.starts_with('#')).count(), 7);
+    }
+
+    #[test]
+    fn runs_network_models() {
+        let scenario = Scenario::from_toml(
+            r#"
+            days = 20
+            [model.network]
+            population = 300
+            graph = { barabasi_albert = { m = 2 } }
+            transmission_rate = 0.4
+
+            [[interventions]]
+            name = "lockdown"
+            trigger = { days = { start = 5 } }
+            action = { beta = 0.1 }
+            "#,
+        )
+        .unwrap();
+        let run = scenario.run(4).unwrap();
+        assert_eq!(run.metadata.seed, Some(4));
+        assert_eq!(run.firings.len(), 1);
+        let mut csv = Vec::new();
+        run.write(&mut csv, Format::WideCsv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("# model: network\n# seed: 4\n"));
+        assert!(csv.contains("# graph.barabasi_albert.m: 2\n"));
+
+        let missing = "days = 5\n[model.network]\ngraph = { edge_list = \"missing.csv\" }";
+        let scenario = Scenario::from_toml(missing).unwrap();
+        let error = scenario.run(1).unwrap_err();
+        assert!(matches!(error, ScenarioError::File { .. }));
+        assert_eq!(error.path(), Some("model.network.graph.edge_list"));
+        assert!(error.to_string().contains("missing.csv"), "{}", error);
+        let error = Scenario::from_toml(
+            "days = 5\n[model.network]\ngraph = { barabasi_albert = { m = 0 } }",
+        )
+        .unwrap()
+        .run(1)
+        .unwrap_err();
+        assert_eq!(error.path(), Some("model.network.graph.barabasi_albert.m"));
+    }
+
+    #[test]
+    fn edge_lists_are_read_once_next_to_the_scenario() {
+        let directory = std::env::temp_dir().join(format!("lazaretto-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let edges = directory.join("contacts.csv");
+        let ring: String = (0..50)
+            .map(|n| format!("{},{}\n", n, (n + 1) % 50))
+            .collect();
+        fs::write(&edges, format!("source,target\n{}", ring)).unwrap();
+        let path = directory.join("ring.toml");
+        fs::write(
+            &path,
+            "days = 30\n[model.network]\npopulation = 50\ninitial_infected = 1\n\
+             transmission_rate = 1\ngraph = { edge_list = \"contacts.csv\" }\n",
+        )
+        .unwrap();
+
+        let scenario = Scenario::load(&path).unwrap();
+        fs::remove_file(&edges).unwrap();
+        let first = scenario.run(3).unwrap();
+        assert_eq!(first.output, scenario.run(3).unwrap().output);
+        assert!(first.output.outcome().final_size > 1.0);
+        assert!(matches!(
+            Scenario::load(&path).unwrap_err(),
+            ScenarioError::File { .. }
+        ));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn runs_age_structured_models() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../scenarios/age.toml");
+        let scenario = Scenario::load(path).unwrap();
+        let run = scenario.run(0).unwrap();
+        assert_eq!(run.metadata.seed, None);
+        assert!(run.output.outcome().final_size > 1000.0);
+        let mut csv = Vec::new();
+        run.write(&mut csv, Format::WideCsv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.contains("# 65+.fatality: 0.06\n"));
+        assert!(csv.contains(",65+.i_symptomatic,65+.i_asymptomatic,65+.r,65+.d\n"));
+
+        let with_parameters = |change: &dyn Fn(&mut AgeSeirParameters)| {
+            let mut scenario = scenario.clone();
+            match &mut scenario.model {
+                Model::AgeSeir(parameters) => change(parameters),
+                model => panic!("unexpected model {:?}", model),
+            }
+            scenario
+        };
+
+        let swapped = with_parameters(&|parameters| parameters.bands[0].name = "children".into());
+        let error = swapped.run(0).unwrap_err();
+        assert_eq!(error.path(), Some("model.age_seir.contacts.csv"));
+        let gillespie = with_parameters(&|parameters| parameters.method = Method::Gillespie);
+        let error = gillespie.validate().unwrap_err();
+        assert_eq!(error.path(), Some("model.age_seir.method"));
+        let missing = with_parameters(&|parameters| {
+            parameters.contacts = Contacts::Csv("missing.csv".into())
+        });
+        let error = missing.run(0).unwrap_err();
+        assert!(matches!(error, ScenarioError::File { .. }));
+        assert_eq!(error.path(), Some("model.age_seir.contacts.csv"));
+
+        let vaccination = |daily_doses| {
+            parse_toml::<VaccinationConfig>(&format!(
+                "daily_doses = {}\n\
+                 efficacy_against_infection = 0.6\nefficacy_against_symptoms = 0.9\n",
+                daily_doses
+            ))
+            .unwrap()
+        };
+        let vaccinated = with_parameters(&|parameters| {
+            parameters.vaccination = Some(vaccination(100));
+        });
+        let vaccinated = vaccinated.run(0).unwrap();
+        assert!(vaccinated
+            .metadata
+            .parameters
+            .contains(&("vaccination.daily_doses".into(), 100.0.into())));
+        assert!(vaccinated.output.outcome().final_size < run.output.outcome().final_size);
+        let error = with_parameters(&|parameters| {
+            parameters.vaccination = Some(vaccination(-1));
+        })
+        .validate()
+        .unwrap_err();
+        assert_eq!(error.path(), Some("model.age_seir.vaccination.daily_doses"));
+    }
+
+    #[test]
+    fn configures_durations() {
+        let scenario = Scenario::from_toml(
+            r#"
+            days = 30
+            [model.world]
+            incubation_period = { gamma = { mean = 5.0, sd = 2.0 } }
+            infectious_period = { weibull = { shape = 3.0, scale = 8.0 } }
+            asymptomatic_infectious_period = { fixed = 6.0 }
+            "#,
+        )
+        .unwrap();
+        let config = scenario.world().unwrap().config().clone();
+        assert_eq!(
+            config.incubation_period,
+            Some(Distribution::Gamma { mean: 5.0, sd: 2.0 })
+        );
+        assert_eq!(
+            config.asymptomatic_infectious_period,
+            Some(Distribution::Fixed(6.0))
+        );
+        let metadata = config.metadata(0);
+        assert!(metadata
+            .parameters
+            .contains(&("infectious_period.weibull.shape".to_string(), 3.0.into())));
+
+        let seir = SEIR.replace(
+            "gamma = 0.1",
+            "gamma = 0.1
+exposed_stages = 2
+infectious_stages = 3",
+        );
+        let scenario = Scenario::from_toml(&seir).unwrap();
+        match &scenario.model {
+            Model::Seir(parameters) => {
+                let model = parameters.model().unwrap();
+                assert_eq!((model.exposed_stages(), model.infectious_stages()), (2, 3));
+            }
+            model => panic!("unexpected model {:?}", model),
+        }
+        assert_eq!(
+            error(
+                "days = 10\n[model.world]\n\
+                 incubation_period = { log_normal = { mean = 5, sd = 0 } }"
+            ),
+            "invalid `model.world.incubation_period`: needs a `mean` and `sd` greater than zero"
+        );
+        assert_eq!(
+            error("days = 10\n[model.sir]\npopulation = 100\nbeta = 1\ngamma = 0.1\ninfectious_stages = 0"),
+            "invalid `model.sir.infectious_stages`: must be a finite number greater than zero"
+        );
+        assert_eq!(
+            error(&SEIR.replace("gamma = 0.1", "gamma = 0.1\nexposed_stages = 8")),
+            "invalid `model.seir.exposed_stages`: must be at most `1 / sigma` for daily steps"
+        );
+        assert!(Scenario::from_toml(
+            "days = 10\n[model.seir]\npopulation = 100\nbeta = 1\nsigma = 0.2\n\
+             gamma = 0.1\nexposed_stages = 8\nmethod = { rk4 = { step = 0.1 } }"
+        )
+        .is_ok());
+    }
+
+    fn error(text: &str) -> String {
+        Scenario::from_toml(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn errors_point_at_the_field() {
+        assert_eq!(
+            error("days = 10\n[model.sir]\npopulation = 100\nbeta = -1\ngamma = 0.1"),
+            "invalid `model.sir.beta`: must be a finite, non-negative number"
+        );
+        assert!(
+            error("days = 10\n[model.sir]\npopulation = 100\nbeta = \"high\"\ngamma = 0.1")
+                .starts_with("`model.sir.beta`: invalid type")
+        );
+        assert!(error("days = 10\n[model.world]\npopulaton = 100")
+            .starts_with("`model.world.populaton`: unknown field"));
+        assert_eq!(
+            error(
+                "days = 10\n[model.world]\n[[interventions]]\nname = \"tests\"\n\
+                 trigger = { days = { start = 5 } }\naction = { testing_rate = 0.5 }"
+            ),
+            "invalid `interventions[0].action.testing_rate`: needs a quarantine region"
+        );
+        assert_eq!(
+            error(
+                "days = 10\n[model.world]\n[[interventions]]\nname = \"lockdown\"\n\
+                 trigger = { days = { start = 5, end = nan } }\naction = { beta = 0.1 }"
+            ),
+            "invalid `interventions[0].trigger.days.end`: must be a finite day after the start"
+        );
+        assert_eq!(
+            error(
+                "days = 10\n[model.network]\n[[interventions]]\nname = \"distancing\"\n\
+                 trigger = { days = { start = 5 } }\naction = { distancing_compliance = 0.5 }"
+            ),
+            "invalid `interventions[0].action`: must be `beta` for a network model"
+        );
+        assert_eq!(
+            error("days = 10\n[model.sir]\npopulation = 100\nbeta = 1\ngamma = 0.1\nmethod = { rk4 = { step = 0 } }"),
+            "invalid `model.sir.method.rk4.step`: must be a finite number greater than zero"
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! Deterministic Susceptible-Exposed-Infectious-Removed compartment model.
+//!
+//! Newly infected people spend a latent period in `Status::Exposed` before
+//! they become infectious, leaving it at rate `sigma`. Like the SIR model,
+//! both the latent and the infectious period can be split into a chain of
+//! stages to make them Erlang rather than exponentially distributed.
+
+use std::ops::Range;
+
+use serde::Serialize;
+
+use crate::error::{self, ParamError};
+use crate::export::Metadata;
+use crate::sir::{advance_chain, chain_derivative, check_daily_chain};
+use crate::solver::{self, OdeModel, SolveError, Solver};
+use crate::trajectory::{Sample, Trajectory};
+
+/// The state of an SEIR model at time `t`, in days.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SeirSample {
+    pub t: f64,
+    pub s: f64,
+    pub e: f64,
+    pub i: f64,
+    pub r: f64,
+}
+
+impl Sample for SeirSample {
+    const COMPARTMENTS: &'static [&'static str] = &["s", "e", "i", "r"];
+
+    fn time(&self) -> f64 {
+        self.t
+    }
+
+    fn values(&self) -> Vec<f64> {
+        vec![self.s, self.e, self.i, self.r]
+    }
+
+    fn infectious(&self) -> f64 {
+        self.i
+    }
+
+    fn removed(&self) -> f64 {
+        self.r
+    }
+
+    fn at_time(&self, t: f64) -> SeirSample {
+        SeirSample { t, ..*self }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SeirModel {
+    population: f64,
+    ///avg contacts per person per day that would transmit the disease
+    beta: f64,
+    ///the fraction of exposed people that become infectious each day
+    sigma: f64,
+    ///the fraction of infectious people that recover each day
+    gamma: f64,
+    state: SeirSample,
+    ///exposed people in each stage of the chain, summing to `state.e`
+    exposed_stages: Vec<f64>,
+    ///infectious people in each stage of the chain, summing to `state.i`
+    infectious_stages: Vec<f64>,
+}
+
+impl SeirModel {
+    /// Creates a model with `initial_infected` people infectious at `t = 0`
+    /// and everybody else susceptible.
+    pub fn new(
+        population: f64,
+        beta: f64,
+        sigma: f64,
+        gamma: f64,
+        initial_infected: f64,
+    ) -> Result<SeirModel, ParamError> {
+        let population = error::positive("population", population)?;
+        let beta = error::non_negative("beta", beta)?;
+        let sigma = error::non_negative("sigma", sigma)?;
+        let gamma = error::positive("gamma", gamma)?;
+        let i = error::within_population("initial_infected", initial_infected, population)?;
+
+        Ok(SeirModel {
+            population,
+            beta,
+            sigma,
+            gamma,
+            state: SeirSample {
+                t: 0.0,
+                s: population - i,
+                e: 0.0,
+                i,
+                r: 0.0,
+            },
+            exposed_stages: vec![0.0],
+            infectious_stages: vec![i],
+        })
+    }
+
+    /// Splits the latent and infectious periods into `exposed` and
+    /// `infectious` stages, left at rates `exposed * sigma` and
+    /// `infectious * gamma`. Everyone currently exposed or infectious starts
+    /// in the first stage.
+    pub fn with_stages(
+        mut self,
+        exposed: usize,
+        infectious: usize,
+    ) -> Result<SeirModel, ParamError> {
+        error::positive("exposed_stages", exposed as f64)?;
+        error::positive("infectious_stages", infectious as f64)?;
+        self.exposed_stages = vec![0.0; exposed];
+        self.exposed_stages[0] = self.state.e;
+        self.infectious_stages = vec![0.0; infectious];
+        self.infectious_stages[0] = self.state.i;
+        Ok(self)
+    }
+
+    pub fn exposed_stages(&self) -> usize {
+        self.exposed_stages.len()
+    }
+
+    pub fn infectious_stages(&self) -> usize {
+        self.infectious_stages.len()
+    }
+
+    pub fn population(&self) -> f64 {
+        self.population
+    }
+
+    pub fn beta(&self) -> f64 {
+        self.beta
+    }
+
+    /// Changes the contact rate from now on, for example when an
+    /// intervention starts or ends.
+    pub fn set_beta(&mut self, beta: f64) -> Result<(), ParamError> {
+        self.beta = error::non_negative("beta", beta)?;
+        Ok(())
+    }
+
+    pub fn sigma(&self) -> f64 {
+        self.sigma
+    }
+
+    pub fn gamma(&self) -> f64 {
+        self.gamma
+    }
+
+    /// The expected number of secondary infections from one case in a fully
+    /// susceptible population. The latent period delays but does not change it.
+    pub fn basic_reproduction_number(&self) -> f64 {
+        self.beta / self.gamma
+    }
+
+    pub fn state(&self) -> SeirSample {
+        self.state
+    }
+
+    /// Checks the model can be advanced a whole day at a time. Each day
+    /// `step` moves `exposed_stages * sigma` and `infectious_stages * gamma`
+    /// of every stage along, which must not be more than the stage holds.
+    pub fn check_daily_step(&self) -> Result<(), ParamError> {
+        check_daily_chain(
+            "exposed_stages",
+            "must be at most `1 / sigma` for daily steps",
+            self.sigma,
+            self.exposed_stages.len(),
+        )?;
+        check_daily_chain(
+            "infectious_stages",
+            "must be at most `1 / gamma` for daily steps",
+            self.gamma,
+            self.infectious_stages.len(),
+        )
+    }
+
+    /// Advances the model by one day and returns the new state. People pile
+    /// up as negative counts unless `check_daily_step` passes.
+    pub fn step(&mut self) -> SeirSample {
+        let SeirSample { t, s, i, r, .. } = self.state;
+        let exposures = self.beta * (i / self.population) * s;
+        let onsets = advance_chain(&mut self.exposed_stages, self.sigma, exposures);
+        let recoveries = advance_chain(&mut self.infectious_stages, self.gamma, onsets);
+
+        self.state = SeirSample {
+            t: t + 1.0,
+            s: s - exposures,
+            e: self.exposed_stages.iter().sum(),
+            i: self.infectious_stages.iter().sum(),
+            r: r + recoveries,
+        };
+        self.state
+    }
+
+    /// Advances the model by `days`, returning the current state followed by
+    /// the state at the end of each day.
+    pub fn run(&mut self, days: usize) -> Trajectory<SeirSample> {
+        let mut trajectory = Trajectory::with_capacity(days + 1);
+        trajectory.push(self.state);
+        for _ in 0..days {
+            trajectory.push(self.step());
+        }
+        trajectory
+    }
+
+    /// Integrates the model from its current state with `solver`, sampling
+    /// it at each of the increasing `times`.
+    pub fn solve(
+        &self,
+        solver: Solver,
+        times: &[f64],
+    ) -> Result<Trajectory<SeirSample>, SolveError> {
+        solver::solve(self, solver, times)
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        Metadata::new("seir")
+            .parameter("population", self.population())
+            .parameter("beta", self.beta())
+            .parameter("sigma", self.sigma())
+            .parameter("gamma", self.gamma())
+            .stages("exposed_stages", self.exposed_stages())
+            .stages("infectious_stages", self.infectious_stages())
+    }
+}
+
+impl SeirModel {
+    /// Where the exposed and infectious stages are in the ODE state, which
+    /// starts with `s` and ends with `r`.
+    fn chains(&self) -> (Range<usize>, Range<usize>) {
+        let exposed = 1..1 + self.exposed_stages.len();
+        let infectious = exposed.end..exposed.end + self.infectious_stages.len();
+        (exposed, infectious)
+    }
+}
+
+impl OdeModel for SeirModel {
+    type Sample = SeirSample;
+
+    fn initial_time(&self) -> f64 {
+        self.state.t
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        let SeirSample { s, r, .. } = self.state;
+        let mut y = vec![s];
+        y.extend(&self.exposed_stages);
+        y.extend(&self.infectious_stages);
+        y.push(r);
+        y
+    }
+
+    fn derivative(&self, _t: f64, y: &[f64], dy: &mut [f64]) {
+        let (exposed, infectious) = self.chains();
+        let i: f64 = y[infectious.clone()].iter().sum();
+        let exposures = self.beta * (i / self.population) * y[0];
+        dy[0] = -exposures;
+        let onsets = chain_derivative(&y[exposed.clone()], &mut dy[exposed], self.sigma, exposures);
+        let end = infectious.end;
+        dy[end] = chain_derivative(
+            &y[infectious.clone()],
+            &mut dy[infectious],
+            self.gamma,
+            onsets,
+        );
+    }
+
+    fn sample(&self, t: f64, y: &[f64]) -> SeirSample {
+        let (exposed, infectious) = self.chains();
+        SeirSample {
+            t,
+            s: y[0],
+            e: y[exposed].iter().sum(),
+            i: y[infectious.clone()].iter().sum(),
+            r: y[infectious.end],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sir::SirModel;
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        assert_eq!(
+            SeirModel::new(100.0, 1.0, -0.5, 0.2, 1.0)
+                .unwrap_err()
+                .field,
+            "sigma"
+        );
+        assert_eq!(
+            SeirModel::new(100.0, 1.0, 0.5, 0.0, 1.0).unwrap_err().field,
+            "gamma"
+        );
+        assert_eq!(
+            SeirModel::new(100.0, 1.0, 0.5, 0.2, -1.0)
+                .unwrap_err()
+                .field,
+            "initial_infected"
+        );
+    }
+
+    #[test]
+    fn conserves_population() {
+        let mut model = SeirModel::new(1000.0, 1.0, 0.5, 0.2, 1.0).unwrap();
+        for sample in &model.run(100) {
+            assert!((sample.s + sample.e + sample.i + sample.r - 1000.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn latent_period_delays_the_peak() {
+        fn peak_day<S, F: Fn(&S) -> f64>(samples: &[S], infectious: F) -> usize {
+            (0..samples.len())
+                .max_by(|&a, &b| {
+                    infectious(&samples[a])
+                        .partial_cmp(&infectious(&samples[b]))
+                        .unwrap()
+                })
+                .unwrap()
+        }
+
+        let sir = SirModel::new(1000.0, 1.0, 0.2, 1.0).unwrap().run(200);
+        let seir = SeirModel::new(1000.0, 1.0, 0.2, 0.2, 1.0).unwrap().run(200);
+
+        assert!(peak_day(seir.samples(), |x| x.i) > peak_day(sir.samples(), |x| x.i));
+        assert!(seir.iter().any(|x| x.e > 0.0));
+    }
+
+    #[test]
+    fn stages_conserve_population() {
+        let model = SeirModel::new(1000.0, 1.0, 0.5, 0.2, 1.0)
+            .unwrap()
+            .with_stages(2, 3)
+            .unwrap();
+        let times: Vec<f64> = (0..=100).map(f64::from).collect();
+        let solved = model.solve(Solver::default(), &times).unwrap();
+        let stepped = model.clone().run(100);
+        for sample in solved.iter().chain(&stepped) {
+            assert!((sample.s + sample.e + sample.i + sample.r - 1000.0).abs() < 1e-6);
+        }
+        assert!(solved.last().unwrap().r > 900.0);
+
+        // One stage each is the plain SEIR model.
+        let plain = SeirModel::new(1000.0, 1.0, 0.5, 0.2, 1.0).unwrap();
+        assert_eq!(
+            plain.clone().with_stages(1, 1).unwrap().run(50),
+            plain.clone().run(50)
+        );
+    }
+}
@@ -0,0 +1,365 @@
+//! Deterministic Susceptible-Infectious-Removed compartment model.
+//!
+//! With a constant recovery rate the infectious period is exponentially
+//! distributed. `with_infectious_stages` splits it into a chain of stages
+//! passed through in turn, which makes it Erlang distributed with the same
+//! mean but far less spread, the so-called linear chain trick.
+
+use serde::Serialize;
+
+use crate::error::{self, ParamError};
+use crate::export::Metadata;
+use crate::solver::{self, OdeModel, SolveError, Solver};
+use crate::trajectory::{Sample, Trajectory};
+
+/// The state of an SIR model at time `t`, in days.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SirSample {
+    pub t: f64,
+    pub s: f64,
+    pub i: f64,
+    pub r: f64,
+}
+
+impl Sample for SirSample {
+    const COMPARTMENTS: &'static [&'static str] = &["s", "i", "r"];
+
+    fn time(&self) -> f64 {
+        self.t
+    }
+
+    fn values(&self) -> Vec<f64> {
+        vec![self.s, self.i, self.r]
+    }
+
+    fn infectious(&self) -> f64 {
+        self.i
+    }
+
+    fn removed(&self) -> f64 {
+        self.r
+    }
+
+    fn at_time(&self, t: f64) -> SirSample {
+        SirSample { t, ..*self }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SirModel {
+    population: f64,
+    ///avg contacts per person per day that would transmit the disease
+    beta: f64,
+    ///the fraction of infectious people that recover each day
+    gamma: f64,
+    state: SirSample,
+    ///infectious people in each stage of the chain, summing to `state.i`
+    stages: Vec<f64>,
+}
+
+impl SirModel {
+    /// Creates a model with `initial_infected` people infectious at `t = 0`
+    /// and everybody else susceptible.
+    pub fn new(
+        population: f64,
+        beta: f64,
+        gamma: f64,
+        initial_infected: f64,
+    ) -> Result<SirModel, ParamError> {
+        let population = error::positive("population", population)?;
+        let beta = error::non_negative("beta", beta)?;
+        let gamma = error::positive("gamma", gamma)?;
+        let i = error::within_population("initial_infected", initial_infected, population)?;
+
+        Ok(SirModel {
+            population,
+            beta,
+            gamma,
+            state: SirSample {
+                t: 0.0,
+                s: population - i,
+                i,
+                r: 0.0,
+            },
+            stages: vec![i],
+        })
+    }
+
+    /// Splits the infectious period into `stages` stages, each left at rate
+    /// `stages * gamma`. Everyone currently infectious starts in the first.
+    pub fn with_infectious_stages(mut self, stages: usize) -> Result<SirModel, ParamError> {
+        error::positive("infectious_stages", stages as f64)?;
+        self.stages = vec![0.0; stages];
+        self.stages[0] = self.state.i;
+        Ok(self)
+    }
+
+    pub fn infectious_stages(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn population(&self) -> f64 {
+        self.population
+    }
+
+    pub fn beta(&self) -> f64 {
+        self.beta
+    }
+
+    /// Changes the contact rate from now on, for example when an
+    /// intervention starts or ends.
+    pub fn set_beta(&mut self, beta: f64) -> Result<(), ParamError> {
+        self.beta = error::non_negative("beta", beta)?;
+        Ok(())
+    }
+
+    pub fn gamma(&self) -> f64 {
+        self.gamma
+    }
+
+    /// The expected number of secondary infections from one case in a fully
+    /// susceptible population.
+    pub fn basic_reproduction_number(&self) -> f64 {
+        self.beta / self.gamma
+    }
+
+    pub fn state(&self) -> SirSample {
+        self.state
+    }
+
+    /// Checks the model can be advanced a whole day at a time. Each day
+    /// `step` moves `infectious_stages * gamma` of every stage along, which
+    /// must not be more than the stage holds.
+    pub fn check_daily_step(&self) -> Result<(), ParamError> {
+        check_daily_chain(
+            "infectious_stages",
+            "must be at most `1 / gamma` for daily steps",
+            self.gamma,
+            self.stages.len(),
+        )
+    }
+
+    /// Advances the model by one day and returns the new state. People pile
+    /// up as negative counts unless `check_daily_step` passes.
+    pub fn step(&mut self) -> SirSample {
+        let SirSample { t, s, i, r } = self.state;
+        let infections = self.beta * (i / self.population) * s;
+        let recoveries = advance_chain(&mut self.stages, self.gamma, infections);
+
+        self.state = SirSample {
+            t: t + 1.0,
+            s: s - infections,
+            i: self.stages.iter().sum(),
+            r: r + recoveries,
+        };
+        self.state
+    }
+
+    /// Advances the model by `days`, returning the current state followed by
+    /// the state at the end of each day.
+    pub fn run(&mut self, days: usize) -> Trajectory<SirSample> {
+        let mut trajectory = Trajectory::with_capacity(days + 1);
+        trajectory.push(self.state);
+        for _ in 0..days {
+            trajectory.push(self.step());
+        }
+        trajectory
+    }
+
+    /// Integrates the model from its current state with `solver`, sampling
+    /// it at each of the increasing `times`.
+    pub fn solve(
+        &self,
+        solver: Solver,
+        times: &[f64],
+    ) -> Result<Trajectory<SirSample>, SolveError> {
+        solver::solve(self, solver, times)
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        Metadata::new("sir")
+            .parameter("population", self.population())
+            .parameter("beta", self.beta())
+            .parameter("gamma", self.gamma())
+            .stages("infectious_stages", self.infectious_stages())
+    }
+}
+
+impl OdeModel for SirModel {
+    type Sample = SirSample;
+
+    fn initial_time(&self) -> f64 {
+        self.state.t
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        let SirSample { s, r, .. } = self.state;
+        let mut y = vec![s];
+        y.extend(&self.stages);
+        y.push(r);
+        y
+    }
+
+    fn derivative(&self, _t: f64, y: &[f64], dy: &mut [f64]) {
+        let k = self.stages.len();
+        let i: f64 = y[1..=k].iter().sum();
+        let infections = self.beta * (i / self.population) * y[0];
+        dy[0] = -infections;
+        dy[k + 1] = chain_derivative(&y[1..=k], &mut dy[1..=k], self.gamma, infections);
+    }
+
+    fn sample(&self, t: f64, y: &[f64]) -> SirSample {
+        let k = self.stages.len();
+        SirSample {
+            t,
+            s: y[0],
+            i: y[1..=k].iter().sum(),
+            r: y[k + 1],
+        }
+    }
+}
+
+/// Moves people one day along a chain of stages with a total mean duration
+/// of `1 / rate`, adding `inflow` to the first stage. Returns how many leave
+/// the last stage.
+pub(crate) fn advance_chain(stages: &mut [f64], rate: f64, inflow: f64) -> f64 {
+    let rate = rate * stages.len() as f64;
+    let mut inflow = inflow;
+    for stage in stages {
+        let outflow = rate * *stage;
+        *stage = *stage + inflow - outflow;
+        inflow = outflow;
+    }
+    inflow
+}
+
+/// Fails with `reason` when `advance_chain` would take more people out of a
+/// stage in a day than it holds.
+pub(crate) fn check_daily_chain(
+    field: &'static str,
+    reason: &'static str,
+    rate: f64,
+    stages: usize,
+) -> Result<(), ParamError> {
+    if rate * stages as f64 > 1.0 {
+        return Err(ParamError::new(field, reason));
+    }
+    Ok(())
+}
+
+/// Writes the rate of change of each stage of a chain into `dy`, like
+/// `advance_chain`, and returns the rate people leave the last stage.
+pub(crate) fn chain_derivative(stages: &[f64], dy: &mut [f64], rate: f64, inflow: f64) -> f64 {
+    let rate = rate * stages.len() as f64;
+    let mut inflow = inflow;
+    for (stage, dy) in stages.iter().zip(dy) {
+        let outflow = rate * stage;
+        *dy = inflow - outflow;
+        inflow = outflow;
+    }
+    inflow
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        assert_eq!(
+            SirModel::new(0.0, 1.0, 0.2, 0.0).unwrap_err().field,
+            "population"
+        );
+        assert_eq!(
+            SirModel::new(100.0, -1.0, 0.2, 1.0).unwrap_err().field,
+            "beta"
+        );
+        assert_eq!(
+            SirModel::new(100.0, 1.0, f64::NAN, 1.0).unwrap_err().field,
+            "gamma"
+        );
+        assert_eq!(
+            SirModel::new(100.0, 1.0, 0.0, 1.0).unwrap_err().field,
+            "gamma"
+        );
+        assert_eq!(
+            SirModel::new(100.0, 1.0, 0.2, 101.0).unwrap_err().field,
+            "initial_infected"
+        );
+    }
+
+    #[test]
+    fn conserves_population() {
+        let mut model = SirModel::new(1000.0, 1.0, 0.2, 1.0).unwrap();
+        for sample in &model.run(100) {
+            assert!((sample.s + sample.i + sample.r - 1000.0).abs() < 1e-6);
+        }
+
+        // Five stages at a total rate of 0.25 would empty each stage 1.25
+        // times a day, so only the ODE solvers can run it.
+        let model = SirModel::new(1000.0, 1.0, 0.25, 1.0)
+            .unwrap()
+            .with_infectious_stages(5)
+            .unwrap();
+        assert_eq!(
+            model.check_daily_step().unwrap_err().field,
+            "infectious_stages"
+        );
+        let times: Vec<f64> = (0..=100).map(f64::from).collect();
+        for sample in &model.solve(Solver::default(), &times).unwrap() {
+            assert!(sample.s >= 0.0 && sample.i >= 0.0 && sample.r >= 0.0);
+        }
+    }
+
+    #[test]
+    fn outbreak_peaks_and_burns_out() {
+        let mut model = SirModel::new(1000.0, 1.0, 0.2, 1.0).unwrap();
+        let trajectory = model.run(100);
+        let peak = trajectory
+            .iter()
+            .max_by(|a, b| a.i.partial_cmp(&b.i).unwrap())
+            .unwrap();
+
+        assert!(peak.t > 0.0 && peak.t < 100.0);
+        assert!(trajectory.last().unwrap().i < 1.0);
+        assert_eq!(trajectory.len(), 101);
+    }
+
+    #[test]
+    fn stages_keep_the_mean_but_narrow_the_infectious_period() {
+        // With nobody susceptible, `i` is the fraction still infectious after
+        // an infectious period of `t` days.
+        let still_infectious = |stages| {
+            SirModel::new(1.0, 0.0, 0.2, 1.0)
+                .unwrap()
+                .with_infectious_stages(stages)
+                .unwrap()
+                .solve(Solver::default(), &[0.0, 2.0, 5.0, 15.0])
+                .unwrap()
+                .iter()
+                .map(|sample| sample.i)
+                .collect::<Vec<_>>()
+        };
+        let exponential = still_infectious(1);
+        let erlang = still_infectious(4);
+        assert!((exponential[2] - (-1.0f64).exp()).abs() < 1e-4);
+        assert!(erlang[1] > exponential[1] && erlang[3] < exponential[3]);
+
+        let mut model = SirModel::new(1000.0, 1.0, 0.2, 1.0)
+            .unwrap()
+            .with_infectious_stages(3)
+            .unwrap();
+        for sample in &model.run(100) {
+            assert!((sample.s + sample.i + sample.r - 1000.0).abs() < 1e-6);
+        }
+
+        assert_eq!(
+            SirModel::new(1000.0, 1.0, 0.2, 1.0)
+                .unwrap()
+                .with_infectious_stages(0)
+                .unwrap_err()
+                .field,
+            "infectious_stages"
+        );
+    }
+}
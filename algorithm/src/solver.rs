@@ -0,0 +1,379 @@
+//! Numerical integration of compartmental models with `ode_solvers`.
+
+use std::fmt;
+
+use ode_solvers::dop853::Dop853;
+use ode_solvers::dop_shared::OutputType;
+use ode_solvers::dopri5::Dopri5;
+use ode_solvers::rk4::Rk4;
+use ode_solvers::{DVector, System};
+
+use crate::error::{self, ParamError};
+use crate::trajectory::Trajectory;
+
+/// A compartmental model expressed as a system of ordinary differential
+/// equations over its compartment sizes.
+pub trait OdeModel {
+    type Sample;
+
+    /// The time at which `initial_state` applies.
+    fn initial_time(&self) -> f64;
+
+    fn initial_state(&self) -> Vec<f64>;
+
+    /// Writes the rate of change of every compartment at `(t, y)` into `dy`.
+    fn derivative(&self, t: f64, y: &[f64], dy: &mut [f64]);
+
+    fn sample(&self, t: f64, y: &[f64]) -> Self::Sample;
+}
+
+/// The integration method used by [`solve`].
+///
+/// [`solve`]: fn.solve.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Solver {
+    /// Classic fourth order Runge-Kutta with a fixed step, in days.
+    Rk4 { step: f64 },
+    /// Adaptive Dormand-Prince method of order 5(4).
+    Dopri5 { rtol: f64, atol: f64 },
+    /// Adaptive Dormand-Prince method of order 8(5,3).
+    Dop853 { rtol: f64, atol: f64 },
+}
+
+impl Solver {
+    pub(crate) fn validate(self) -> Result<Solver, ParamError> {
+        match self {
+            Solver::Rk4 { step } => {
+                error::positive("step", step)?;
+            }
+            Solver::Dopri5 { rtol, atol } | Solver::Dop853 { rtol, atol } => {
+                error::positive("rtol", rtol)?;
+                error::positive("atol", atol)?;
+            }
+        }
+        Ok(self)
+    }
+}
+
+impl Default for Solver {
+    fn default() -> Solver {
+        Solver::Dopri5 {
+            rtol: 1e-8,
+            atol: 1e-8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SolveError {
+    /// The solver was configured with an invalid step or tolerance.
+    Solver(ParamError),
+    /// The requested output times were empty, not finite, not increasing or
+    /// earlier than the model's initial time.
+    Times,
+    /// The integrator gave up before reaching time `t`.
+    Integration { t: f64, message: String },
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveError::Solver(e) => write!(f, "invalid solver: {}", e),
+            SolveError::Times => write!(
+                f,
+                "output times must be finite, increasing and not before the initial time"
+            ),
+            SolveError::Integration { t, message } => {
+                write!(f, "integration failed before t = {}: {}", t, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SolveError {}
+
+impl From<ParamError> for SolveError {
+    fn from(e: ParamError) -> SolveError {
+        SolveError::Solver(e)
+    }
+}
+
+struct Equations<'a, M>(&'a M);
+
+impl<M: OdeModel> System<DVector<f64>> for Equations<'_, M> {
+    fn system(&self, t: f64, y: &DVector<f64>, dy: &mut DVector<f64>) {
+        self.0.derivative(t, y.as_slice(), dy.as_mut_slice());
+    }
+}
+
+/// Integrates `model` from its initial state and samples it at each of
+/// `times`, which must be increasing.
+///
+/// The whole span is integrated in one go, taking no step longer than the
+/// longest gap between output times. Samples that fall between steps are
+/// interpolated with the cubic through the states and derivatives at the
+/// steps either side.
+pub fn solve<M: OdeModel>(
+    model: &M,
+    solver: Solver,
+    times: &[f64],
+) -> Result<Trajectory<M::Sample>, SolveError> {
+    let solver = solver.validate()?;
+
+    let t0 = model.initial_time();
+    let mut previous = t0;
+    let mut longest_gap: f64 = 0.0;
+    for &time in times {
+        if !time.is_finite() || time < previous {
+            return Err(SolveError::Times);
+        }
+        longest_gap = longest_gap.max(time - previous);
+        previous = time;
+    }
+    if times.is_empty() {
+        return Err(SolveError::Times);
+    }
+
+    let y0 = DVector::from_vec(model.initial_state());
+    let (steps, states) = if previous > t0 {
+        integrate(model, solver, t0, previous, longest_gap, y0)?
+    } else {
+        (vec![t0], vec![y0])
+    };
+
+    let mut trajectory = Trajectory::with_capacity(times.len());
+    let mut k = 0;
+    for &time in times {
+        while k + 1 < steps.len() && steps[k + 1] < time {
+            k += 1;
+        }
+        let sample = if time == steps[k] {
+            model.sample(time, states[k].as_slice())
+        } else if time == steps[k + 1] {
+            model.sample(time, states[k + 1].as_slice())
+        } else {
+            let y = interpolate(
+                model,
+                (steps[k], states[k].as_slice()),
+                (steps[k + 1], states[k + 1].as_slice()),
+                time,
+            );
+            model.sample(time, &y)
+        };
+        trajectory.push(sample);
+    }
+    Ok(trajectory)
+}
+
+/// Integrates from `t0` to `t1` and returns the time and state after every
+/// step, starting with `t0` and `y0`.
+fn integrate<M: OdeModel>(
+    model: &M,
+    solver: Solver,
+    t0: f64,
+    t1: f64,
+    max_step: f64,
+    y0: DVector<f64>,
+) -> Result<(Vec<f64>, Vec<DVector<f64>>), SolveError> {
+    let equations = Equations(model);
+    let failed = |e| SolveError::Integration {
+        t: t1,
+        message: format!("{:?}", e),
+    };
+
+    // The adaptive methods are set up as by their `new`, except that they
+    // keep every step rather than dense output at a fixed interval.
+    let (steps, states) = match solver {
+        Solver::Rk4 { step } => {
+            // Shrink the step so that a whole number of them ends exactly on `t1`.
+            let count = ((t1 - t0) / step).ceil().max(1.0);
+            let mut stepper = Rk4::new(equations, t0, y0, t1, (t1 - t0) / count);
+            stepper.integrate().map_err(failed)?;
+            (stepper.x_out().clone(), stepper.y_out().clone())
+        }
+        Solver::Dopri5 { rtol, atol } => {
+            let mut stepper = Dopri5::from_param(
+                equations,
+                t0,
+                t1,
+                0.0,
+                y0,
+                rtol,
+                atol,
+                0.9,
+                0.04,
+                0.2,
+                10.0,
+                max_step,
+                0.0,
+                100_000,
+                1000,
+                OutputType::Sparse,
+            );
+            stepper.integrate().map_err(failed)?;
+            (stepper.x_out().clone(), stepper.y_out().clone())
+        }
+        Solver::Dop853 { rtol, atol } => {
+            let mut stepper = Dop853::from_param(
+                equations,
+                t0,
+                t1,
+                0.0,
+                y0,
+                rtol,
+                atol,
+                0.9,
+                0.0,
+                0.333,
+                6.0,
+                max_step,
+                0.0,
+                100_000,
+                1000,
+                OutputType::Sparse,
+            );
+            stepper.integrate().map_err(failed)?;
+            (stepper.x_out().clone(), stepper.y_out().clone())
+        }
+    };
+
+    // Rounding can leave the last step a hair either side of `t1`, or a
+    // fixed step just past it.
+    let mut steps = steps;
+    match steps.last_mut() {
+        Some(t) if (*t - t1).abs() <= 1e-9 * t1.abs().max(1.0) => {
+            *t = t1;
+            Ok((steps, states))
+        }
+        Some(t) if *t > t1 => Ok((steps, states)),
+        _ => Err(SolveError::Integration {
+            t: t1,
+            message: "solver stopped short of the end".to_string(),
+        }),
+    }
+}
+
+/// Evaluates at `t` the cubic through `y0` and `y1` at times `t0` and `t1`
+/// with the model's derivatives there as its slopes.
+fn interpolate<M: OdeModel>(
+    model: &M,
+    (t0, y0): (f64, &[f64]),
+    (t1, y1): (f64, &[f64]),
+    t: f64,
+) -> Vec<f64> {
+    let mut dy0 = vec![0.0; y0.len()];
+    let mut dy1 = vec![0.0; y1.len()];
+    model.derivative(t0, y0, &mut dy0);
+    model.derivative(t1, y1, &mut dy1);
+
+    let h = t1 - t0;
+    let s = (t - t0) / h;
+    let h00 = (1.0 + 2.0 * s) * (1.0 - s) * (1.0 - s);
+    let h10 = s * (1.0 - s) * (1.0 - s);
+    let h01 = s * s * (3.0 - 2.0 * s);
+    let h11 = s * s * (s - 1.0);
+    (0..y0.len())
+        .map(|i| h00 * y0[i] + h10 * h * dy0[i] + h01 * y1[i] + h11 * h * dy1[i])
+        .collect()
+}
+
+/// `days + 1` output times, one per day starting at `start`.
+pub fn daily_times(start: f64, days: usize) -> Vec<f64> {
+    (0..=days).map(|day| start + day as f64).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SeirModel, SirModel};
+
+    #[test]
+    fn rejects_bad_times_and_tolerances() {
+        let model = SirModel::new(1000.0, 1.0, 0.2, 1.0).unwrap();
+
+        assert_eq!(
+            solve(&model, Solver::default(), &[]).unwrap_err(),
+            SolveError::Times
+        );
+        assert_eq!(
+            solve(&model, Solver::default(), &[2.0, 1.0]).unwrap_err(),
+            SolveError::Times
+        );
+        assert_eq!(
+            solve(&model, Solver::default(), &[-1.0]).unwrap_err(),
+            SolveError::Times
+        );
+        assert!(matches!(
+            solve(&model, Solver::Rk4 { step: 0.0 }, &[1.0]),
+            Err(SolveError::Solver(_))
+        ));
+    }
+
+    #[test]
+    fn samples_at_requested_times() {
+        let model = SeirModel::new(1000.0, 1.0, 0.5, 0.2, 1.0).unwrap();
+        let times = [0.0, 0.5, 3.25, 10.0, 10.0, 40.0];
+        let trajectory = solve(&model, Solver::Rk4 { step: 0.1 }, &times).unwrap();
+
+        let sampled: Vec<f64> = trajectory.iter().map(|x| x.t).collect();
+        assert_eq!(sampled, times);
+        assert_eq!(trajectory.first().unwrap(), &model.state());
+    }
+
+    #[test]
+    fn interpolated_samples_match_integrating_to_them() {
+        let model = SeirModel::new(1000.0, 1.0, 0.5, 0.2, 1.0).unwrap();
+        let times = [0.0, 2.5, 7.0, 13.75, 30.0];
+        for solver in &[Solver::Rk4 { step: 0.01 }, Solver::default()] {
+            let trajectory = solve(&model, *solver, &times).unwrap();
+            for (sample, &time) in trajectory.iter().zip(&times) {
+                let direct = *solve(&model, *solver, &[time]).unwrap().last().unwrap();
+                assert!(
+                    (sample.e - direct.e).abs() < 1e-4,
+                    "{:?} at {}",
+                    solver,
+                    time
+                );
+                assert!(
+                    (sample.i - direct.i).abs() < 1e-4,
+                    "{:?} at {}",
+                    solver,
+                    time
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn large_beta_does_not_overshoot() {
+        let mut model = SirModel::new(1000.0, 5.0, 0.2, 1.0).unwrap();
+        let times = daily_times(0.0, 60);
+
+        for solver in &[
+            Solver::Rk4 { step: 0.05 },
+            Solver::default(),
+            Solver::Dop853 {
+                rtol: 1e-8,
+                atol: 1e-8,
+            },
+        ] {
+            for sample in &solve(&model, *solver, &times).unwrap() {
+                assert!(sample.s >= 0.0 && sample.i >= 0.0);
+                assert!((sample.s + sample.i + sample.r - 1000.0).abs() < 1e-6);
+            }
+        }
+        // The one-step-per-day update cannot keep up.
+        assert!(model.run(60).iter().any(|x| x.s < 0.0));
+    }
+
+    #[test]
+    fn reaches_the_final_size_relation() {
+        // In the limit, the susceptible fraction s satisfies
+        // ln(s) = -R0 * (1 - s) for an SIR epidemic started from a tiny seed.
+        let model = SirModel::new(1e6, 0.5, 0.25, 1.0).unwrap();
+        let trajectory = solve(&model, Solver::default(), &[0.0, 2000.0]).unwrap();
+        let s = trajectory.last().unwrap().s / 1e6;
+
+        assert!((s.ln() + 2.0 * (1.0 - s)).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,155 @@
+//! Uniform grid spatial index for neighbour queries in the arena.
+
+use crate::geometry::Vec2;
+
+/// Buckets points into square cells so that everything within a radius of a
+/// point can be found by looking at a handful of nearby cells.
+///
+/// Cells are stored as a counting sort over a flat array rather than a hash
+/// map, which keeps rebuilds allocation free and iteration order
+/// deterministic.
+#[derive(Debug, Clone)]
+pub struct SpatialGrid {
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    /// `starts[c]..starts[c + 1]` is the range of `indices` in cell `c`.
+    starts: Vec<usize>,
+    indices: Vec<usize>,
+    cells: Vec<usize>,
+}
+
+/// Upper bound on cells along either axis, so a tiny radius in a huge arena
+/// does not allocate a gigantic grid.
+const MAX_CELLS_PER_AXIS: f32 = 1024.0;
+
+impl SpatialGrid {
+    /// Creates an empty grid covering `0..=width` by `0..=height`. Queries are
+    /// cheapest when `cell_size` matches the typical query radius.
+    pub fn new(width: f32, height: f32, cell_size: f32) -> SpatialGrid {
+        let cell_size = cell_size
+            .max(width / MAX_CELLS_PER_AXIS)
+            .max(height / MAX_CELLS_PER_AXIS)
+            .max(f32::MIN_POSITIVE);
+        let columns = ((width / cell_size).ceil() as usize).max(1);
+        let rows = ((height / cell_size).ceil() as usize).max(1);
+
+        SpatialGrid {
+            cell_size,
+            columns,
+            rows,
+            starts: vec![0; columns * rows + 1],
+            indices: Vec::new(),
+            cells: Vec::new(),
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    fn cell_coords(&self, position: Vec2) -> (usize, usize) {
+        let column = (position.x / self.cell_size).max(0.0) as usize;
+        let row = (position.y / self.cell_size).max(0.0) as usize;
+        (column.min(self.columns - 1), row.min(self.rows - 1))
+    }
+
+    /// Replaces the contents of the grid with `positions`, which are later
+    /// referred to by their index in the slice.
+    pub fn rebuild(&mut self, positions: &[Vec2]) {
+        self.cells.clear();
+        for &position in positions {
+            let (column, row) = self.cell_coords(position);
+            self.cells.push(row * self.columns + column);
+        }
+
+        for start in &mut self.starts {
+            *start = 0;
+        }
+        for &cell in &self.cells {
+            self.starts[cell] += 1;
+        }
+        for cell in 1..self.starts.len() {
+            self.starts[cell] += self.starts[cell - 1];
+        }
+
+        // Each start now points one past the end of its cell, so filling the
+        // cells back to front leaves it pointing at the beginning.
+        self.indices.clear();
+        self.indices.resize(positions.len(), 0);
+        for (index, &cell) in self.cells.iter().enumerate().rev() {
+            self.starts[cell] -= 1;
+            self.indices[self.starts[cell]] = index;
+        }
+    }
+
+    /// Calls `f` with the index of every point in a cell that overlaps the
+    /// square around `center` with half-width `radius`. Callers still need to
+    /// check the exact distance.
+    pub fn for_each_candidate<F: FnMut(usize)>(&self, center: Vec2, radius: f32, mut f: F) {
+        let (min_column, min_row) = self.cell_coords(center - Vec2::new(radius, radius));
+        let (max_column, max_row) = self.cell_coords(center + Vec2::new(radius, radius));
+
+        for row in min_row..=max_row {
+            let first = row * self.columns + min_column;
+            let last = row * self.columns + max_column;
+            for &index in &self.indices[self.starts[first]..self.starts[last + 1]] {
+                f(index);
+            }
+        }
+    }
+
+    /// Collects the indices of every point within `radius` of `center`.
+    pub fn within(&self, positions: &[Vec2], center: Vec2, radius: f32) -> Vec<usize> {
+        let radius_squared = radius * radius;
+        let mut found = Vec::new();
+        self.for_each_candidate(center, radius, |index| {
+            if positions[index].distance_squared(center) <= radius_squared {
+                found.push(index);
+            }
+        });
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_pcg::Pcg64Mcg;
+
+    #[test]
+    fn matches_brute_force() {
+        let mut rng = Pcg64Mcg::seed_from_u64(3);
+        let positions: Vec<Vec2> = (0..2000)
+            .map(|_| Vec2::new(rng.gen_range(0.0, 500.0), rng.gen_range(0.0, 300.0)))
+            .collect();
+        let mut grid = SpatialGrid::new(500.0, 300.0, 10.0);
+        grid.rebuild(&positions);
+
+        for &radius in &[0.0, 4.0, 10.0, 35.0] {
+            for &center in positions.iter().take(100) {
+                let mut found = grid.within(&positions, center, radius);
+                found.sort();
+                let expected: Vec<usize> = (0..positions.len())
+                    .filter(|&i| positions[i].distance_squared(center) <= radius * radius)
+                    .collect();
+                assert_eq!(found, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn handles_points_on_and_outside_the_edges() {
+        let positions = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(100.0, 100.0),
+            Vec2::new(-5.0, 120.0),
+        ];
+        let mut grid = SpatialGrid::new(100.0, 100.0, 30.0);
+        grid.rebuild(&positions);
+
+        assert_eq!(grid.within(&positions, Vec2::new(100.0, 100.0), 1.0), [1]);
+        assert_eq!(grid.within(&positions, Vec2::new(-5.0, 120.0), 1.0), [2]);
+    }
+}
@@ -0,0 +1,570 @@
+//! Parameter sweeps: running a scenario across a range of parameter values.
+//!
+//! A sweep varies one or more numeric fields of a scenario, named by their
+//! path such as `model.sir.beta`, runs an ensemble of seeds at every point
+//! and tabulates peak size, peak time and final size per point:
+//!
+//! ```toml
+//! replicates = 50
+//!
+//! [[parameters]]
+//! name = "model.sir.beta"
+//! values = { linear = { from = 0.1, to = 1.0, count = 20 } }
+//! ```
+//!
+//! Points are either every combination of the parameters' values, or a
+//! Latin hypercube sample that covers the ranges with far fewer runs.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::ensemble::{self, Ensemble, Outcome, OutcomeDistribution};
+use crate::error::{self, ParamError};
+use crate::rng;
+use crate::scenario::{self, Scenario, ScenarioError};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sweep {
+    pub parameters: Vec<Parameter>,
+    #[serde(default)]
+    pub sampling: Sampling,
+    ///runs per point
+    #[serde(default = "default_replicates")]
+    pub replicates: usize,
+    ///replicate `n` of every point is seeded with `seed + n`, and the Latin
+    ///hypercube is drawn from `seed`
+    #[serde(default)]
+    pub seed: u64,
+    ///percentiles reported besides the mean and median, between 0 and 100
+    #[serde(default = "default_percentiles")]
+    pub percentiles: Vec<f64>,
+}
+
+fn default_replicates() -> usize {
+    Ensemble::default().replicates
+}
+
+fn default_percentiles() -> Vec<f64> {
+    Ensemble::default().percentiles
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Parameter {
+    ///path to a numeric field of the scenario, like `model.world.population`
+    ///or `interventions.0.action.beta`
+    pub name: String,
+    pub values: Values,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Values {
+    /// `count` evenly spaced values from `from` to `to`, inclusive.
+    Linear {
+        from: f64,
+        to: f64,
+        count: usize,
+    },
+    /// `count` values from `from` to `to` spaced evenly on a log scale.
+    Log {
+        from: f64,
+        to: f64,
+        count: usize,
+    },
+    List(Vec<f64>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Sampling {
+    /// Every combination of every parameter's values.
+    #[default]
+    Grid,
+    /// `samples` points, splitting each parameter's range into as many
+    /// strata and sampling every stratum exactly once.
+    LatinHypercube { samples: usize },
+}
+
+impl Values {
+    fn validate(&self) -> Result<(), ParamError> {
+        match *self {
+            Values::Linear { from, to, count } => {
+                if !from.is_finite() || !to.is_finite() {
+                    return Err(ParamError::new("values.linear", "must be finite"));
+                }
+                error::positive("values.linear.count", count as f64)?;
+            }
+            Values::Log { from, to, count } => {
+                error::positive("values.log.from", from)?;
+                error::positive("values.log.to", to)?;
+                error::positive("values.log.count", count as f64)?;
+            }
+            Values::List(ref values) => {
+                if values.is_empty() || values.iter().any(|v| !v.is_finite()) {
+                    return Err(ParamError::new(
+                        "values.list",
+                        "must hold at least one finite number",
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Every value, in order.
+    pub fn expand(&self) -> Vec<f64> {
+        match *self {
+            Values::Linear { from, to, count } => spaced(from, to, count),
+            Values::Log { from, to, count } => spaced(libm::log(from), libm::log(to), count)
+                .into_iter()
+                .map(libm::exp)
+                .collect(),
+            Values::List(ref values) => values.clone(),
+        }
+    }
+
+    /// Maps `u` in `0..1` onto the range.
+    fn at(&self, u: f64) -> f64 {
+        match *self {
+            Values::Linear { from, to, .. } => from + u * (to - from),
+            Values::Log { from, to, .. } => {
+                libm::exp(libm::log(from) + u * (libm::log(to) - libm::log(from)))
+            }
+            Values::List(ref values) => {
+                values[((u * values.len() as f64) as usize).min(values.len() - 1)]
+            }
+        }
+    }
+}
+
+fn spaced(from: f64, to: f64, count: usize) -> Vec<f64> {
+    if count == 1 {
+        return vec![from];
+    }
+    let step = (to - from) / (count - 1) as f64;
+    (0..count).map(|n| from + n as f64 * step).collect()
+}
+
+/// The outcome of every run at one point of a sweep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+    ///value of each parameter, in the order of `Sweep::parameters`
+    pub values: Vec<f64>,
+    pub peak_size: OutcomeDistribution,
+    pub peak_time: OutcomeDistribution,
+    pub final_size: OutcomeDistribution,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepTable {
+    pub parameters: Vec<String>,
+    pub percentiles: Vec<f64>,
+    pub points: Vec<Point>,
+}
+
+impl Sweep {
+    pub fn from_toml(text: &str) -> Result<Sweep, ScenarioError> {
+        let sweep: Sweep = scenario::parse_toml(text)?;
+        sweep.validate()?;
+        Ok(sweep)
+    }
+
+    pub fn from_json(text: &str) -> Result<Sweep, ScenarioError> {
+        let sweep: Sweep = scenario::parse_json(text)?;
+        sweep.validate()?;
+        Ok(sweep)
+    }
+
+    /// Reads a sweep from a `.json` file, or from TOML otherwise.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Sweep, ScenarioError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Sweep::from_json(&text),
+            _ => Sweep::from_toml(&text),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ScenarioError> {
+        let ensemble = Ensemble {
+            replicates: self.replicates,
+            seed: self.seed,
+            percentiles: self.percentiles.clone(),
+        };
+        ensemble.validate()?;
+        if self.parameters.is_empty() {
+            return Err(ScenarioError::Invalid {
+                path: "parameters".to_string(),
+                reason: "must name at least one parameter",
+            });
+        }
+        for (n, parameter) in self.parameters.iter().enumerate() {
+            parameter
+                .values
+                .validate()
+                .map_err(|e| ScenarioError::invalid(&format!("parameters[{}]", n), e))?;
+        }
+        if let Sampling::LatinHypercube { samples } = self.sampling {
+            error::positive("sampling.latin_hypercube.samples", samples as f64)?;
+        }
+        Ok(())
+    }
+
+    /// The parameter values of every point of the sweep.
+    pub fn points(&self) -> Vec<Vec<f64>> {
+        match self.sampling {
+            Sampling::Grid => {
+                let mut points = vec![Vec::new()];
+                for parameter in &self.parameters {
+                    let values = parameter.values.expand();
+                    points = points
+                        .iter()
+                        .flat_map(|point| {
+                            values.iter().map(move |&value| {
+                                let mut point = point.clone();
+                                point.push(value);
+                                point
+                            })
+                        })
+                        .collect();
+                }
+                points
+            }
+            Sampling::LatinHypercube { samples } => {
+                let mut rng = rng::seeded(self.seed);
+                let mut points = vec![Vec::with_capacity(self.parameters.len()); samples];
+                for parameter in &self.parameters {
+                    let mut strata: Vec<usize> = (0..samples).collect();
+                    strata.shuffle(&mut rng);
+                    for (point, stratum) in points.iter_mut().zip(strata) {
+                        let u = (stratum as f64 + rng.gen::<f64>()) / samples as f64;
+                        point.push(parameter.values.at(u));
+                    }
+                }
+                points
+            }
+        }
+    }
+
+    /// Runs `replicates` seeds of `input` at every point.
+    pub fn run(&self, input: &Scenario) -> Result<SweepTable, ScenarioError> {
+        self.validate()?;
+        let base = serde_json::to_value(input).expect("scenarios serialize to JSON");
+
+        let mut points = Vec::new();
+        for mut values in self.points() {
+            let mut document = base.clone();
+            for (parameter, value) in self.parameters.iter().zip(&mut values) {
+                *value = set(&mut document, &parameter.name, *value)?;
+            }
+            let mut scenario: Scenario =
+                serde_path_to_error::deserialize(document).map_err(ScenarioError::parse)?;
+            scenario.validate()?;
+            // The edge list is not serialized, so reuse the one `input` read
+            // rather than reading it again on every run.
+            scenario.edge_list = input.edge_list.clone();
+
+            let seeds = (0..self.replicates as u64)
+                .map(|n| self.seed.wrapping_add(n))
+                .collect();
+            let outcomes = ensemble::map_seeds(seeds, |seed| {
+                scenario.run(seed).map(|run| run.output.outcome())
+            })
+            .into_iter()
+            .collect::<Result<Vec<Outcome>, _>>()?;
+
+            let (peak_size, peak_time, final_size) =
+                Outcome::distributions(&outcomes, &self.percentiles);
+            points.push(Point {
+                values,
+                peak_size,
+                peak_time,
+                final_size,
+            });
+        }
+
+        Ok(SweepTable {
+            parameters: self.parameters.iter().map(|p| p.name.clone()).collect(),
+            percentiles: self.percentiles.clone(),
+            points,
+        })
+    }
+}
+
+/// Sets the field at the dotted `path` of a serialized scenario to `value`
+/// and returns the value actually used.
+fn set(document: &mut Value, path: &str, value: f64) -> Result<f64, ScenarioError> {
+    let pointer = format!("/{}", path.replace('.', "/"));
+    let field = document
+        .pointer_mut(&pointer)
+        .filter(|field| field.is_number())
+        .ok_or_else(|| ScenarioError::Invalid {
+            path: path.to_string(),
+            reason: "is not a numeric field of the scenario",
+        })?;
+    if !field.is_u64() {
+        *field = Value::from(value);
+        return Ok(value);
+    }
+    // Counts like `population` only deserialize from whole numbers, so
+    // values in between are rounded and the table records the rounded one.
+    let value = value.round();
+    if value < 0.0 {
+        return Err(ScenarioError::Invalid {
+            path: path.to_string(),
+            reason: "is a count and must not be negative",
+        });
+    }
+    *field = Value::from(value as u64);
+    Ok(value)
+}
+
+impl SweepTable {
+    /// Writes a tidy table with one row per point and outcome metric:
+    ///
+    /// ```text
+    /// point,model.sir.beta,metric,mean,median,p2.5,p97.5
+    /// 0,0.1,peak_size,3.2,3,1,6
+    /// ```
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "point")?;
+        for parameter in &self.parameters {
+            write!(writer, ",{}", parameter)?;
+        }
+        write!(writer, ",metric,mean,median")?;
+        for percentile in &self.percentiles {
+            write!(writer, ",p{}", percentile)?;
+        }
+        writeln!(writer)?;
+
+        for (n, point) in self.points.iter().enumerate() {
+            let metrics = [
+                ("peak_size", &point.peak_size),
+                ("peak_time", &point.peak_time),
+                ("final_size", &point.final_size),
+            ];
+            for (metric, distribution) in metrics.iter() {
+                write!(writer, "{}", n)?;
+                for value in &point.values {
+                    write!(writer, ",{}", value)?;
+                }
+                write!(
+                    writer,
+                    ",{},{},{}",
+                    metric, distribution.mean, distribution.median
+                )?;
+                for (_, value) in &distribution.percentiles {
+                    write!(writer, ",{}", value)?;
+                }
+                writeln!(writer)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sweep(parameters: Vec<Parameter>, sampling: Sampling) -> Sweep {
+        Sweep {
+            parameters,
+            sampling,
+            replicates: 4,
+            seed: 0,
+            percentiles: vec![5.0, 95.0],
+        }
+    }
+
+    fn parameter(name: &str, values: Values) -> Parameter {
+        Parameter {
+            name: name.to_string(),
+            values,
+        }
+    }
+
+    #[test]
+    fn example_sweep_loads() {
+        let sweep = Sweep::from_toml(include_str!("../../scenarios/beta-sweep.toml")).unwrap();
+        assert_eq!(sweep.points().len(), 20);
+        Scenario::from_toml(include_str!("../../scenarios/sir.toml")).unwrap();
+    }
+
+    #[test]
+    fn expands_values() {
+        let linear = Values::Linear {
+            from: 0.0,
+            to: 1.0,
+            count: 5,
+        };
+        assert_eq!(linear.expand(), [0.0, 0.25, 0.5, 0.75, 1.0]);
+        let log = Values::Log {
+            from: 1.0,
+            to: 100.0,
+            count: 3,
+        };
+        let log = log.expand();
+        assert!((log[1] - 10.0).abs() < 1e-9 && (log[2] - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn grid_covers_every_combination() {
+        let sweep = sweep(
+            vec![
+                parameter("a", Values::List(vec![1.0, 2.0])),
+                parameter("b", Values::List(vec![3.0, 4.0, 5.0])),
+            ],
+            Sampling::Grid,
+        );
+        let points = sweep.points();
+        assert_eq!(points.len(), 6);
+        assert_eq!(points[0], [1.0, 3.0]);
+        assert_eq!(points[5], [2.0, 5.0]);
+    }
+
+    #[test]
+    fn latin_hypercube_hits_every_stratum() {
+        let range = Values::Linear {
+            from: 0.0,
+            to: 10.0,
+            count: 1,
+        };
+        let sweep = sweep(
+            vec![parameter("a", range.clone()), parameter("b", range)],
+            Sampling::LatinHypercube { samples: 10 },
+        );
+        let points = sweep.points();
+        assert_eq!(points.len(), 10);
+        for axis in 0..2 {
+            let mut strata: Vec<usize> = points.iter().map(|p| p[axis] as usize).collect();
+            strata.sort();
+            assert_eq!(strata, (0..10).collect::<Vec<_>>());
+        }
+        assert_eq!(points, sweep.points());
+    }
+
+    #[test]
+    fn peaks_grow_with_beta() {
+        let scenario = Scenario::from_toml(
+            "days = 200\n[model.sir]\npopulation = 1000\nbeta = 0.3\ngamma = 0.1",
+        )
+        .unwrap();
+        let sweep = sweep(
+            vec![parameter(
+                "model.sir.beta",
+                Values::Linear {
+                    from: 0.2,
+                    to: 0.6,
+                    count: 5,
+                },
+            )],
+            Sampling::Grid,
+        );
+        let table = sweep.run(&scenario).unwrap();
+
+        let peaks: Vec<f64> = table.points.iter().map(|p| p.peak_size.mean).collect();
+        assert!(peaks.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let mut csv = Vec::new();
+        table.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(
+            csv.lines().next().unwrap(),
+            "point,model.sir.beta,metric,mean,median,p5,p95"
+        );
+        assert_eq!(csv.lines().count(), 1 + 5 * 3);
+    }
+
+    #[test]
+    fn rejects_unknown_parameters() {
+        let scenario = Scenario::from_toml("days = 5\n[model.world]\npopulation = 50").unwrap();
+        let sweep = sweep(
+            vec![parameter("model.world.speed", Values::List(vec![1.0]))],
+            Sampling::Grid,
+        );
+        let error = sweep.run(&scenario).unwrap_err();
+        assert_eq!(error.path(), Some("model.world.speed"));
+
+        let sweep = Sweep {
+            parameters: vec![parameter(
+                "model.world.population",
+                Values::List(vec![20.0]),
+            )],
+            ..sweep
+        };
+        let table = sweep.run(&scenario).unwrap();
+        assert!(table.points[0].final_size.values.iter().all(|&r| r <= 20.0));
+    }
+
+    #[test]
+    fn replicates_reuse_the_edge_list_they_were_loaded_with() {
+        let directory =
+            std::env::temp_dir().join(format!("lazaretto-sweep-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let edges = directory.join("contacts.csv");
+        let ring: String = (0..50)
+            .map(|n| format!("{},{}\n", n, (n + 1) % 50))
+            .collect();
+        fs::write(&edges, format!("source,target\n{}", ring)).unwrap();
+        let path = directory.join("ring.toml");
+        fs::write(
+            &path,
+            "days = 30\n[model.network]\npopulation = 50\ninitial_infected = 1\n\
+             transmission_rate = 1\ngraph = { edge_list = \"contacts.csv\" }\n",
+        )
+        .unwrap();
+        let scenario = Scenario::load(&path).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let sweep = Sweep {
+            replicates: 2,
+            seed: 3,
+            ..sweep(
+                vec![parameter(
+                    "model.network.transmission_rate",
+                    Values::List(vec![1.0]),
+                )],
+                Sampling::Grid,
+            )
+        };
+        let table = sweep.run(&scenario).unwrap();
+        let mut expected: Vec<f64> = (3..5)
+            .map(|seed| scenario.run(seed).unwrap().output.outcome().final_size)
+            .collect();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(table.points[0].final_size.values, expected);
+    }
+
+    #[test]
+    fn rounds_counts_and_records_the_rounded_value() {
+        let scenario = Scenario::from_toml("days = 5\n[model.world]\npopulation = 50").unwrap();
+        let sweep = sweep(
+            vec![parameter(
+                "model.world.population",
+                Values::List(vec![20.4]),
+            )],
+            Sampling::Grid,
+        );
+        let table = sweep.run(&scenario).unwrap();
+        assert_eq!(table.points[0].values, [20.0]);
+
+        let sweep = Sweep {
+            parameters: vec![parameter(
+                "model.world.population",
+                Values::List(vec![-3.0]),
+            )],
+            ..sweep
+        };
+        let error = sweep.run(&scenario).unwrap_err();
+        assert_eq!(error.path(), Some("model.world.population"));
+    }
+}
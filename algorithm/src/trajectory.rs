@@ -0,0 +1,117 @@
+use serde::Serialize;
+
+/// A snapshot of a model at a point in time.
+pub trait Sample: Clone {
+    /// Names of the quantities returned by `values`, in the same order.
+    const COMPARTMENTS: &'static [&'static str];
+
+    fn time(&self) -> f64;
+
+    /// Every quantity of the sample other than its time.
+    fn values(&self) -> Vec<f64>;
+
+    /// How many people are currently infectious.
+    fn infectious(&self) -> f64;
+
+    /// How many people have been infected and are no longer infectious.
+    fn removed(&self) -> f64;
+
+    /// The same state, relabelled as happening at `t`.
+    fn at_time(&self, t: f64) -> Self;
+}
+
+/// A time-ordered series of model samples.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Trajectory<S> {
+    samples: Vec<S>,
+}
+
+impl<S> Trajectory<S> {
+    pub fn new() -> Trajectory<S> {
+        Trajectory {
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Trajectory<S> {
+        Trajectory {
+            samples: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, sample: S) {
+        self.samples.push(sample);
+    }
+
+    pub fn samples(&self) -> &[S] {
+        &self.samples
+    }
+
+    pub fn into_samples(self) -> Vec<S> {
+        self.samples
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn first(&self) -> Option<&S> {
+        self.samples.first()
+    }
+
+    pub fn last(&self) -> Option<&S> {
+        self.samples.last()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, S> {
+        self.samples.iter()
+    }
+}
+
+impl<S: Sample> Trajectory<S> {
+    /// The state at each of `times`, holding every sample until the next one.
+    ///
+    /// This turns the irregular event times of a stochastic simulation into a
+    /// regular grid. Times before the first sample take on its state.
+    pub fn resample(&self, times: &[f64]) -> Trajectory<S> {
+        let mut resampled = Trajectory::with_capacity(times.len());
+        if self.samples.is_empty() {
+            return resampled;
+        }
+
+        let mut current = 0;
+        for &t in times {
+            while current + 1 < self.samples.len() && self.samples[current + 1].time() <= t {
+                current += 1;
+            }
+            resampled.push(self.samples[current].at_time(t));
+        }
+        resampled
+    }
+}
+
+impl<S> Default for Trajectory<S> {
+    fn default() -> Trajectory<S> {
+        Trajectory::new()
+    }
+}
+
+impl<S> From<Vec<S>> for Trajectory<S> {
+    fn from(samples: Vec<S>) -> Trajectory<S> {
+        Trajectory { samples }
+    }
+}
+
+impl<'a, S> IntoIterator for &'a Trajectory<S> {
+    type Item = &'a S;
+    type IntoIter = std::slice::Iter<'a, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.samples.iter()
+    }
+}
@@ -0,0 +1,270 @@
+//! Who infected whom in the agent-based model.
+//!
+//! Every infection is recorded as it happens, building a forest with one tree
+//! per initial case. The forest can be written out for Graphviz or as Newick
+//! trees for phylogenetics tools, and summarised as the number of secondary
+//! cases each person caused.
+
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::geometry::Vec2;
+use crate::location::LocationKind;
+use crate::Status;
+
+/// A single infection. People are identified by their index in
+/// `World::people`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Infection {
+    pub t: f64,
+    ///`None` for the people infected at the start
+    pub infector: Option<usize>,
+    pub infectee: usize,
+    ///where the infectee was when they were infected
+    pub location: Vec2,
+    pub setting: Setting,
+    ///status of the infector at the time of the infection
+    pub infector_status: Option<Status>,
+    ///whether the infector was on the symptomatic branch
+    pub infector_symptomatic: Option<bool>,
+}
+
+/// Where an infection happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Setting {
+    /// One of the people infected at the start.
+    Initial,
+    /// Between people who came close to each other.
+    Community,
+    /// Between members of a household.
+    Household,
+    /// Between visitors of a workplace.
+    Work,
+    /// Between visitors of a school.
+    School,
+    /// Between visitors of a market.
+    Market,
+    /// Along an edge of a contact network.
+    Network,
+}
+
+impl From<LocationKind> for Setting {
+    fn from(kind: LocationKind) -> Setting {
+        match kind {
+            LocationKind::Work => Setting::Work,
+            LocationKind::School => Setting::School,
+            LocationKind::Market => Setting::Market,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TransmissionTree {
+    infections: Vec<Infection>,
+    ///index into `infections` for each person, if they have been infected
+    #[serde(skip)]
+    by_infectee: Vec<Option<usize>>,
+}
+
+impl TransmissionTree {
+    pub fn new(population: usize) -> TransmissionTree {
+        TransmissionTree {
+            infections: Vec::new(),
+            by_infectee: vec![None; population],
+        }
+    }
+
+    pub(crate) fn record(&mut self, infection: Infection) {
+        self.by_infectee[infection.infectee] = Some(self.infections.len());
+        self.infections.push(infection);
+    }
+
+    /// Every infection, in the order they happened.
+    pub fn infections(&self) -> &[Infection] {
+        &self.infections
+    }
+
+    /// How `person` was infected, if they have been.
+    pub fn infection_of(&self, person: usize) -> Option<&Infection> {
+        self.by_infectee[person].map(|n| &self.infections[n])
+    }
+
+    /// The people infected at the start, each the root of a tree.
+    pub fn roots(&self) -> Vec<usize> {
+        self.infections
+            .iter()
+            .filter(|infection| infection.infector.is_none())
+            .map(|infection| infection.infectee)
+            .collect()
+    }
+
+    /// The people each person infected, indexed by person.
+    pub fn children(&self) -> Vec<Vec<usize>> {
+        let mut children = vec![Vec::new(); self.by_infectee.len()];
+        for infection in &self.infections {
+            if let Some(infector) = infection.infector {
+                children[infector].push(infection.infectee);
+            }
+        }
+        children
+    }
+
+    /// How many people each person infected, indexed by person.
+    pub fn secondary_cases(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.by_infectee.len()];
+        for infection in &self.infections {
+            if let Some(infector) = infection.infector {
+                counts[infector] += 1;
+            }
+        }
+        counts
+    }
+
+    /// How many infected people went on to infect `k` others, for every `k`
+    /// up to the largest. People still infectious may infect more later, so
+    /// this is only final once the outbreak is over.
+    pub fn offspring_distribution(&self) -> Vec<usize> {
+        let counts = self.secondary_cases();
+        let mut distribution = Vec::new();
+        for infection in &self.infections {
+            let k = counts[infection.infectee];
+            if distribution.len() <= k {
+                distribution.resize(k + 1, 0);
+            }
+            distribution[k] += 1;
+        }
+        distribution
+    }
+
+    /// Writes the forest as a Graphviz digraph, with initial cases drawn as
+    /// double circles and every edge labelled with the day of infection.
+    pub fn write_dot<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "digraph transmission {{")?;
+        for infection in &self.infections {
+            match infection.infector {
+                None => writeln!(writer, "    {} [shape=doublecircle];", infection.infectee)?,
+                Some(infector) => writeln!(
+                    writer,
+                    "    {} -> {} [label=\"{:.1}\"];",
+                    infector, infection.infectee, infection.t
+                )?,
+            }
+        }
+        writeln!(writer, "}}")
+    }
+
+    /// Writes one Newick tree per initial case, one per line. Branch lengths
+    /// are the days between the infector's and the infectee's infection.
+    pub fn write_newick<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let children = self.children();
+        for root in self.roots() {
+            let mut tree = String::new();
+            self.newick(root, &children, &mut tree);
+            writeln!(writer, "{};", tree)?;
+        }
+        Ok(())
+    }
+
+    fn newick(&self, person: usize, children: &[Vec<usize>], out: &mut String) {
+        if !children[person].is_empty() {
+            let t = self.infection_of(person).map_or(0.0, |i| i.t);
+            out.push('(');
+            for (n, &child) in children[person].iter().enumerate() {
+                if n > 0 {
+                    out.push(',');
+                }
+                self.newick(child, children, out);
+                let length = self.infection_of(child).map_or(0.0, |i| i.t) - t;
+                out.push_str(&format!(":{}", length));
+            }
+            out.push(')');
+        }
+        out.push_str(&person.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{World, WorldConfig};
+
+    fn infection(t: f64, infector: Option<usize>, infectee: usize) -> Infection {
+        Infection {
+            t,
+            infector,
+            infectee,
+            location: Vec2::ZERO,
+            setting: match infector {
+                Some(_) => Setting::Community,
+                None => Setting::Initial,
+            },
+            infector_status: infector.map(|_| Status::Infectious),
+            infector_symptomatic: infector.map(|_| true),
+        }
+    }
+
+    fn example() -> TransmissionTree {
+        let mut tree = TransmissionTree::new(6);
+        tree.record(infection(0.0, None, 0));
+        tree.record(infection(0.0, None, 4));
+        tree.record(infection(2.0, Some(0), 1));
+        tree.record(infection(3.5, Some(0), 2));
+        tree.record(infection(6.0, Some(1), 3));
+        tree
+    }
+
+    #[test]
+    fn counts_secondary_cases() {
+        let tree = example();
+        assert_eq!(tree.roots(), [0, 4]);
+        assert_eq!(tree.secondary_cases(), [2, 1, 0, 0, 0, 0]);
+        assert_eq!(tree.offspring_distribution(), [3, 1, 1]);
+        assert_eq!(tree.infection_of(3).unwrap().infector, Some(1));
+        assert!(tree.infection_of(5).is_none());
+    }
+
+    #[test]
+    fn writes_newick_and_dot() {
+        let tree = example();
+
+        let mut newick = Vec::new();
+        tree.write_newick(&mut newick).unwrap();
+        assert_eq!(
+            String::from_utf8(newick).unwrap(),
+            "((3:4)1:2,2:3.5)0;\n4;\n"
+        );
+
+        let mut dot = Vec::new();
+        tree.write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with("digraph transmission {\n    0 [shape=doublecircle];\n"));
+        assert!(dot.contains("    1 -> 3 [label=\"6.0\"];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn world_records_every_infection() {
+        let mut world = World::new(WorldConfig::default(), 21).unwrap();
+        world.run(60);
+        let tree = world.transmission_tree();
+
+        let infected = world
+            .people()
+            .iter()
+            .filter(|p| p.status() != Status::Susceptible)
+            .count();
+        assert_eq!(tree.infections().len(), infected);
+        assert_eq!(tree.roots().len(), 3);
+        for infection in tree.infections() {
+            if let Some(infector) = infection.infector {
+                let source = tree.infection_of(infector).unwrap();
+                assert!(source.t < infection.t);
+                assert_eq!(infection.infector_status, Some(Status::Infectious));
+            }
+        }
+        let secondary: usize = tree.secondary_cases().iter().sum();
+        assert_eq!(secondary, infected - 3);
+    }
+}
@@ -0,0 +1,390 @@
+//! Vaccination campaigns.
+//!
+//! A campaign gives `daily_doses` doses a day from day `start`, offering
+//! first doses in order of `priority` to people who have not been infected.
+//! Vaccines with a `second_dose_delay` need two doses, the first of which
+//! gives `first_dose_efficacy` of the full protection. Second doses come out
+//! of the same daily capacity and are given before any new first doses.
+//!
+//! The efficacies follow the usual trial definitions:
+//! `efficacy_against_infection` is the drop in the chance of being infected,
+//! and `efficacy_against_symptoms` the drop in the chance of symptomatic
+//! disease, counting the infections prevented outright. It can therefore not
+//! be below the efficacy against infection.
+//!
+//! The agent-based world needs `age_groups` to prioritise by age or risk.
+
+use std::collections::VecDeque;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{self, ParamError};
+use crate::{Person, Status};
+
+/// Who is offered a first dose first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Everybody has the same chance of being next.
+    #[default]
+    Random,
+    /// Oldest first, going by the `min_age` of each band in age-structured
+    /// models.
+    Age,
+    /// Those most likely to die first: high risk people, oldest first, in
+    /// the agent-based world, and the bands with the highest fatality per
+    /// infection in age-structured models.
+    Risk,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VaccinationConfig {
+    ///day the first doses are given
+    #[serde(default)]
+    pub start: f64,
+    ///first and second doses given each day
+    pub daily_doses: f64,
+    #[serde(default)]
+    pub priority: Priority,
+    ///drop in the chance of being infected once fully vaccinated
+    pub efficacy_against_infection: f64,
+    ///drop in the chance of symptomatic disease once fully vaccinated
+    pub efficacy_against_symptoms: f64,
+    ///days from the first dose to the second, single dose without one
+    #[serde(default)]
+    pub second_dose_delay: Option<f64>,
+    ///fraction of the full efficacy given by the first of two doses
+    #[serde(default = "half")]
+    pub first_dose_efficacy: f64,
+}
+
+fn half() -> f64 {
+    0.5
+}
+
+impl VaccinationConfig {
+    pub fn validate(&self) -> Result<(), ParamError> {
+        error::non_negative("vaccination.start", self.start)?;
+        error::non_negative("vaccination.daily_doses", self.daily_doses)?;
+        error::probability(
+            "vaccination.efficacy_against_infection",
+            self.efficacy_against_infection,
+        )?;
+        error::probability(
+            "vaccination.efficacy_against_symptoms",
+            self.efficacy_against_symptoms,
+        )?;
+        if self.efficacy_against_symptoms < self.efficacy_against_infection {
+            return Err(ParamError::new(
+                "vaccination.efficacy_against_symptoms",
+                "must not be below `efficacy_against_infection`",
+            ));
+        }
+        if let Some(delay) = self.second_dose_delay {
+            error::positive("vaccination.second_dose_delay", delay)?;
+        }
+        error::probability("vaccination.first_dose_efficacy", self.first_dose_efficacy)?;
+        Ok(())
+    }
+
+    /// The number of doses of a full course.
+    pub fn course(&self) -> u8 {
+        match self.second_dose_delay {
+            Some(_) => 2,
+            None => 1,
+        }
+    }
+
+    /// How much `doses` doses lower the chance of being infected, and the
+    /// chance that an infection is symptomatic when it happens anyway.
+    pub fn protection(&self, doses: u8) -> (f64, f64) {
+        let scale = match doses {
+            0 => 0.0,
+            doses if doses >= self.course() => 1.0,
+            _ => self.first_dose_efficacy,
+        };
+        let infection = scale * self.efficacy_against_infection;
+        let symptoms = scale * self.efficacy_against_symptoms;
+        // (1 - symptoms) = (1 - infection) * (1 - symptoms given infection)
+        let given_infection = if infection < 1.0 {
+            1.0 - (1.0 - symptoms) / (1.0 - infection)
+        } else {
+            0.0
+        };
+        (infection, given_infection)
+    }
+}
+
+/// A share of the agent-based world's people, with ages spread evenly
+/// between `min_age` and `max_age`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgeGroup {
+    pub min_age: f32,
+    pub max_age: f32,
+    ///relative size of the group
+    pub share: f64,
+    ///chance somebody in the group is at high risk from the disease
+    #[serde(default)]
+    pub high_risk: f32,
+}
+
+pub(crate) fn validate_age_groups(groups: &[AgeGroup]) -> Result<(), ParamError> {
+    for group in groups {
+        error::non_negative("age_groups.min_age", group.min_age.into())?;
+        if !(group.max_age >= group.min_age && group.max_age.is_finite()) {
+            return Err(ParamError::new(
+                "age_groups.max_age",
+                "must not be below `min_age`",
+            ));
+        }
+        error::non_negative("age_groups.share", group.share)?;
+        error::probability("age_groups.high_risk", group.high_risk.into())?;
+    }
+    error::positive("age_groups.share", groups.iter().map(|g| g.share).sum())?;
+    Ok(())
+}
+
+/// Draws an age and whether they are at high risk for each of `population`
+/// people.
+pub(crate) fn draw_ages<R: Rng + ?Sized>(
+    groups: &[AgeGroup],
+    population: usize,
+    rng: &mut R,
+) -> Vec<(f32, bool)> {
+    let total: f64 = groups.iter().map(|group| group.share).sum();
+    (0..population)
+        .map(|_| {
+            let mut u = rng.gen::<f64>() * total;
+            let mut group = &groups[groups.len() - 1];
+            for candidate in groups {
+                if u < candidate.share {
+                    group = candidate;
+                    break;
+                }
+                u -= candidate.share;
+            }
+            let age = group.min_age + rng.gen::<f32>() * (group.max_age - group.min_age);
+            (age, rng.gen::<f32>() < group.high_risk)
+        })
+        .collect()
+}
+
+/// Protection a person has from vaccination.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Immunity {
+    pub doses: u8,
+    ///time in days of the latest dose
+    pub last_dose: f64,
+    ///drop in the chance of being infected
+    pub against_infection: f32,
+    ///drop in the chance that an infection is symptomatic
+    pub against_symptoms: f32,
+}
+
+/// Hands out the doses of a campaign in the agent-based world.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Rollout {
+    ///everybody, in the order they are offered a first dose
+    queue: Vec<usize>,
+    ///index into `queue` of the next person to offer a first dose
+    next: usize,
+    ///people waiting for their second dose with when it is due, in order
+    second_doses: VecDeque<(f64, usize)>,
+    ///doses available but not yet given today
+    budget: f64,
+}
+
+impl Rollout {
+    /// Orders `people` by `config.priority`, breaking ties at random.
+    pub(crate) fn new<R: Rng + ?Sized>(
+        config: &VaccinationConfig,
+        people: &[Person],
+        rng: &mut R,
+    ) -> Rollout {
+        let mut queue: Vec<usize> = (0..people.len()).collect();
+        queue.shuffle(rng);
+        match config.priority {
+            Priority::Random => {}
+            Priority::Age => {
+                queue.sort_by(|&a, &b| people[b].age.partial_cmp(&people[a].age).unwrap())
+            }
+            Priority::Risk => queue.sort_by(|&a, &b| {
+                let key = |person: &Person| (person.high_risk, person.age);
+                key(&people[b]).partial_cmp(&key(&people[a])).unwrap()
+            }),
+        }
+        Rollout {
+            queue,
+            ..Rollout::default()
+        }
+    }
+
+    /// Gives out the doses for a tick of `dt` days ending at `t`.
+    pub(crate) fn update(
+        &mut self,
+        config: &VaccinationConfig,
+        people: &mut [Person],
+        t: f64,
+        dt: f64,
+    ) {
+        if t < config.start {
+            return;
+        }
+        self.budget += config.daily_doses * dt;
+        while self.budget >= 1.0 {
+            let person = match self.second_doses.front() {
+                Some(&(due, person)) if due <= t => {
+                    self.second_doses.pop_front();
+                    person
+                }
+                _ => match self.next_first_dose(people) {
+                    Some(person) => person,
+                    None => {
+                        // Doses nobody can take yet are not kept for later.
+                        self.budget = 0.0;
+                        return;
+                    }
+                },
+            };
+            let immunity = &mut people[person].immunity;
+            immunity.doses += 1;
+            immunity.last_dose = t;
+            let (infection, symptoms) = config.protection(immunity.doses);
+            immunity.against_infection = infection as f32;
+            immunity.against_symptoms = symptoms as f32;
+            if let (Some(delay), 1) = (config.second_dose_delay, immunity.doses) {
+                self.second_doses.push_back((t + delay, person));
+            }
+            self.budget -= 1.0;
+        }
+    }
+
+    /// The next person in the queue who has not been infected.
+    fn next_first_dose(&mut self, people: &[Person]) -> Option<usize> {
+        while let Some(&person) = self.queue.get(self.next) {
+            self.next += 1;
+            if people[person].status == Status::Susceptible {
+                return Some(person);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Vec2;
+    use crate::rng::seeded;
+    use crate::world::{World, WorldConfig};
+
+    fn config() -> VaccinationConfig {
+        VaccinationConfig {
+            start: 0.0,
+            daily_doses: 10.0,
+            priority: Priority::Age,
+            efficacy_against_infection: 0.6,
+            efficacy_against_symptoms: 0.9,
+            second_dose_delay: Some(21.0),
+            first_dose_efficacy: 0.5,
+        }
+    }
+
+    #[test]
+    fn protection_grows_with_doses() {
+        let config = config();
+        assert_eq!(config.protection(0), (0.0, 0.0));
+        let (infection, symptoms) = config.protection(1);
+        assert!((infection - 0.3).abs() < 1e-12);
+        // 1 - 0.45 = (1 - 0.3) * (1 - symptoms)
+        assert!((symptoms - (1.0 - 0.55 / 0.7)).abs() < 1e-12);
+        let (infection, symptoms) = config.protection(2);
+        assert!((infection - 0.6).abs() < 1e-12);
+        assert!((symptoms - 0.75).abs() < 1e-12);
+
+        let mut invalid = config;
+        invalid.efficacy_against_symptoms = 0.5;
+        let error = invalid.validate().unwrap_err();
+        assert_eq!(error.field, "vaccination.efficacy_against_symptoms");
+    }
+
+    #[test]
+    fn rolls_out_by_priority_and_gives_second_doses() {
+        let config = config();
+        let mut people: Vec<Person> = (0..100)
+            .map(|n| {
+                let mut person = Person::new(1.0, 0.5, 1.0, Vec2::ZERO);
+                person.age = n as f32;
+                person
+            })
+            .collect();
+        people[99].status = Status::Removed;
+        let mut rollout = Rollout::new(&config, &people, &mut seeded(3));
+
+        rollout.update(&config, &mut people, 1.0, 1.0);
+        let dosed: Vec<usize> = (0..100).filter(|&n| people[n].immunity.doses > 0).collect();
+        assert_eq!(
+            dosed,
+            (89..99).collect::<Vec<_>>(),
+            "oldest susceptible first"
+        );
+
+        for day in 2..=21 {
+            rollout.update(&config, &mut people, f64::from(day), 1.0);
+        }
+        let first: usize = people.iter().filter(|p| p.immunity.doses > 0).count();
+        assert_eq!(first, 99, "everybody susceptible has a first dose");
+        rollout.update(&config, &mut people, 22.0, 1.0);
+        let second: Vec<&Person> = people.iter().filter(|p| p.immunity.doses == 2).collect();
+        assert_eq!(second.len(), 10);
+        assert!(second.iter().all(|p| p.age >= 89.0));
+        assert!((second[0].immunity.against_infection - 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn vaccination_shrinks_the_outbreak() {
+        let groups = vec![
+            AgeGroup {
+                min_age: 0.0,
+                max_age: 60.0,
+                share: 3.0,
+                high_risk: 0.1,
+            },
+            AgeGroup {
+                min_age: 60.0,
+                max_age: 90.0,
+                share: 1.0,
+                high_risk: 0.5,
+            },
+        ];
+        let mut vaccination = config();
+        vaccination.daily_doses = 40.0;
+        vaccination.priority = Priority::Risk;
+        let removed = |vaccination: Option<VaccinationConfig>| {
+            let config = WorldConfig {
+                age_groups: Some(groups.clone()),
+                vaccination,
+                ..WorldConfig::default()
+            };
+            let trajectory = World::new(config, 5).unwrap().run(150);
+            let last = *trajectory.last().unwrap();
+            (last.removed, last.vaccinated, last.fully_vaccinated)
+        };
+        let (unvaccinated, none, _) = removed(None);
+        assert_eq!(none, 0);
+        let (vaccinated, first, second) = removed(Some(vaccination.clone()));
+        assert!(vaccinated < unvaccinated, "{} {}", vaccinated, unvaccinated);
+        assert!(first > 0 && second > 0 && second <= first);
+
+        let config = WorldConfig {
+            vaccination: Some(vaccination),
+            ..WorldConfig::default()
+        };
+        let error = World::new(config, 5).err().unwrap();
+        assert_eq!(error.field, "vaccination.priority");
+    }
+}
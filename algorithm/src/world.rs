@@ -0,0 +1,1360 @@
+//! Agent-based "bouncing dots" epidemic simulation.
+//!
+//! People wander around a rectangular arena, bouncing off its walls. Each
+//! tick, every infectious person may infect the susceptible people within
+//! their `infection_radius`, who then progress through `Status::Exposed`
+//! and `Status::Infectious` before being removed. Detected cases can be
+//! isolated in a quarantine region, see `QuarantineConfig`. People can also
+//! live in households and commute between locations on a daily schedule,
+//! see `HouseholdConfig` and `LocationsConfig`, and be vaccinated, see
+//! `VaccinationConfig`.
+//!
+//! A compliant fraction of the population can be told to keep their distance,
+//! slowing down or staying put altogether. When collisions are enabled,
+//! people bounce off each other and stationary people act as walls.
+
+use std::fmt;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::distribution::Distribution;
+use crate::error::{self, ParamError};
+use crate::export::Metadata;
+use crate::geometry::{Rect, Vec2};
+use crate::household::{Household, HouseholdConfig};
+use crate::location::{turn_back, Closures, Location, LocationKind, Locations, LocationsConfig};
+use crate::quarantine::{Quarantine, QuarantineConfig};
+use crate::rng::{self, ModelRng};
+use crate::spatial::SpatialGrid;
+use crate::trajectory::{Sample, Trajectory};
+use crate::transmission::{Infection, Setting, TransmissionTree};
+use crate::vaccination::{self, AgeGroup, Priority, Rollout, VaccinationConfig};
+use crate::{Person, Status};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldConfig {
+    ///size of the arena, in arbitrary distance units
+    pub width: f32,
+    pub height: f32,
+    pub population: usize,
+    ///people who start out infectious
+    pub initial_infected: usize,
+    pub infection_radius: f32,
+    ///chance per tick that an infectious person infects a susceptible within range
+    pub transmission_probability: f32,
+    pub p_symptomatic_on_infection: f32,
+    ///how infectious asymptomatic carriers are relative to symptomatic ones
+    pub asymptomatic_infectiousness: f32,
+    ///distance units per day
+    pub max_speed: f32,
+    ///fraction of people who follow social distancing
+    pub distancing_compliance: f32,
+    ///fraction of `max_speed` compliant people move at, 0 stays put
+    pub distancing_mobility: f32,
+    ///radius of each person for collisions, 0 lets people pass through each other
+    pub collision_radius: f32,
+    ///days spent exposed before becoming infectious
+    pub incubation_days: f32,
+    ///days spent infectious before being removed
+    pub infectious_days: f32,
+    ///days asymptomatic carriers spend infectious before being removed
+    pub asymptomatic_infectious_days: f32,
+    ///draws each person's incubation period, instead of `incubation_days` for all
+    pub incubation_period: Option<Distribution>,
+    ///draws each person's infectious period, instead of `infectious_days` for all
+    pub infectious_period: Option<Distribution>,
+    ///draws each asymptomatic carrier's infectious period, instead of
+    ///`asymptomatic_infectious_days` for all
+    pub asymptomatic_infectious_period: Option<Distribution>,
+    pub ticks_per_day: u32,
+    ///isolation of detected cases, disabled when `None`
+    pub quarantine: Option<QuarantineConfig>,
+    ///homes and transmission within them, disabled when `None`
+    pub households: Option<HouseholdConfig>,
+    ///daily trips to work, school and markets instead of wandering at
+    ///random, disabled when `None`
+    pub locations: Option<LocationsConfig>,
+    ///ages and risk of the population, only used to prioritise vaccination
+    pub age_groups: Option<Vec<AgeGroup>>,
+    ///a vaccination campaign, disabled when `None`
+    pub vaccination: Option<VaccinationConfig>,
+}
+
+impl Default for WorldConfig {
+    fn default() -> WorldConfig {
+        WorldConfig {
+            width: 800.0,
+            height: 600.0,
+            population: 400,
+            initial_infected: 3,
+            infection_radius: 8.0,
+            transmission_probability: 0.2,
+            p_symptomatic_on_infection: 0.7,
+            asymptomatic_infectiousness: 0.5,
+            max_speed: 60.0,
+            distancing_compliance: 0.0,
+            distancing_mobility: 0.0,
+            collision_radius: 0.0,
+            incubation_days: 3.0,
+            infectious_days: 10.0,
+            asymptomatic_infectious_days: 7.0,
+            incubation_period: None,
+            infectious_period: None,
+            asymptomatic_infectious_period: None,
+            ticks_per_day: 10,
+            quarantine: None,
+            households: None,
+            locations: None,
+            age_groups: None,
+            vaccination: None,
+        }
+    }
+}
+
+impl WorldConfig {
+    pub fn validate(&self) -> Result<(), ParamError> {
+        error::positive("width", self.width.into())?;
+        error::positive("height", self.height.into())?;
+        error::positive("population", self.population as f64)?;
+        error::within_population(
+            "initial_infected",
+            self.initial_infected as f64,
+            self.population as f64,
+        )?;
+        error::non_negative("infection_radius", self.infection_radius.into())?;
+        error::probability(
+            "transmission_probability",
+            self.transmission_probability.into(),
+        )?;
+        error::probability(
+            "p_symptomatic_on_infection",
+            self.p_symptomatic_on_infection.into(),
+        )?;
+        error::probability(
+            "asymptomatic_infectiousness",
+            self.asymptomatic_infectiousness.into(),
+        )?;
+        error::non_negative("max_speed", self.max_speed.into())?;
+        error::probability("distancing_compliance", self.distancing_compliance.into())?;
+        error::probability("distancing_mobility", self.distancing_mobility.into())?;
+        error::non_negative("collision_radius", self.collision_radius.into())?;
+        error::non_negative("incubation_days", self.incubation_days.into())?;
+        error::non_negative("infectious_days", self.infectious_days.into())?;
+        error::non_negative(
+            "asymptomatic_infectious_days",
+            self.asymptomatic_infectious_days.into(),
+        )?;
+        for (field, period) in &[
+            ("incubation_period", self.incubation_period),
+            ("infectious_period", self.infectious_period),
+            (
+                "asymptomatic_infectious_period",
+                self.asymptomatic_infectious_period,
+            ),
+        ] {
+            if let Some(period) = period {
+                period.validate(field)?;
+            }
+        }
+        error::positive("ticks_per_day", self.ticks_per_day.into())?;
+        if let Some(quarantine) = &self.quarantine {
+            quarantine.validate(&self.arena())?;
+        }
+        if let Some(households) = &self.households {
+            households.validate()?;
+        }
+        if let Some(locations) = &self.locations {
+            locations.validate()?;
+        }
+        if let Some(groups) = &self.age_groups {
+            vaccination::validate_age_groups(groups)?;
+        }
+        if let Some(vaccination) = &self.vaccination {
+            vaccination.validate()?;
+            if vaccination.priority != Priority::Random && self.age_groups.is_none() {
+                return Err(ParamError::new(
+                    "vaccination.priority",
+                    "needs `age_groups` to go by age or risk",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws how long a newly infected person stays exposed and then
+    /// infectious. Fixed durations do not consume any randomness.
+    fn draw_durations<R: Rng + ?Sized>(&self, person: &mut Person, rng: &mut R) {
+        let draw = |period: Option<Distribution>, days: f32, rng: &mut R| {
+            period.map_or(f64::from(days), |period| period.sample(rng))
+        };
+        person.incubation_days = draw(self.incubation_period, self.incubation_days, rng);
+        person.infectious_days = if person.symptomatic {
+            draw(self.infectious_period, self.infectious_days, rng)
+        } else {
+            draw(
+                self.asymptomatic_infectious_period,
+                self.asymptomatic_infectious_days,
+                rng,
+            )
+        };
+    }
+
+    /// The area people move around in when they are not quarantined.
+    pub fn arena(&self) -> Rect {
+        Rect::new(0.0, 0.0, self.width, self.height)
+    }
+
+    pub fn metadata(&self, seed: u64) -> Metadata {
+        let mut metadata = Metadata::new("world")
+            .seed(seed)
+            .parameter("width", self.width)
+            .parameter("height", self.height)
+            .parameter("population", self.population as f64)
+            .parameter("initial_infected", self.initial_infected as f64)
+            .parameter("infection_radius", self.infection_radius)
+            .parameter("transmission_probability", self.transmission_probability)
+            .parameter(
+                "p_symptomatic_on_infection",
+                self.p_symptomatic_on_infection,
+            )
+            .parameter(
+                "asymptomatic_infectiousness",
+                self.asymptomatic_infectiousness,
+            )
+            .parameter("max_speed", self.max_speed)
+            .parameter("distancing_compliance", self.distancing_compliance)
+            .parameter("distancing_mobility", self.distancing_mobility)
+            .parameter("collision_radius", self.collision_radius)
+            .parameter("incubation_days", self.incubation_days)
+            .parameter("infectious_days", self.infectious_days)
+            .parameter(
+                "asymptomatic_infectious_days",
+                self.asymptomatic_infectious_days,
+            )
+            .parameter("ticks_per_day", f64::from(self.ticks_per_day));
+        for (name, period) in &[
+            ("incubation_period", self.incubation_period),
+            ("infectious_period", self.infectious_period),
+            (
+                "asymptomatic_infectious_period",
+                self.asymptomatic_infectious_period,
+            ),
+        ] {
+            if let Some(period) = period {
+                for (parameter, value) in period.parameters() {
+                    let name = format!("{}.{}.{}", name, period.name(), parameter);
+                    metadata = metadata.parameter(&name, value);
+                }
+            }
+        }
+        if let Some(quarantine) = &self.quarantine {
+            metadata = metadata
+                .parameter("quarantine.capacity", quarantine.capacity as f64)
+                .parameter(
+                    "quarantine.detection_delay_days",
+                    quarantine.detection_delay_days,
+                )
+                .parameter("quarantine.testing_rate", quarantine.testing_rate);
+        }
+        if let Some(households) = &self.households {
+            metadata = metadata
+                .parameter(
+                    "households.transmission_probability",
+                    households.transmission_probability,
+                )
+                .parameter("households.roam_radius", households.roam_radius);
+            for (n, &weight) in households.size_distribution.iter().enumerate() {
+                let name = format!("households.size_distribution.{}", n + 1);
+                metadata = metadata.parameter(&name, weight);
+            }
+        }
+        if let Some(locations) = &self.locations {
+            metadata = metadata
+                .parameter(
+                    "locations.transmission_probability",
+                    locations.transmission_probability,
+                )
+                .parameter("locations.day_start", locations.day_start)
+                .parameter("locations.day_end", locations.day_end)
+                .parameter("locations.evening_end", locations.evening_end)
+                .parameter("locations.commute_speed", locations.commute_speed)
+                .parameter("locations.radius", locations.radius);
+            for &kind in &LocationKind::ALL {
+                if let Some(site) = locations.site(kind) {
+                    let name = |parameter| format!("locations.{}.{}", kind.name(), parameter);
+                    metadata = metadata
+                        .parameter(&name("count"), site.count as f64)
+                        .parameter(&name("capacity"), site.capacity as f64)
+                        .parameter(&name("contact_rate"), site.contact_rate)
+                        .parameter(&name("share"), site.share);
+                }
+            }
+        }
+        for (n, group) in self.age_groups.iter().flatten().enumerate() {
+            let name = |parameter| format!("age_groups.{}.{}", n, parameter);
+            metadata = metadata
+                .parameter(&name("min_age"), group.min_age)
+                .parameter(&name("max_age"), group.max_age)
+                .parameter(&name("share"), group.share)
+                .parameter(&name("high_risk"), group.high_risk);
+        }
+        metadata.vaccination(self.vaccination.as_ref())
+    }
+}
+
+/// Head counts of a `World` at time `t`, in days.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct WorldSample {
+    pub t: f64,
+    pub susceptible: usize,
+    pub exposed: usize,
+    pub infectious: usize,
+    pub removed: usize,
+    ///infectious people showing symptoms
+    pub symptomatic: usize,
+    ///infectious people without symptoms, `infectious - symptomatic`
+    pub asymptomatic: usize,
+    ///everyone ever infected on the symptomatic branch
+    pub total_symptomatic: usize,
+    ///everyone ever infected on the asymptomatic branch
+    pub total_asymptomatic: usize,
+    ///people in the quarantine region
+    pub quarantined: usize,
+    ///detected cases still infectious but waiting for room in quarantine
+    pub awaiting_quarantine: usize,
+    ///people given at least one dose of vaccine
+    pub vaccinated: usize,
+    ///people given every dose of their vaccine
+    pub fully_vaccinated: usize,
+}
+
+impl Sample for WorldSample {
+    const COMPARTMENTS: &'static [&'static str] = &[
+        "susceptible",
+        "exposed",
+        "infectious",
+        "removed",
+        "symptomatic",
+        "asymptomatic",
+        "total_symptomatic",
+        "total_asymptomatic",
+        "quarantined",
+        "awaiting_quarantine",
+        "vaccinated",
+        "fully_vaccinated",
+    ];
+
+    fn time(&self) -> f64 {
+        self.t
+    }
+
+    fn values(&self) -> Vec<f64> {
+        [
+            self.susceptible,
+            self.exposed,
+            self.infectious,
+            self.removed,
+            self.symptomatic,
+            self.asymptomatic,
+            self.total_symptomatic,
+            self.total_asymptomatic,
+            self.quarantined,
+            self.awaiting_quarantine,
+            self.vaccinated,
+            self.fully_vaccinated,
+        ]
+        .iter()
+        .map(|&n| n as f64)
+        .collect()
+    }
+
+    fn infectious(&self) -> f64 {
+        self.infectious as f64
+    }
+
+    fn removed(&self) -> f64 {
+        self.removed as f64
+    }
+
+    fn at_time(&self, t: f64) -> WorldSample {
+        WorldSample { t, ..*self }
+    }
+}
+
+#[derive(Clone)]
+pub struct World {
+    config: WorldConfig,
+    people: Vec<Person>,
+    rng: Box<dyn ModelRng>,
+    tick: u64,
+    grid: SpatialGrid,
+    quarantine: Quarantine,
+    ///whether detected cases are currently sent to quarantine
+    quarantine_enabled: bool,
+    ///people whose draw is below `distancing_compliance` comply, so raising
+    ///it only ever adds compliant people
+    compliance_draws: Vec<f32>,
+    ///scratch buffer the grid is rebuilt from every tick
+    positions: Vec<Vec2>,
+    tree: TransmissionTree,
+    households: Vec<Household>,
+    locations: Locations,
+    closures: Closures,
+    rollout: Rollout,
+}
+
+impl World {
+    /// Scatters `config.population` people uniformly over the arena, heading
+    /// in random directions at their maximum speed.
+    pub fn new(config: WorldConfig, seed: u64) -> Result<World, ParamError> {
+        World::with_rng(config, Box::new(rng::seeded(seed)))
+    }
+
+    /// Like `new`, but draws all randomness from `rng`.
+    pub fn with_rng(config: WorldConfig, mut rng: Box<dyn ModelRng>) -> Result<World, ParamError> {
+        config.validate()?;
+
+        let arena = config.arena();
+        let mut tree = TransmissionTree::new(config.population);
+        let households = match &config.households {
+            Some(households) => households.assign(config.population, &arena, &mut rng),
+            None => Vec::new(),
+        };
+        let mut homes = vec![None; config.population];
+        for (h, household) in households.iter().enumerate() {
+            for &member in &household.members {
+                homes[member] = Some(h);
+            }
+        }
+        // Households are filled with consecutive people, so the first few
+        // would all share a home. Pick the initial cases at random then,
+        // which leaves the draws of worlds without households as they were.
+        let mut initial = vec![false; config.population];
+        let mut order: Vec<usize> = (0..config.population).collect();
+        for n in 0..config.initial_infected {
+            if config.households.is_some() {
+                let pick = rng.gen_range(n, order.len());
+                order.swap(n, pick);
+            }
+            initial[order[n]] = true;
+        }
+
+        let mut people: Vec<Person> = (0..config.population)
+            .map(|n| {
+                let position = match homes[n] {
+                    Some(h) => households[h].home,
+                    None => arena.random_point(&mut rng),
+                };
+                let mut person = Person::new(
+                    config.infection_radius,
+                    config.p_symptomatic_on_infection,
+                    config.max_speed,
+                    position,
+                );
+                person.velocity = Vec2::random_unit(&mut rng) * person.max_speed;
+                person.household = homes[n];
+                if initial[n] {
+                    person.infect(Status::Infectious, 0.0, &mut rng);
+                    config.draw_durations(&mut person, &mut rng);
+                    tree.record(Infection {
+                        t: 0.0,
+                        infector: None,
+                        infectee: n,
+                        location: position,
+                        setting: Setting::Initial,
+                        infector_status: None,
+                        infector_symptomatic: None,
+                    });
+                }
+                person
+            })
+            .collect();
+        let compliance_draws = (0..config.population).map(|_| rng.gen()).collect();
+        let locations = match &config.locations {
+            Some(locations) => {
+                let homes = people.iter().map(|person| person.position).collect();
+                Locations::new(locations, &arena, homes, &mut rng)
+            }
+            None => Locations::default(),
+        };
+        if let Some(groups) = &config.age_groups {
+            let ages = vaccination::draw_ages(groups, config.population, &mut rng);
+            for (person, (age, high_risk)) in people.iter_mut().zip(ages) {
+                person.age = age;
+                person.high_risk = high_risk;
+            }
+        }
+        let rollout = match &config.vaccination {
+            Some(vaccination) => Rollout::new(vaccination, &people, &mut rng),
+            None => Rollout::default(),
+        };
+
+        let grid = SpatialGrid::new(config.width, config.height, config.infection_radius);
+        let mut world = World {
+            config,
+            people,
+            rng,
+            tick: 0,
+            grid,
+            quarantine: Quarantine::default(),
+            quarantine_enabled: true,
+            compliance_draws,
+            positions: Vec::new(),
+            tree,
+            households,
+            locations,
+            closures: Closures::default(),
+            rollout,
+        };
+        world.apply_distancing();
+        Ok(world)
+    }
+
+    /// Changes the fraction of people who follow social distancing.
+    pub fn set_distancing_compliance(&mut self, compliance: f32) -> Result<(), ParamError> {
+        error::probability("distancing_compliance", compliance.into())?;
+        self.config.distancing_compliance = compliance;
+        self.apply_distancing();
+        Ok(())
+    }
+
+    /// Changes how fast compliant people move, as a fraction of `max_speed`.
+    pub fn set_distancing_mobility(&mut self, mobility: f32) -> Result<(), ParamError> {
+        error::probability("distancing_mobility", mobility.into())?;
+        self.config.distancing_mobility = mobility;
+        self.apply_distancing();
+        Ok(())
+    }
+
+    /// Changes the chance per tick that an infectious person infects a
+    /// susceptible within range.
+    pub fn set_transmission_probability(&mut self, p: f32) -> Result<(), ParamError> {
+        error::probability("transmission_probability", p.into())?;
+        self.config.transmission_probability = p;
+        Ok(())
+    }
+
+    /// Changes the chance per day that an undetected case tests positive.
+    /// Only a world with a quarantine region can test anyone.
+    pub fn set_testing_rate(&mut self, rate: f32) -> Result<(), ParamError> {
+        error::probability("quarantine.testing_rate", rate.into())?;
+        match &mut self.config.quarantine {
+            Some(quarantine) => quarantine.testing_rate = rate,
+            None if rate == 0.0 => {}
+            None => {
+                return Err(ParamError::new(
+                    "quarantine.testing_rate",
+                    "needs a quarantine region",
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_quarantine_enabled(&self) -> bool {
+        self.quarantine_enabled
+    }
+
+    /// Starts or stops sending detected cases to quarantine. People already
+    /// there stay until they are removed.
+    pub fn set_quarantine_enabled(&mut self, enabled: bool) {
+        self.quarantine_enabled = enabled;
+    }
+
+    pub fn closures(&self) -> Closures {
+        self.closures
+    }
+
+    /// Closes and reopens kinds of location. Everybody at or headed to a
+    /// location that closes goes home, and only a world with locations of a
+    /// kind can close them.
+    pub fn set_closures(&mut self, closures: Closures) -> Result<(), ParamError> {
+        for &kind in &LocationKind::ALL {
+            let exists = self
+                .config
+                .locations
+                .as_ref()
+                .and_then(|locations| locations.site(kind))
+                .is_some();
+            if closures.is_closed(kind) && !exists {
+                return Err(ParamError::new(
+                    "closures",
+                    "can only close kinds of location the world has",
+                ));
+            }
+        }
+        self.closures = closures;
+        self.locations.close(&closures);
+        Ok(())
+    }
+
+    fn apply_distancing(&mut self) {
+        let compliance = self.config.distancing_compliance;
+        let mobility = self.config.distancing_mobility;
+        for (person, &draw) in self.people.iter_mut().zip(&self.compliance_draws) {
+            person.mobility = if draw < compliance { mobility } else { 1.0 };
+        }
+    }
+
+    pub fn config(&self) -> &WorldConfig {
+        &self.config
+    }
+
+    /// Everybody's households, empty unless `WorldConfig::households` is set.
+    pub fn households(&self) -> &[Household] {
+        &self.households
+    }
+
+    /// Workplaces, schools and markets, empty unless
+    /// `WorldConfig::locations` is set.
+    pub fn locations(&self) -> &[Location] {
+        self.locations.sites()
+    }
+
+    /// The location `person` is at, as an index into `locations`, or `None`
+    /// on the way there, at home, or without locations.
+    pub fn location_of(&self, person: usize) -> Option<usize> {
+        self.locations.present(person)
+    }
+
+    /// Who infected whom so far.
+    pub fn transmission_tree(&self) -> &TransmissionTree {
+        &self.tree
+    }
+
+    pub fn people(&self) -> &[Person] {
+        &self.people
+    }
+
+    /// Simulation time in days.
+    pub fn time(&self) -> f64 {
+        self.tick as f64 / f64::from(self.config.ticks_per_day)
+    }
+
+    fn dt(&self) -> f32 {
+        1.0 / self.config.ticks_per_day as f32
+    }
+
+    pub fn sample(&self) -> WorldSample {
+        let mut sample = WorldSample {
+            t: self.time(),
+            susceptible: 0,
+            exposed: 0,
+            infectious: 0,
+            removed: 0,
+            symptomatic: 0,
+            asymptomatic: 0,
+            total_symptomatic: 0,
+            total_asymptomatic: 0,
+            quarantined: self.quarantine.occupancy(),
+            awaiting_quarantine: self.quarantine.overflow(),
+            vaccinated: 0,
+            fully_vaccinated: 0,
+        };
+        let course = self.config.vaccination.as_ref().map_or(1, |v| v.course());
+        for person in &self.people {
+            if person.immunity.doses > 0 {
+                sample.vaccinated += 1;
+            }
+            if person.immunity.doses >= course {
+                sample.fully_vaccinated += 1;
+            }
+            match person.status {
+                Status::Susceptible => sample.susceptible += 1,
+                Status::Exposed => sample.exposed += 1,
+                Status::Infectious => sample.infectious += 1,
+                Status::Removed => sample.removed += 1,
+            }
+            if person.status == Status::Susceptible {
+                continue;
+            }
+            match (person.symptomatic, person.status == Status::Infectious) {
+                (true, true) => sample.symptomatic += 1,
+                (false, true) => sample.asymptomatic += 1,
+                _ => {}
+            }
+            if person.symptomatic {
+                sample.total_symptomatic += 1;
+            } else {
+                sample.total_asymptomatic += 1;
+            }
+        }
+        sample
+    }
+
+    /// Whether nobody is left who could still infect anyone.
+    pub fn is_over(&self) -> bool {
+        self.people
+            .iter()
+            .all(|p| !matches!(p.status, Status::Exposed | Status::Infectious))
+    }
+
+    /// Advances the simulation by a single tick.
+    pub fn step(&mut self) {
+        self.tick += 1;
+        if let Some(config) = &self.config.locations {
+            let t = self.time();
+            self.locations
+                .plan(config, t, &self.closures, &self.people, &mut self.rng);
+        }
+        self.move_people();
+        self.collide();
+        self.transmit();
+        self.progress();
+        if let Some(config) = &self.config.quarantine {
+            let t = self.time();
+            let dt = self.dt();
+            self.quarantine.update(
+                config,
+                &self.config.arena(),
+                &mut self.people,
+                t,
+                dt,
+                self.quarantine_enabled,
+                &mut self.rng,
+            );
+        }
+        if let Some(config) = &self.config.vaccination {
+            let t = self.time();
+            let dt = 1.0 / f64::from(self.config.ticks_per_day);
+            self.rollout.update(config, &mut self.people, t, dt);
+        }
+    }
+
+    /// Advances the simulation by `days`, returning the current counts
+    /// followed by the counts at the end of each day.
+    pub fn run(&mut self, days: usize) -> Trajectory<WorldSample> {
+        let mut trajectory = Trajectory::with_capacity(days + 1);
+        trajectory.push(self.sample());
+        for _ in 0..days {
+            for _ in 0..self.config.ticks_per_day {
+                self.step();
+            }
+            trajectory.push(self.sample());
+        }
+        trajectory
+    }
+
+    fn move_people(&mut self) {
+        let dt = self.dt();
+        let arena = self.config.arena();
+        let region = self.config.quarantine.as_ref().map(|q| q.region);
+        let roam_radius = self.config.households.as_ref().map(|h| h.roam_radius);
+        for (i, person) in self.people.iter_mut().enumerate() {
+            if let (Some(locations), false) = (&self.config.locations, person.quarantined) {
+                let home_radius = roam_radius.unwrap_or(locations.radius);
+                self.locations.steer(i, person, locations, home_radius, dt);
+            }
+
+            let bounds = match region {
+                Some(region) if person.quarantined => region,
+                _ => arena,
+            };
+            let (min, max) = (bounds.min(), bounds.max());
+            person.position += person.velocity() * dt;
+            bounce(&mut person.position.x, &mut person.velocity.x, min.x, max.x);
+            bounce(&mut person.position.y, &mut person.velocity.y, min.y, max.y);
+
+            if let (Some(h), Some(radius), false, None) = (
+                person.household,
+                roam_radius,
+                person.quarantined,
+                &self.config.locations,
+            ) {
+                turn_back(person, self.households[h].home, radius);
+            }
+        }
+    }
+
+    fn rebuild_grid(&mut self) {
+        self.positions.clear();
+        self.positions
+            .extend(self.people.iter().map(|person| person.position));
+        self.grid.rebuild(&self.positions);
+    }
+
+    /// Separates overlapping people, bouncing them off each other.
+    /// Quarantined people are left alone.
+    fn collide(&mut self) {
+        let diameter = 2.0 * self.config.collision_radius;
+        if diameter <= 0.0 {
+            return;
+        }
+        self.rebuild_grid();
+
+        let arena = self.config.arena();
+        let mut neighbours = Vec::new();
+        for i in 0..self.people.len() {
+            if self.people[i].quarantined {
+                continue;
+            }
+            neighbours.clear();
+            self.grid
+                .for_each_candidate(self.people[i].position, diameter, |j| {
+                    if j > i {
+                        neighbours.push(j);
+                    }
+                });
+            for &j in &neighbours {
+                let (head, tail) = self.people.split_at_mut(j);
+                let (a, b) = (&mut head[i], &mut tail[0]);
+                if !b.quarantined {
+                    collide_pair(a, b, diameter, &arena);
+                }
+            }
+        }
+    }
+
+    fn transmit(&mut self) {
+        let t = self.time();
+        let p_symptomatic = self.config.transmission_probability;
+        let p_asymptomatic = p_symptomatic * self.config.asymptomatic_infectiousness;
+
+        self.rebuild_grid();
+
+        // Quarantined people cannot infect anyone outside, and everybody
+        // inside has already been infected.
+        let can_infect =
+            |person: &Person| person.status == Status::Infectious && !person.quarantined;
+        let infectious: Vec<usize> = (0..self.people.len())
+            .filter(|&i| can_infect(&self.people[i]))
+            .collect();
+
+        let people = &mut self.people;
+        let rng = &mut self.rng;
+        let tree = &mut self.tree;
+        let config = &self.config;
+        // People inside a location only meet through its contact rate.
+        let locations = &self.locations;
+        for &i in &infectious {
+            if locations.present(i).is_some() {
+                continue;
+            }
+            let source = people[i].position;
+            let radius = people[i].infection_radius;
+            let (status, symptomatic) = (people[i].status, people[i].symptomatic);
+            let p = if symptomatic {
+                p_symptomatic
+            } else {
+                p_asymptomatic
+            };
+            self.grid.for_each_candidate(source, radius, |j| {
+                let target = &mut people[j];
+                if target.status == Status::Susceptible
+                    && locations.present(j).is_none()
+                    && target.position.distance_squared(source) <= radius * radius
+                    && rng.gen::<f32>() < p * target.susceptibility()
+                {
+                    target.infect(Status::Exposed, t, rng);
+                    config.draw_durations(target, rng);
+                    tree.record(Infection {
+                        t,
+                        infector: Some(i),
+                        infectee: j,
+                        location: target.position,
+                        setting: Setting::Community,
+                        infector_status: Some(status),
+                        infector_symptomatic: Some(symptomatic),
+                    });
+                }
+            });
+        }
+
+        if let Some(households) = &self.config.households {
+            let p = households.probability_per_tick(self.config.ticks_per_day);
+            self.transmit_at_home(t, p, &infectious);
+        }
+        self.transmit_at_locations(t);
+    }
+
+    /// Lets each of the `infectious` infect the susceptible members of their
+    /// household with chance `p`, scaled down for asymptomatic carriers.
+    fn transmit_at_home(&mut self, t: f64, p: f64, infectious: &[usize]) {
+        let p_asymptomatic = p * f64::from(self.config.asymptomatic_infectiousness);
+        for &i in infectious {
+            let h = match self.people[i].household {
+                Some(h) => h,
+                None => continue,
+            };
+            let (status, symptomatic) = (self.people[i].status, self.people[i].symptomatic);
+            let p = if symptomatic { p } else { p_asymptomatic };
+            for &j in &self.households[h].members {
+                let target = &mut self.people[j];
+                if target.status == Status::Susceptible
+                    && self.rng.gen::<f64>() < p * f64::from(target.susceptibility())
+                {
+                    target.infect(Status::Exposed, t, &mut self.rng);
+                    self.config.draw_durations(target, &mut self.rng);
+                    self.tree.record(Infection {
+                        t,
+                        infector: Some(i),
+                        infectee: j,
+                        location: target.position,
+                        setting: Setting::Household,
+                        infector_status: Some(status),
+                        infector_symptomatic: Some(symptomatic),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Lets the visitors of each location infect each other. Every visitor
+    /// meets `contact_rate` others a day, picked at random from everybody
+    /// there, and each contact with an infectious visitor passes on the
+    /// infection with the locations' `transmission_probability`.
+    fn transmit_at_locations(&mut self, t: f64) {
+        let config = match &self.config.locations {
+            Some(config) => config,
+            None => return,
+        };
+        let mut visitors = vec![Vec::new(); self.locations.sites().len()];
+        for (i, person) in self.people.iter().enumerate() {
+            if let (Some(site), false) = (self.locations.present(i), person.quarantined) {
+                visitors[site].push(i);
+            }
+        }
+
+        let dt = f64::from(self.dt());
+        let p = f64::from(config.transmission_probability);
+        let asymptomatic = f64::from(self.config.asymptomatic_infectiousness);
+        for (site, visitors) in visitors.iter().enumerate() {
+            let kind = self.locations.sites()[site].kind;
+            let contact_rate = config.site(kind).map_or(0.0, |site| site.contact_rate);
+            let infectious: Vec<(usize, f64)> = visitors
+                .iter()
+                .filter(|&&i| self.people[i].status == Status::Infectious)
+                .map(|&i| {
+                    let weight = if self.people[i].symptomatic {
+                        1.0
+                    } else {
+                        asymptomatic
+                    };
+                    (i, weight)
+                })
+                .collect();
+            let pressure: f64 = infectious.iter().map(|&(_, weight)| weight).sum();
+            if pressure <= 0.0 || visitors.len() < 2 {
+                continue;
+            }
+
+            let hazard = f64::from(contact_rate) * dt * p * pressure / (visitors.len() - 1) as f64;
+            let p_infection = 1.0 - libm::exp(-hazard);
+            for &j in visitors {
+                if self.people[j].status != Status::Susceptible
+                    || self.rng.gen::<f64>()
+                        >= p_infection * f64::from(self.people[j].susceptibility())
+                {
+                    continue;
+                }
+                // Blame whoever the contact was with, in proportion to how
+                // infectious they are.
+                let mut draw = self.rng.gen::<f64>() * pressure;
+                let mut infector = infectious[infectious.len() - 1].0;
+                for &(i, weight) in &infectious {
+                    if draw < weight {
+                        infector = i;
+                        break;
+                    }
+                    draw -= weight;
+                }
+
+                let (status, symptomatic) = (
+                    self.people[infector].status,
+                    self.people[infector].symptomatic,
+                );
+                let target = &mut self.people[j];
+                target.infect(Status::Exposed, t, &mut self.rng);
+                self.config.draw_durations(target, &mut self.rng);
+                self.tree.record(Infection {
+                    t,
+                    infector: Some(infector),
+                    infectee: j,
+                    location: target.position,
+                    setting: kind.into(),
+                    infector_status: Some(status),
+                    infector_symptomatic: Some(symptomatic),
+                });
+            }
+        }
+    }
+
+    fn progress(&mut self) {
+        // Compare in ticks so whole-day periods are not cut short by rounding.
+        let t = self.time();
+        let ticks_per_day = f64::from(self.config.ticks_per_day);
+        let ticks = |days: f64| (days * ticks_per_day).round();
+        let elapsed = |person: &Person| ticks(t - person.status_since);
+
+        for person in &mut self.people {
+            if person.status == Status::Exposed && elapsed(person) >= ticks(person.incubation_days)
+            {
+                person.set_status(Status::Infectious, t);
+            }
+            if person.status == Status::Infectious
+                && elapsed(person) >= ticks(person.infectious_days)
+            {
+                person.set_status(Status::Removed, t);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for World {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("World")
+            .field("config", &self.config)
+            .field("tick", &self.tick)
+            .field("people", &self.people.len())
+            .finish()
+    }
+}
+
+/// Pushes two overlapping people apart and reflects their velocities off each
+/// other. A stationary person does not budge, so the other one bounces off
+/// them like off a wall.
+fn collide_pair(a: &mut Person, b: &mut Person, diameter: f32, arena: &Rect) {
+    let (a_fixed, b_fixed) = (a.is_stationary(), b.is_stationary());
+    let offset = b.position - a.position;
+    let distance_squared = offset.length_squared();
+    if (a_fixed && b_fixed) || distance_squared >= diameter * diameter || distance_squared == 0.0 {
+        return;
+    }
+    let distance = distance_squared.sqrt();
+    let normal = offset * (1.0 / distance);
+    let overlap = diameter - distance;
+
+    // Velocities are compared at full speed; mobility only scales movement.
+    let a_normal = a.velocity.dot(normal);
+    let b_normal = b.velocity.dot(normal);
+    if a_fixed {
+        b.position += normal * overlap;
+        if b_normal < 0.0 {
+            b.velocity += normal * (-2.0 * b_normal);
+        }
+    } else if b_fixed {
+        a.position += normal * -overlap;
+        if a_normal > 0.0 {
+            a.velocity += normal * (-2.0 * a_normal);
+        }
+    } else {
+        a.position += normal * (-overlap / 2.0);
+        b.position += normal * (overlap / 2.0);
+        if b_normal - a_normal < 0.0 {
+            a.velocity += normal * (b_normal - a_normal);
+            b.velocity += normal * (a_normal - b_normal);
+        }
+    }
+
+    for person in [a, b].iter_mut() {
+        let (min, max) = (arena.min(), arena.max());
+        person.position.x = person.position.x.max(min.x).min(max.x);
+        person.position.y = person.position.y.max(min.y).min(max.y);
+    }
+}
+
+/// Reflects a coordinate that left `min..=max` back inside it.
+fn bounce(position: &mut f32, velocity: &mut f32, min: f32, max: f32) {
+    if *position < min {
+        *position = 2.0 * min - *position;
+        *velocity = velocity.abs();
+    } else if *position > max {
+        *position = 2.0 * max - *position;
+        *velocity = -velocity.abs();
+    }
+    *position = position.max(min).min(max);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_config() {
+        let config = WorldConfig {
+            initial_infected: 500,
+            ..WorldConfig::default()
+        };
+        assert_eq!(
+            World::new(config, 0).err().unwrap().field,
+            "initial_infected"
+        );
+    }
+
+    #[test]
+    fn draws_durations_per_person() {
+        let config = WorldConfig {
+            population: 1000,
+            initial_infected: 1000,
+            p_symptomatic_on_infection: 1.0,
+            infectious_period: Some(Distribution::Gamma { mean: 8.0, sd: 2.0 }),
+            ..WorldConfig::default()
+        };
+        let world = World::new(config, 3).unwrap();
+        let durations: Vec<f64> = world.people().iter().map(Person::infectious_days).collect();
+        let mean = durations.iter().sum::<f64>() / durations.len() as f64;
+        assert!((mean - 8.0).abs() < 0.3, "{}", mean);
+        assert!(durations.iter().any(|&days| days < 6.0));
+        assert!(durations.iter().any(|&days| days > 10.0));
+
+        // Without a distribution everyone gets the configured days.
+        let world = World::new(WorldConfig::default(), 3).unwrap();
+        assert_eq!(world.people()[0].infectious_days(), 10.0);
+    }
+
+    #[test]
+    fn people_stay_inside_the_arena() {
+        let mut world = World::new(WorldConfig::default(), 1).unwrap();
+        for _ in 0..500 {
+            world.step();
+        }
+        for person in world.people() {
+            let Vec2 { x, y } = person.position();
+            assert!((0.0..=800.0).contains(&x) && (0.0..=600.0).contains(&y));
+        }
+    }
+
+    #[test]
+    fn infection_spreads_and_burns_out() {
+        let mut world = World::new(WorldConfig::default(), 7).unwrap();
+        let trajectory = world.run(200);
+
+        for sample in &trajectory {
+            let total = sample.susceptible + sample.exposed + sample.infectious + sample.removed;
+            assert_eq!(total, 400);
+        }
+        assert!(world.is_over());
+        assert!(trajectory.last().unwrap().removed > 3);
+    }
+
+    #[test]
+    fn simulates_a_large_population() {
+        let config = WorldConfig {
+            width: 4000.0,
+            height: 3000.0,
+            population: 20_000,
+            initial_infected: 20,
+            ..WorldConfig::default()
+        };
+        let mut world = World::new(config, 5).unwrap();
+        let trajectory = world.run(10);
+
+        assert!(trajectory.last().unwrap().susceptible < 19_980);
+    }
+
+    #[test]
+    fn splits_infections_by_symptoms() {
+        let mut world = World::new(WorldConfig::default(), 13).unwrap();
+        let trajectory = world.run(150);
+
+        for sample in &trajectory {
+            assert_eq!(sample.symptomatic + sample.asymptomatic, sample.infectious);
+            assert_eq!(
+                sample.total_symptomatic + sample.total_asymptomatic,
+                400 - sample.susceptible
+            );
+        }
+        let last = trajectory.last().unwrap();
+        let fraction = last.total_symptomatic as f32 / (400 - last.susceptible) as f32;
+        assert!(last.total_asymptomatic > 0);
+        assert!((fraction - 0.7).abs() < 0.15);
+    }
+
+    #[test]
+    fn silent_carriers_can_be_made_harmless() {
+        let config = WorldConfig {
+            initial_infected: 20,
+            p_symptomatic_on_infection: 0.0,
+            asymptomatic_infectiousness: 0.0,
+            ..WorldConfig::default()
+        };
+        let last = *World::new(config, 2).unwrap().run(30).last().unwrap();
+
+        assert_eq!(last.susceptible, 380);
+        assert_eq!(last.total_symptomatic, 0);
+    }
+
+    fn lazaretto(capacity: usize) -> QuarantineConfig {
+        QuarantineConfig {
+            region: Rect::new(820.0, 0.0, 200.0, 200.0),
+            capacity,
+            detection_delay_days: 1.0,
+            testing_rate: 0.0,
+        }
+    }
+
+    #[test]
+    fn quarantine_must_be_outside_the_arena() {
+        let config = WorldConfig {
+            quarantine: Some(QuarantineConfig {
+                region: Rect::new(700.0, 0.0, 200.0, 200.0),
+                ..lazaretto(10)
+            }),
+            ..WorldConfig::default()
+        };
+        assert_eq!(
+            World::new(config, 0).err().unwrap().field,
+            "quarantine.region"
+        );
+    }
+
+    #[test]
+    fn quarantine_isolates_detected_cases() {
+        let config = WorldConfig {
+            quarantine: Some(lazaretto(1000)),
+            ..WorldConfig::default()
+        };
+        let mut world = World::new(config, 4).unwrap();
+        let region = lazaretto(0).region;
+
+        let mut peak = 0;
+        for _ in 0..600 {
+            world.step();
+            let sample = world.sample();
+            assert_eq!(sample.awaiting_quarantine, 0);
+            peak = peak.max(sample.quarantined);
+            for person in world.people() {
+                assert_eq!(person.is_quarantined(), region.contains(person.position()));
+                if person.is_quarantined() {
+                    assert!(person.is_detected());
+                    assert!(person.is_symptomatic());
+                }
+            }
+        }
+        assert!(peak > 0);
+    }
+
+    #[test]
+    fn full_quarantine_overflows() {
+        let config = WorldConfig {
+            initial_infected: 30,
+            p_symptomatic_on_infection: 1.0,
+            quarantine: Some(lazaretto(5)),
+            ..WorldConfig::default()
+        };
+        let mut world = World::new(config, 6).unwrap();
+        let trajectory = world.run(20);
+
+        assert!(trajectory.iter().all(|x| x.quarantined <= 5));
+        assert!(trajectory.iter().any(|x| x.awaiting_quarantine > 0));
+    }
+
+    #[test]
+    fn paused_quarantine_requeues_detected_cases() {
+        let config = WorldConfig {
+            initial_infected: 30,
+            p_symptomatic_on_infection: 1.0,
+            quarantine: Some(lazaretto(5)),
+            ..WorldConfig::default()
+        };
+        let mut world = World::new(config, 6).unwrap();
+        world.run(20);
+        assert!(world.sample().awaiting_quarantine > 0);
+
+        world.set_quarantine_enabled(false);
+        world.step();
+        assert_eq!(world.sample().awaiting_quarantine, 0);
+        assert!(world
+            .people()
+            .iter()
+            .filter(|p| p.status() == Status::Infectious && !p.is_quarantined())
+            .all(|p| !p.is_detected()));
+
+        world.set_quarantine_enabled(true);
+        world.step();
+        assert!(world.sample().awaiting_quarantine > 0);
+    }
+
+    #[test]
+    fn quarantine_flattens_the_curve() {
+        let peak = |quarantine| {
+            let config = WorldConfig {
+                p_symptomatic_on_infection: 1.0,
+                quarantine,
+                ..WorldConfig::default()
+            };
+            let trajectory = World::new(config, 8).unwrap().run(100);
+            trajectory.iter().map(|x| x.infectious).max().unwrap()
+        };
+        assert!(peak(Some(lazaretto(1000))) < peak(None));
+    }
+
+    #[test]
+    fn compliant_people_stay_put() {
+        let config = WorldConfig {
+            distancing_compliance: 0.75,
+            ..WorldConfig::default()
+        };
+        let mut world = World::new(config, 10).unwrap();
+        let start: Vec<Vec2> = world.people().iter().map(|p| p.position()).collect();
+        for _ in 0..50 {
+            world.step();
+        }
+
+        let stayed = world
+            .people()
+            .iter()
+            .zip(&start)
+            .filter(|(person, &start)| person.position() == start)
+            .count();
+        let stationary = world.people().iter().filter(|p| p.is_stationary()).count();
+        assert_eq!(stayed, stationary);
+        assert!((stationary as f32 / 400.0 - 0.75).abs() < 0.1);
+
+        world.set_distancing_compliance(0.0).unwrap();
+        assert!(world.people().iter().all(|p| p.mobility() == 1.0));
+        assert!(world.set_distancing_compliance(1.5).is_err());
+    }
+
+    #[test]
+    fn stationary_people_are_obstacles() {
+        let config = WorldConfig {
+            population: 800,
+            distancing_compliance: 0.5,
+            collision_radius: 4.0,
+            ..WorldConfig::default()
+        };
+        let mut world = World::new(config, 12).unwrap();
+        let start: Vec<Vec2> = world.people().iter().map(|p| p.position()).collect();
+        for _ in 0..200 {
+            world.step();
+        }
+
+        let people = world.people();
+        for (person, &start) in people.iter().zip(&start) {
+            if person.is_stationary() {
+                assert_eq!(person.position(), start);
+            }
+        }
+        for a in people.iter().filter(|p| p.is_stationary()) {
+            for b in people.iter().filter(|p| !p.is_stationary()) {
+                // Allow for people who were placed overlapping and for the
+                // last step moving them before collisions are resolved.
+                let distance = a.position().distance_squared(b.position()).sqrt();
+                assert!(distance > 8.0 - 60.0 / 10.0 - 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn distancing_flattens_the_curve() {
+        let peak = |distancing_compliance| {
+            let config = WorldConfig {
+                distancing_compliance,
+                ..WorldConfig::default()
+            };
+            let trajectory = World::new(config, 14).unwrap().run(150);
+            trajectory.iter().map(|x| x.infectious).max().unwrap()
+        };
+        assert!(peak(0.75) < peak(0.0));
+    }
+
+    #[test]
+    fn same_seed_same_outbreak() {
+        let a = World::new(WorldConfig::default(), 42).unwrap().run(30);
+        let b = World::new(WorldConfig::default(), 42).unwrap().run(30);
+        assert_eq!(a, b);
+    }
+}
@@ -0,0 +1,252 @@
+//! Runs lazaretto scenarios without a window, for batch experiments on
+//! servers and CI.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::process;
+
+use algorithm::export::Format;
+use algorithm::{Scenario, Sweep};
+
+const USAGE: &str = "\
+Runs a lazaretto scenario and writes its daily trajectory.
+
+USAGE:
+    lazaretto-cli [OPTIONS] <SCENARIO>
+
+ARGS:
+    <SCENARIO>    scenario file, JSON if it ends in .json and TOML otherwise
+
+OPTIONS:
+    -s, --seed <SEED>         seed to run, may be given several times
+                              [default: the scenario's seed]
+        --seeds <FROM>..<TO>  run every seed from FROM up to, but not including, TO
+    -o, --output <PATH>       file to write to, `-` for stdout [default: -]
+                              `{seed}` in the path is replaced by the seed
+    -f, --format <FORMAT>     wide, long or json [default: wide]
+        --sweep <FILE>        run the sweep in FILE over the scenario and write a
+                              CSV table of outcomes per point instead
+    -h, --help                print this message
+";
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    scenario: PathBuf,
+    seeds: Vec<u64>,
+    output: Option<String>,
+    format: Format,
+    sweep: Option<PathBuf>,
+}
+
+impl Options {
+    fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Options>, String> {
+        let mut scenario = None;
+        let mut seeds = Vec::new();
+        let mut output = None;
+        let mut format = Format::WideCsv;
+        let mut sweep = None;
+        // The first option that only applies to single runs, if any.
+        let mut per_run = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("`{}` needs a value", name))
+            };
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "-s" | "--seed" => {
+                    seeds.push(parse_seed(&value(&arg)?)?);
+                    per_run.get_or_insert(arg);
+                }
+                "--seeds" => {
+                    let range = value(&arg)?;
+                    let mut bounds = range.splitn(2, "..");
+                    let from = parse_seed(bounds.next().unwrap_or_default())?;
+                    let to = bounds
+                        .next()
+                        .ok_or_else(|| format!("`{}` is not a range like `0..10`", range))
+                        .and_then(parse_seed)?;
+                    seeds.extend(from..to);
+                    per_run.get_or_insert(arg);
+                }
+                "-o" | "--output" => output = Some(value(&arg)?),
+                "--sweep" => sweep = Some(PathBuf::from(value(&arg)?)),
+                "-f" | "--format" => {
+                    format = value(&arg)?.parse().map_err(|e| format!("{}", e))?;
+                    per_run.get_or_insert(arg);
+                }
+                _ if arg.starts_with('-') && arg != "-" => {
+                    return Err(format!("unknown option `{}`", arg))
+                }
+                _ if scenario.is_none() => scenario = Some(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument `{}`", arg)),
+            }
+        }
+
+        let scenario = scenario.ok_or("missing the scenario file")?;
+        if let (Some(_), Some(option)) = (&sweep, per_run) {
+            return Err(format!("`{}` cannot be used with `--sweep`", option));
+        }
+        Ok(Some(Options {
+            scenario,
+            seeds,
+            output: output.filter(|output| output != "-"),
+            format,
+            sweep,
+        }))
+    }
+
+    /// Where to write the run with `seed`, or `None` for stdout.
+    fn output_path(&self, seed: u64) -> Option<PathBuf> {
+        let output = self.output.as_ref()?;
+        if output.contains("{seed}") {
+            return Some(PathBuf::from(output.replace("{seed}", &seed.to_string())));
+        }
+        if self.seeds.len() <= 1 {
+            return Some(PathBuf::from(output));
+        }
+
+        // Keep one file per seed rather than overwriting the same one.
+        let path = PathBuf::from(output);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = path
+            .extension()
+            .map_or_else(|| self.format.extension().into(), |e| e.to_string_lossy());
+        Some(path.with_file_name(format!("{}-{}.{}", stem, seed, extension)))
+    }
+}
+
+fn parse_seed(seed: &str) -> Result<u64, String> {
+    seed.parse()
+        .map_err(|_| format!("`{}` is not a valid seed", seed))
+}
+
+fn main() {
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(error) = run(options) {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run(mut options: Options) -> Result<(), Box<dyn std::error::Error>> {
+    let scenario = Scenario::load(&options.scenario)
+        .map_err(|e| format!("{}: {}", options.scenario.display(), e))?;
+    if let Some(path) = &options.sweep {
+        let sweep = Sweep::load(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let table = sweep.run(&scenario)?;
+        return write(options.output_path(sweep.seed), |writer| {
+            table.write_csv(writer)
+        });
+    }
+    if options.seeds.is_empty() {
+        options.seeds.push(scenario.seed);
+    }
+
+    for &seed in &options.seeds {
+        let run = scenario.run(seed)?;
+        for firing in &run.firings {
+            let state = if firing.active { "on" } else { "off" };
+            eprintln!("seed {}, day {}: {} {}", seed, firing.t, firing.name, state);
+        }
+
+        write(options.output_path(seed), |writer| {
+            run.write(writer, options.format)
+        })?;
+    }
+    Ok(())
+}
+
+/// Calls `f` with a writer to the file at `path`, or to stdout without one.
+fn write<F>(path: Option<PathBuf>, f: F) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    match path {
+        Some(path) => {
+            let file = File::create(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let mut writer = BufWriter::new(file);
+            f(&mut writer)?;
+            writer.flush()?;
+        }
+        None => {
+            let stdout = io::stdout();
+            f(&mut stdout.lock())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Option<Options>, String> {
+        Options::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = parse("-s 3 run.toml --seeds 10..12 -f json -o out.json")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            options,
+            Options {
+                scenario: PathBuf::from("run.toml"),
+                seeds: vec![3, 10, 11],
+                output: Some("out.json".to_string()),
+                format: Format::Json,
+                sweep: None,
+            }
+        );
+        assert_eq!(parse("run.toml --help").unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse("").is_err());
+        assert!(parse("a.toml b.toml").is_err());
+        assert!(parse("a.toml --seed").is_err());
+        assert!(parse("a.toml --seeds 10").is_err());
+        assert!(parse("a.toml --format xml").is_err());
+        assert!(parse("a.toml --verbose").is_err());
+        assert_eq!(
+            parse("a.toml --sweep s.toml -s 3"),
+            Err("`-s` cannot be used with `--sweep`".to_string())
+        );
+        assert!(parse("a.toml --seeds 0..2 --sweep s.toml").is_err());
+        assert!(parse("a.toml --sweep s.toml --format json").is_err());
+        assert!(parse("a.toml --sweep s.toml -o out.csv").is_ok());
+    }
+
+    #[test]
+    fn one_output_per_seed() {
+        let output = |args: &str, seed| parse(args).unwrap().unwrap().output_path(seed);
+        assert_eq!(output("a.toml", 1), None);
+        assert_eq!(output("a.toml -o -", 1), None);
+        assert_eq!(output("a.toml -o out.csv", 1), Some("out.csv".into()));
+        assert_eq!(
+            output("a.toml -o runs/out.csv --seeds 0..3", 2),
+            Some("runs/out-2.csv".into())
+        );
+        assert_eq!(
+            output("a.toml -o run-{seed}.json -s 4 -s 5", 5),
+            Some("run-5.json".into())
+        );
+    }
+}
@@ -8,17 +8,20 @@ use iced_native::{slider, Align, Color, Column, Element, Length, Row, Slider, Te
 
 pub struct Controls {
     sliders: [slider::State; 3],
+    distancing: slider::State,
 }
 
 #[derive(Debug)]
 pub enum Message {
     BackgroundColorChanged(QsColor),
+    DistancingChanged(f32),
 }
 
 impl Controls {
     pub fn new() -> Controls {
         Controls {
             sliders: Default::default(),
+            distancing: Default::default(),
         }
     }
 
@@ -27,12 +30,31 @@ impl Controls {
             Message::BackgroundColorChanged(color) => {
                 scene.background_color = color;
             }
+            Message::DistancingChanged(compliance) => {
+                // Going through the schedule keeps the value once a
+                // distancing intervention switches off again, and the slider
+                // range keeps the compliance valid
+                let _ = scene.schedule.adjust_baseline(&mut scene.world, |levers| {
+                    levers.distancing_compliance = compliance
+                });
+            }
         }
     }
 
     pub fn view(&mut self, scene: &Scene) -> Element<Message, Renderer> {
         let [r, g, b] = &mut self.sliders;
         let background_color = scene.background_color;
+        let compliance = scene.world.config().distancing_compliance;
+
+        let distancing = Row::new()
+            .width(Length::Units(500))
+            .spacing(20)
+            .push(Slider::new(
+                &mut self.distancing,
+                0.0..=1.0,
+                compliance,
+                Message::DistancingChanged,
+            ));
 
         let sliders = Row::new()
             .width(Length::Units(500))
@@ -83,6 +105,11 @@ impl Controls {
                         Column::new()
                             .padding(10)
                             .spacing(10)
+                            .push(
+                                Text::new(format!("Staying home: {:.0}%", compliance * 100.0))
+                                    .color(Color::WHITE),
+                            )
+                            .push(distancing)
                             .push(Text::new("Background color").color(Color::WHITE))
                             .push(sliders)
                             .push(
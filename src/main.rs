@@ -69,6 +69,12 @@ impl State for Application {
         Ok(())
     }
 
+    fn update(&mut self, _window: &mut Window) -> Result<()> {
+        // Advance the simulation at quicksilver's fixed update rate
+        self.scene.update();
+        Ok(())
+    }
+
     fn draw(&mut self, window: &mut Window) -> Result<()> {
         // We need to:
         // 1. Process events of our user interface.
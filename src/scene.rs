@@ -1,49 +1,55 @@
+use algorithm::{Person, Scenario, Schedule, Status, World};
 use quicksilver::prelude::*;
 
+/// The scenario loaded at start up, embedded so it also works on the web.
+const DEFAULT_SCENARIO: &str = include_str!("../scenarios/default.toml");
+
 pub struct Scene {
     pub background_color: Color,
+    pub world: World,
+    pub schedule: Schedule,
 }
 
 impl Scene {
     pub fn new() -> Scene {
+        let scenario = Scenario::from_toml(DEFAULT_SCENARIO).expect("default scenario is valid");
         Scene {
             background_color: Color::from_hex("#335577"),
+            world: scenario.world().expect("default scenario is a world"),
+            schedule: scenario.schedule(),
         }
     }
 
+    pub fn update(&mut self) {
+        self.world.step();
+        self.schedule
+            .update(&mut self.world)
+            .expect("scenario interventions are validated");
+    }
+
     pub fn draw(&self, window: &mut Window) -> Result<()> {
         // Remove any lingering artifacts from the previous frame
         window.clear(self.background_color)?;
-        // Draw a rectangle with a top-left corner at (100, 100) and a width and height of 32 with
-        // a blue background
-        window.draw(&Rectangle::new((100, 100), (32, 32)), Col(Color::BLUE));
-        // Draw another rectangle, rotated by 45 degrees, with a z-height of 10
-        window.draw_ex(
-            &Rectangle::new((400, 300), (32, 32)),
-            Col(Color::BLUE),
-            Transform::rotate(45),
-            10,
-        );
-        // Draw a circle with its center at (400, 300) and a radius of 100, with a background of
-        // green
-        window.draw(&Circle::new((400, 300), 100), Col(Color::GREEN));
-        // Draw a line with a thickness of 2 pixels, a red background,
-        // and a z-height of 5
-        window.draw_ex(
-            &Line::new((50, 80), (600, 450)).with_thickness(2.0),
-            Col(Color::RED),
-            Transform::IDENTITY,
-            5,
-        );
-        // Draw a triangle with a red background, rotated by 45 degrees, and scaled down to half
-        // its size
-        window.draw_ex(
-            &Triangle::new((500, 50), (450, 100), (650, 150)),
-            Col(Color::RED),
-            Transform::rotate(45) * Transform::scale((0.5, 0.5)),
-            0,
-        );
+        // Draw every person as a dot coloured by their status
+        for person in self.world.people() {
+            let position = person.position();
+            window.draw(
+                &Circle::new((position.x, position.y), 3),
+                Col(person_color(person)),
+            );
+        }
         // We completed with no errors
         Ok(())
     }
 }
+
+fn person_color(person: &Person) -> Color {
+    match person.status() {
+        Status::Susceptible if person.immunity().doses > 0 => Color::from_hex("#6bbf8a"),
+        Status::Susceptible => Color::from_hex("#8fbcd4"),
+        Status::Exposed => Color::from_hex("#f2c14e"),
+        Status::Infectious if person.is_symptomatic() => Color::from_hex("#e4572e"),
+        Status::Infectious => Color::from_hex("#c878d6"),
+        Status::Removed => Color::from_hex("#9e9e9e"),
+    }
+}